@@ -0,0 +1,515 @@
+use rusqlite::{Connection, Error, OptionalExtension};
+use sha2::{Digest, Sha256};
+
+use crate::property;
+
+/// `prev_hash` of the first entry in the chain
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Rejects edits and deletes on `log`, and inserts that don't extend the chain
+/// from its current head. Created by the schema v4 migration.
+pub(crate) const LOG_TRIGGERS: &str = "
+    CREATE TRIGGER IF NOT EXISTS log_append_only_insert BEFORE INSERT ON log
+    WHEN NEW.hash IS NULL
+      OR NEW.prev_hash IS NOT COALESCE((SELECT hash FROM log ORDER BY id DESC LIMIT 1), '0000000000000000000000000000000000000000000000000000000000000000')
+    BEGIN
+        SELECT RAISE(ABORT, 'log entries must extend the hash chain');
+    END;
+
+    CREATE TRIGGER IF NOT EXISTS log_append_only_update BEFORE UPDATE ON log
+    BEGIN
+        SELECT RAISE(ABORT, 'log entries cannot be modified');
+    END;
+
+    CREATE TRIGGER IF NOT EXISTS log_append_only_delete BEFORE DELETE ON log
+    BEGIN
+        SELECT RAISE(ABORT, 'log entries cannot be deleted');
+    END;
+";
+
+/// Columns linking an entry to the resident, package and card it is about. They are
+/// generated from the payload, so they are covered by the entry's hash and can't drift
+/// from it. There are no foreign key constraints: entries outlive the rows they mention.
+/// Created by the schema v8 migration.
+pub(crate) const LOG_ENTITY_COLUMNS: &str = "
+    ALTER TABLE log ADD COLUMN resident_id INTEGER
+        GENERATED ALWAYS AS (json_extract(payload, '$.resident_id')) VIRTUAL;
+    ALTER TABLE log ADD COLUMN package_id INTEGER
+        GENERATED ALWAYS AS (json_extract(payload, '$.package_id')) VIRTUAL;
+    ALTER TABLE log ADD COLUMN card_id INTEGER
+        GENERATED ALWAYS AS (json_extract(payload, '$.card_id')) VIRTUAL;
+
+    DROP INDEX IF EXISTS idx_log_resident;
+    CREATE INDEX IF NOT EXISTS idx_log_resident_id ON log(resident_id);
+    CREATE INDEX IF NOT EXISTS idx_log_package_id ON log(package_id);
+    CREATE INDEX IF NOT EXISTS idx_log_card_id ON log(card_id);
+";
+
+/// Building an entry belongs to. Entries from before buildings existed have no
+/// `property_id` in their payload and belong to the first one. Created by the schema v10 migration.
+pub(crate) const LOG_PROPERTY_COLUMN: &str = "
+    ALTER TABLE log ADD COLUMN property_id INTEGER
+        GENERATED ALWAYS AS (COALESCE(json_extract(payload, '$.property_id'), 1)) VIRTUAL;
+    CREATE INDEX IF NOT EXISTS idx_log_property ON log(property_id);
+";
+
+/// Lets the action and payload of an entry be replaced once, when it is redacted for
+/// retention or erasure. `verify_chain` accepts a redacted entry only if a later entry
+/// lists it in `redacted_log_ids`. Redacted text also leaves the search index.
+/// Created by the schema v13 migration.
+pub(crate) const LOG_REDACTION: &str = "
+    ALTER TABLE log ADD COLUMN redacted_at TEXT;
+
+    DROP TRIGGER IF EXISTS log_append_only_update;
+    CREATE TRIGGER IF NOT EXISTS log_append_only_update BEFORE UPDATE ON log
+    WHEN OLD.redacted_at IS NOT NULL OR NEW.redacted_at IS NULL
+      OR NEW.id IS NOT OLD.id OR NEW.action_type IS NOT OLD.action_type
+      OR NEW.date_time IS NOT OLD.date_time OR NEW.operator_id IS NOT OLD.operator_id
+      OR NEW.prev_hash IS NOT OLD.prev_hash OR NEW.hash IS NOT OLD.hash
+    BEGIN
+        SELECT RAISE(ABORT, 'log entries cannot be modified');
+    END;
+
+    CREATE TRIGGER IF NOT EXISTS log_fts_redact AFTER UPDATE OF action ON log BEGIN
+        INSERT INTO log_fts(log_fts, rowid, action_type, action) VALUES ('delete', old.id, old.action_type, old.action);
+        INSERT INTO log_fts(rowid, action_type, action) VALUES (new.id, new.action_type, new.action);
+    END;
+";
+
+/// Narrows the one update `LOG_REDACTION` allows to what `redact` does: the action
+/// becomes `REDACTED_ACTION` and the payload keeps some of its fields, unchanged.
+/// Created by the schema v17 migration.
+pub(crate) const LOG_REDACTION_RULES: &str = "
+    DROP TRIGGER IF EXISTS log_append_only_update;
+    CREATE TRIGGER IF NOT EXISTS log_append_only_update BEFORE UPDATE ON log
+    WHEN OLD.redacted_at IS NOT NULL OR NEW.redacted_at IS NULL
+      OR NEW.id IS NOT OLD.id OR NEW.action_type IS NOT OLD.action_type
+      OR NEW.date_time IS NOT OLD.date_time OR NEW.operator_id IS NOT OLD.operator_id
+      OR NEW.prev_hash IS NOT OLD.prev_hash OR NEW.hash IS NOT OLD.hash
+      OR NEW.action IS NOT '(redacted)'
+      OR json_type(NEW.payload) IS NOT 'object'
+      OR EXISTS (
+          SELECT 1 FROM json_each(NEW.payload) n
+          WHERE NOT EXISTS (
+              SELECT 1 FROM json_each(OLD.payload) o
+              WHERE o.key = n.key AND o.type = n.type AND o.value IS n.value
+          )
+      )
+    BEGIN
+        SELECT RAISE(ABORT, 'log entries cannot be modified');
+    END;
+";
+
+/// Action text of a redacted entry
+pub const REDACTED_ACTION: &str = "(redacted)";
+
+/// Payload key of the entry recording a redaction
+const REDACTED_IDS_KEY: &str = "redacted_log_ids";
+
+/// Payload key mapping each redacted id to the `entry_hash` of what the redaction left,
+/// so the redacted contents are verified too. Records written before schema v17 lack it.
+const REDACTED_HASHES_KEY: &str = "redacted_hashes";
+
+/// First entry whose hash doesn't match its contents or its predecessor
+#[derive(Debug, Clone)]
+pub struct ChainBreak {
+    pub log_id: u32,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChainReport {
+    pub checked: usize,
+    /// Entries among `checked` whose contents were redacted
+    pub redacted: usize,
+    pub broken: Option<ChainBreak>,
+}
+
+impl ChainReport {
+    pub fn is_intact(&self) -> bool {
+        self.broken.is_none()
+    }
+
+    pub fn summary(&self) -> String {
+        match &self.broken {
+            None if self.redacted > 0 => format!(
+                "Log chain intact ({} entries checked, {} redacted)", self.checked, self.redacted
+            ),
+            None => format!("Log chain intact ({} entries checked)", self.checked),
+            Some(b) => format!("Log chain broken at entry {}: {}", b.log_id, b.reason),
+        }
+    }
+}
+
+/// Hash of one entry. Fields are separated by 0x1F so values can't run into each other.
+pub fn entry_hash(
+    prev_hash: &str,
+    action_type: &str,
+    action: &str,
+    date_time: &str,
+    operator_id: Option<u32>,
+    payload: &str,
+) -> String {
+    let operator = operator_id.map(|id| id.to_string()).unwrap_or_default();
+    let mut hasher = Sha256::new();
+    for (i, field) in [prev_hash, action_type, action, date_time, &operator, payload].iter().enumerate() {
+        if i > 0 {
+            hasher.update([0x1f]);
+        }
+        hasher.update(field.as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+fn chain_head(db: &Connection) -> Result<String, Error> {
+    let head: Option<String> = db
+        .query_row("SELECT hash FROM log ORDER BY id DESC LIMIT 1", [], |row| row.get(0))
+        .optional()?;
+    Ok(head.unwrap_or_else(|| GENESIS_HASH.to_string()))
+}
+
+/// Appends an entry to the end of the chain. Only `add_log` should call this.
+/// Entries are stamped with the building the connection is working in.
+pub(crate) fn append(db: &Connection, action_type: &str, action: &str, payload: &serde_json::Value) -> Result<(), Error> {
+    use chrono::Local;
+    let date_time = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let mut payload = payload.clone();
+    if let Some(fields) = payload.as_object_mut() {
+        if !fields.contains_key("property_id") {
+            fields.insert("property_id".to_string(), property::current_property_id(db)?.into());
+        }
+    }
+    let payload = payload.to_string();
+
+    // Reading the head and inserting must not interleave with another writer.
+    // SAVEPOINT works both inside and outside a caller's transaction.
+    db.execute_batch("SAVEPOINT add_log")?;
+    let result = (|| {
+        let operator_id: Option<u32> = db
+            .query_row("SELECT operator_id FROM temp.session", [], |row| row.get(0))
+            .optional()?;
+        let prev_hash = chain_head(db)?;
+        let hash = entry_hash(&prev_hash, action_type, action, &date_time, operator_id, &payload);
+
+        db.execute(
+            "INSERT INTO log (action_type, action, date_time, operator_id, payload, prev_hash, hash)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![action_type, action, date_time, operator_id, payload, prev_hash, hash],
+        )?;
+        Ok(())
+    })();
+
+    match result {
+        Ok(()) => db.execute_batch("RELEASE add_log"),
+        Err(e) => {
+            let _ = db.execute_batch("ROLLBACK TO add_log; RELEASE add_log");
+            Err(e)
+        }
+    }
+}
+
+/// Fills in payload and hashes for entries written before schema v4, oldest first
+pub(crate) fn backfill_chain(db: &Connection) -> Result<(), Error> {
+    let mut stmt = db.prepare(
+        "SELECT id, action_type, action, date_time, operator_id FROM log ORDER BY id"
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, u32>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, Option<u32>>(4)?,
+        ))
+    })?.collect::<Result<Vec<_>, _>>()?;
+
+    let mut prev_hash = GENESIS_HASH.to_string();
+    for (id, action_type, action, date_time, operator_id) in rows {
+        let hash = entry_hash(&prev_hash, &action_type, &action, &date_time, operator_id, "{}");
+        db.execute(
+            "UPDATE log SET payload = '{}', prev_hash = ?1, hash = ?2 WHERE id = ?3",
+            rusqlite::params![prev_hash, hash, id],
+        )?;
+        prev_hash = hash;
+    }
+    Ok(())
+}
+
+/// Redacts entries `log_ids`: their action becomes `REDACTED_ACTION` and their payload keeps
+/// only the fields `keep` accepts. Then appends an `action_type` entry recording which
+/// entries were redacted, with `payload` added. Entries already redacted, and records of
+/// earlier redactions, are left alone. Returns how many entries were redacted.
+pub(crate) fn redact(
+    db: &Connection,
+    log_ids: &[u32],
+    keep: &dyn Fn(&str) -> bool,
+    action_type: &str,
+    action: &str,
+    payload: serde_json::Value,
+) -> Result<usize, Error> {
+    use chrono::Local;
+    let redacted_at = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+    db.execute_batch("SAVEPOINT redact_log")?;
+    let result = (|| {
+        let mut redacted = Vec::new();
+        let mut hashes = serde_json::Map::new();
+        for &id in log_ids {
+            let entry = db.query_row(
+                "SELECT action_type, date_time, operator_id, payload, prev_hash FROM log
+                 WHERE id = ?1 AND redacted_at IS NULL",
+                [id],
+                |row| Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<u32>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                )),
+            ).optional()?;
+            let Some((entry_type, date_time, operator_id, old_payload, prev_hash)) = entry else { continue };
+            let mut fields: serde_json::Map<String, serde_json::Value> = old_payload.as_deref()
+                .and_then(|p| serde_json::from_str(p).ok())
+                .unwrap_or_default();
+            if fields.contains_key(REDACTED_IDS_KEY) {
+                continue;
+            }
+            fields.retain(|key, _| keep(key));
+            let new_payload = serde_json::Value::Object(fields).to_string();
+
+            db.execute(
+                "UPDATE log SET action = ?1, payload = ?2, redacted_at = ?3 WHERE id = ?4",
+                rusqlite::params![REDACTED_ACTION, new_payload, redacted_at, id],
+            )?;
+            let retained = entry_hash(
+                prev_hash.as_deref().unwrap_or(""), &entry_type, REDACTED_ACTION, &date_time, operator_id, &new_payload,
+            );
+            hashes.insert(id.to_string(), retained.into());
+            redacted.push(id);
+        }
+        if redacted.is_empty() {
+            return Ok(0);
+        }
+
+        let mut payload = payload;
+        if let Some(fields) = payload.as_object_mut() {
+            fields.insert(REDACTED_IDS_KEY.to_string(), redacted.clone().into());
+            fields.insert(REDACTED_HASHES_KEY.to_string(), hashes.into());
+        }
+        append(db, action_type, action, &payload)?;
+        Ok(redacted.len())
+    })();
+
+    match result {
+        Ok(count) => db.execute_batch("RELEASE redact_log").map(|_| count),
+        Err(e) => {
+            let _ = db.execute_batch("ROLLBACK TO redact_log; RELEASE redact_log");
+            Err(e)
+        }
+    }
+}
+
+/// Walks the log from the oldest entry and reports the first broken link.
+/// Redacted entries can't be rehashed; they pass when their links hold and a
+/// later entry records the redaction along with the hash of what it left.
+/// Entries removed from the end of the log leave no gap and can't be detected here;
+/// compare `checked` with an earlier export or backup for that.
+pub fn verify_chain(db: &Connection) -> Result<ChainReport, Error> {
+    let mut stmt = db.prepare(
+        "SELECT id, action_type, action, date_time, operator_id, payload, prev_hash, hash, redacted_at
+         FROM log ORDER BY id"
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut expected_prev = GENESIS_HASH.to_string();
+    let mut checked = 0;
+    let mut redacted = 0;
+    // Redacted entries not yet accounted for by a redaction record, with the hash of their contents
+    let mut unrecorded = std::collections::BTreeMap::new();
+    while let Some(row) = rows.next()? {
+        let id: u32 = row.get(0)?;
+        let payload: Option<String> = row.get(5)?;
+        let prev_hash: Option<String> = row.get(6)?;
+        let hash: Option<String> = row.get(7)?;
+
+        let (Some(prev_hash), Some(hash)) = (prev_hash, hash) else {
+            return Ok(broken(checked, id, "entry has no hash"));
+        };
+        if prev_hash != expected_prev {
+            return Ok(broken(checked, id, "previous hash doesn't match, an earlier entry was removed or replaced"));
+        }
+
+        let action: String = row.get(2)?;
+        let computed = entry_hash(
+            &prev_hash,
+            &row.get::<_, String>(1)?,
+            &action,
+            &row.get::<_, String>(3)?,
+            row.get(4)?,
+            payload.as_deref().unwrap_or(""),
+        );
+
+        if row.get::<_, Option<String>>(8)?.is_some() {
+            if action != REDACTED_ACTION {
+                return Ok(broken(checked, id, "redacted entry was rewritten"));
+            }
+            unrecorded.insert(id, computed);
+            redacted += 1;
+            expected_prev = hash;
+            checked += 1;
+            continue;
+        }
+
+        if computed != hash {
+            return Ok(broken(checked, id, "contents were changed after the entry was written"));
+        }
+        let record = payload.as_deref()
+            .and_then(|p| serde_json::from_str::<serde_json::Value>(p).ok())
+            .unwrap_or_default();
+        if let Some(serde_json::Value::Array(ids)) = record.get(REDACTED_IDS_KEY) {
+            let hashes = record.get(REDACTED_HASHES_KEY);
+            for redacted_id in ids.iter().filter_map(|v| v.as_u64()) {
+                let redacted_id = redacted_id as u32;
+                let Some(contents) = unrecorded.remove(&redacted_id) else { continue };
+                // Records from before schema v17 list ids only
+                let Some(hashes) = hashes else { continue };
+                if hashes.get(redacted_id.to_string()).and_then(|h| h.as_str()) != Some(contents.as_str()) {
+                    return Ok(broken(checked, redacted_id, "redacted entry doesn't match its redaction record"));
+                }
+            }
+        }
+
+        expected_prev = hash;
+        checked += 1;
+    }
+
+    if let Some((&id, _)) = unrecorded.first_key_value() {
+        return Ok(broken(checked, id, "entry was redacted without a redaction record"));
+    }
+    Ok(ChainReport { checked, redacted, broken: None })
+}
+
+fn broken(checked: usize, log_id: u32, reason: &str) -> ChainReport {
+    ChainReport {
+        checked,
+        redacted: 0,
+        broken: Some(ChainBreak { log_id, reason: reason.to_string() }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // A fresh database with three entries, returning their ids
+    fn logged_db() -> (Connection, Vec<u32>) {
+        let db = crate::open_db(":memory:").unwrap();
+        for (i, name) in ["Ann", "Bob", "Cy"].iter().enumerate() {
+            append(&db, "add_resident", &format!("Added resident {}", name), &json!({ "resident_id": i + 1, "name": name })).unwrap();
+        }
+        let mut stmt = db.prepare("SELECT id FROM log WHERE action_type = 'add_resident' ORDER BY id").unwrap();
+        let ids = stmt.query_map([], |row| row.get(0)).unwrap().collect::<Result<Vec<u32>, _>>().unwrap();
+        drop(stmt);
+        (db, ids)
+    }
+
+    // What a writer going around the triggers could do
+    fn without_triggers(db: &Connection, sql: &str, params: impl rusqlite::Params) {
+        db.execute_batch("DROP TRIGGER log_append_only_update; DROP TRIGGER log_append_only_delete;").unwrap();
+        db.execute(sql, params).unwrap();
+    }
+
+    fn break_at(db: &Connection) -> (u32, String) {
+        let broken = verify_chain(db).unwrap().broken.expect("the chain should be broken");
+        (broken.log_id, broken.reason)
+    }
+
+    #[test]
+    fn untouched_chain_verifies() {
+        let (db, ids) = logged_db();
+        let report = verify_chain(&db).unwrap();
+        assert!(report.is_intact(), "{}", report.summary());
+        assert!(report.checked >= ids.len());
+        assert_eq!(report.redacted, 0);
+    }
+
+    #[test]
+    fn triggers_refuse_edits_and_deletes() {
+        let (db, ids) = logged_db();
+        assert!(db.execute("UPDATE log SET action = 'Added resident Dee' WHERE id = ?1", [ids[1]]).is_err());
+        assert!(db.execute("DELETE FROM log WHERE id = ?1", [ids[1]]).is_err());
+        assert!(db.execute(
+            "UPDATE log SET action = ?1, payload = '{\"name\":\"Dee\"}', redacted_at = 'now' WHERE id = ?2",
+            rusqlite::params![REDACTED_ACTION, ids[1]],
+        ).is_err());
+    }
+
+    #[test]
+    fn edited_entry_is_reported_at_its_id() {
+        let (db, ids) = logged_db();
+        without_triggers(&db, "UPDATE log SET action = 'Added resident Dee' WHERE id = ?1", [ids[1]]);
+        let (id, reason) = break_at(&db);
+        assert_eq!(id, ids[1]);
+        assert!(reason.contains("contents were changed"), "{}", reason);
+    }
+
+    #[test]
+    fn deleted_entry_is_reported_at_the_next_one() {
+        let (db, ids) = logged_db();
+        without_triggers(&db, "DELETE FROM log WHERE id = ?1", [ids[1]]);
+        let (id, reason) = break_at(&db);
+        assert_eq!(id, ids[2]);
+        assert!(reason.contains("previous hash doesn't match"), "{}", reason);
+    }
+
+    #[test]
+    fn redacted_entry_verifies_with_its_record() {
+        let (db, ids) = logged_db();
+        let count = redact(&db, &[ids[1]], &|key| key == "resident_id", "test_redaction", "Redacted an entry", json!({})).unwrap();
+        assert_eq!(count, 1);
+
+        let (action, payload): (String, String) = db.query_row(
+            "SELECT action, payload FROM log WHERE id = ?1", [ids[1]], |row| Ok((row.get(0)?, row.get(1)?)),
+        ).unwrap();
+        assert_eq!(action, REDACTED_ACTION);
+        assert!(!payload.contains("Bob"), "{}", payload);
+
+        let report = verify_chain(&db).unwrap();
+        assert!(report.is_intact(), "{}", report.summary());
+        assert_eq!(report.redacted, 1);
+    }
+
+    #[test]
+    fn entry_redacted_without_a_record_fails() {
+        let (db, ids) = logged_db();
+        without_triggers(
+            &db,
+            "UPDATE log SET action = ?1, payload = '{}', redacted_at = 'now' WHERE id = ?2",
+            rusqlite::params![REDACTED_ACTION, ids[1]],
+        );
+        let (id, reason) = break_at(&db);
+        assert_eq!(id, ids[1]);
+        assert!(reason.contains("without a redaction record"), "{}", reason);
+    }
+
+    #[test]
+    fn redacted_entry_changed_after_its_record_fails() {
+        let (db, ids) = logged_db();
+        redact(&db, &[ids[1]], &|key| key == "resident_id", "test_redaction", "Redacted an entry", json!({})).unwrap();
+        without_triggers(&db, "UPDATE log SET payload = '{\"resident_id\":3}' WHERE id = ?1", [ids[1]]);
+        let (id, reason) = break_at(&db);
+        assert_eq!(id, ids[1]);
+        assert!(reason.contains("doesn't match its redaction record"), "{}", reason);
+    }
+
+    #[test]
+    fn redacted_entry_with_rewritten_action_fails() {
+        let (db, ids) = logged_db();
+        redact(&db, &[ids[1]], &|key| key == "resident_id", "test_redaction", "Redacted an entry", json!({})).unwrap();
+        without_triggers(&db, "UPDATE log SET action = 'Added resident Dee' WHERE id = ?1", [ids[1]]);
+        let (id, reason) = break_at(&db);
+        assert_eq!(id, ids[1]);
+        assert!(reason.contains("redacted entry was rewritten"), "{}", reason);
+    }
+}
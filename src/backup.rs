@@ -0,0 +1,315 @@
+use rusqlite::{Connection, OpenFlags, backup::Backup};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::{Datelike, Local, NaiveDateTime};
+
+use crate::{SCHEMA_VERSION, encryption, get_schema_version};
+
+const BACKUP_PREFIX: &str = "package_room-";
+const BACKUP_STAMP: &str = "%Y%m%d-%H%M%S";
+
+// Tables a file must contain to be accepted as a package room database
+const REQUIRED_TABLES: [&str; 4] = ["resident", "card", "log", "package"];
+
+#[derive(Debug, Clone)]
+pub struct BackupSettings {
+    pub dir: PathBuf,
+    /// Number of most recent days that keep their newest copy
+    pub keep_daily: usize,
+    /// Number of most recent ISO weeks that keep their newest copy
+    pub keep_weekly: usize,
+    /// Time between scheduled backups
+    pub interval: Duration,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        BackupSettings {
+            dir: PathBuf::from("backups"),
+            keep_daily: 7,
+            keep_weekly: 4,
+            interval: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BackupInfo {
+    pub path: PathBuf,
+    pub created: NaiveDateTime,
+}
+
+/// Copies the live database into the backup directory using SQLite's online
+/// backup API, checks the copy and applies the retention policy.
+pub fn run_backup(db: &Connection, settings: &BackupSettings) -> Result<PathBuf, Box<dyn Error>> {
+    fs::create_dir_all(&settings.dir)?;
+
+    let file_name = format!("{}{}.db", BACKUP_PREFIX, Local::now().format(BACKUP_STAMP));
+    let path = settings.dir.join(file_name);
+
+    {
+        // Encrypted with the live database's key, if it has one
+        let mut dest = encryption::open(&path)?;
+        let backup = Backup::new(db, &mut dest)?;
+        backup.run_to_completion(100, Duration::from_millis(25), None)?;
+    }
+
+    if let Err(e) = verify_backup(&path) {
+        let _ = fs::remove_file(&path);
+        return Err(format!("Backup failed verification: {}", e).into());
+    }
+
+    println!("💾 Backup written to {}", path.display());
+
+    for removed in apply_retention(&settings.dir, settings.keep_daily, settings.keep_weekly)? {
+        println!("🗑️  Removed old backup {}", removed.display());
+    }
+
+    Ok(path)
+}
+
+/// Opens a backup read-only, runs `PRAGMA integrity_check` and checks that it
+/// looks like a package room database. Returns its schema version.
+pub fn verify_backup(path: &Path) -> Result<i32, Box<dyn Error>> {
+    let db = encryption::open_connection(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    let integrity: String = db.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    if integrity != "ok" {
+        return Err(format!("Integrity check failed: {}", integrity).into());
+    }
+
+    for table in REQUIRED_TABLES {
+        let exists: bool = db.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+            [table],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Err(format!("Not a package room database: table '{}' is missing", table).into());
+        }
+    }
+
+    Ok(get_schema_version(&db)?)
+}
+
+/// Lists backups in `dir`, newest first
+pub fn list_backups(dir: &Path) -> Result<Vec<BackupInfo>, Box<dyn Error>> {
+    let mut backups = Vec::new();
+
+    if !dir.exists() {
+        return Ok(backups);
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let stamp = path.file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(BACKUP_PREFIX))
+            .and_then(|name| name.strip_suffix(".db"));
+
+        if let Some(created) = stamp.and_then(|s| NaiveDateTime::parse_from_str(s, BACKUP_STAMP).ok()) {
+            backups.push(BackupInfo { path, created });
+        }
+    }
+
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created));
+    Ok(backups)
+}
+
+/// Keeps the newest backup of each of the last `keep_daily` days and of each
+/// of the last `keep_weekly` ISO weeks, deletes everything else.
+/// Returns the removed files.
+pub fn apply_retention(dir: &Path, keep_daily: usize, keep_weekly: usize) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let backups = list_backups(dir)?;
+
+    let mut keep: HashSet<PathBuf> = HashSet::new();
+    let mut days = Vec::new();
+    let mut weeks = Vec::new();
+
+    // Newest first, so the first backup seen for a day/week is the one kept
+    for backup in &backups {
+        let day = backup.created.date();
+        if !days.contains(&day) && days.len() < keep_daily {
+            days.push(day);
+            keep.insert(backup.path.clone());
+        }
+
+        let week = (day.iso_week().year(), day.iso_week().week());
+        if !weeks.contains(&week) && weeks.len() < keep_weekly {
+            weeks.push(week);
+            keep.insert(backup.path.clone());
+        }
+    }
+
+    let mut removed = Vec::new();
+    for backup in backups {
+        if !keep.contains(&backup.path) {
+            fs::remove_file(&backup.path)?;
+            removed.push(backup.path);
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Replaces the live database with a backup after validating it.
+/// The current database is first saved next to the backups as `pre-restore-*.db`.
+pub fn restore_backup(backup_path: &Path, live_path: &Path, settings: &BackupSettings) -> Result<(), Box<dyn Error>> {
+    let version = verify_backup(backup_path)?;
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "Backup schema version {} is newer than this app supports ({})", version, SCHEMA_VERSION
+        ).into());
+    }
+
+    let mut live = encryption::open(live_path)?;
+
+    fs::create_dir_all(&settings.dir)?;
+    let safety_copy = settings.dir.join(format!("pre-restore-{}.db", Local::now().format(BACKUP_STAMP)));
+    {
+        let mut copy = encryption::open(&safety_copy)?;
+        Backup::new(&live, &mut copy)?.run_to_completion(100, Duration::from_millis(25), None)?;
+    }
+    println!("💾 Current database saved to {}", safety_copy.display());
+
+    let backup = encryption::open_connection(backup_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    Backup::new(&backup, &mut live)?.run_to_completion(100, Duration::from_millis(25), None)?;
+    println!("♻️  Restored {} (schema version {})", backup_path.display(), version);
+
+    Ok(())
+}
+
+/// Starts a background thread that backs up `db_path` whenever the newest
+/// backup is older than `settings.interval`.
+pub fn start_backup_scheduler(db_path: PathBuf, settings: BackupSettings) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        loop {
+            let due = match list_backups(&settings.dir) {
+                Ok(backups) => backups.first().map(|latest| {
+                    let age = Local::now().naive_local() - latest.created;
+                    age.to_std().map(|age| age >= settings.interval).unwrap_or(false)
+                }).unwrap_or(true),
+                Err(_) => true,
+            };
+
+            if due {
+                let result = encryption::open(&db_path)
+                    .map_err(|e| -> Box<dyn Error> { e.into() })
+                    .and_then(|db| {
+                        db.busy_timeout(Duration::from_secs(5))?;
+                        run_backup(&db, &settings)
+                    });
+
+                if let Err(e) = result {
+                    println!("❌ Scheduled backup failed: {}", e);
+                }
+            }
+
+            std::thread::sleep(Duration::from_secs(60));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An empty directory holding a file for each backup time, `YYYY-MM-DD HH:MM:SS`
+    fn backup_dir(test: &str, times: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("backup-test-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for time in times {
+            let created = NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").unwrap();
+            fs::write(dir.join(format!("{}{}.db", BACKUP_PREFIX, created.format(BACKUP_STAMP))), b"").unwrap();
+        }
+        dir
+    }
+
+    // Times of the backups left in the directory, newest first
+    fn remaining(dir: &Path) -> Vec<String> {
+        list_backups(dir).unwrap().iter()
+            .map(|backup| backup.created.format("%Y-%m-%d %H:%M:%S").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn only_the_newest_backup_of_a_day_is_kept() {
+        let dir = backup_dir("same-day", &["2025-03-04 09:00:00", "2025-03-04 21:00:00", "2025-03-04 15:00:00"]);
+        let removed = apply_retention(&dir, 7, 0).unwrap();
+
+        assert_eq!(removed.len(), 2);
+        assert_eq!(remaining(&dir), ["2025-03-04 21:00:00"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn midnight_starts_a_new_day() {
+        let dir = backup_dir("day-boundary", &[
+            "2025-03-05 00:00:00", "2025-03-04 23:59:59", "2025-03-04 12:00:00", "2025-03-03 10:00:00",
+        ]);
+        apply_retention(&dir, 2, 0).unwrap();
+
+        assert_eq!(remaining(&dir), ["2025-03-05 00:00:00", "2025-03-04 23:59:59"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn weeks_start_on_monday() {
+        // 2025-03-10 is a Monday; the Sunday before it belongs to the previous ISO week
+        let dir = backup_dir("week-boundary", &[
+            "2025-03-10 08:00:00", "2025-03-09 23:00:00", "2025-03-09 09:00:00",
+            "2025-03-08 12:00:00", "2025-03-03 12:00:00", "2025-03-02 12:00:00",
+        ]);
+        apply_retention(&dir, 1, 2).unwrap();
+
+        assert_eq!(remaining(&dir), ["2025-03-10 08:00:00", "2025-03-09 23:00:00"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn iso_weeks_span_the_new_year() {
+        // 2024-12-30 is in week 1 of 2025, 2024-12-29 in week 52 of 2024
+        let dir = backup_dir("year-boundary", &["2025-01-02 12:00:00", "2024-12-30 12:00:00", "2024-12-29 12:00:00"]);
+        apply_retention(&dir, 0, 2).unwrap();
+
+        assert_eq!(remaining(&dir), ["2025-01-02 12:00:00", "2024-12-29 12:00:00"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn daily_and_weekly_copies_add_up() {
+        // The newest copy of each of the last two days and of each of the last three weeks
+        let dir = backup_dir("combined", &[
+            "2025-03-19 12:00:00", "2025-03-18 12:00:00", "2025-03-17 12:00:00",
+            "2025-03-12 12:00:00", "2025-03-11 12:00:00", "2025-03-05 12:00:00", "2025-02-26 12:00:00",
+        ]);
+        let removed = apply_retention(&dir, 2, 3).unwrap();
+
+        assert_eq!(remaining(&dir), ["2025-03-19 12:00:00", "2025-03-18 12:00:00", "2025-03-12 12:00:00", "2025-03-05 12:00:00"]);
+        assert_eq!(removed.len(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn other_files_in_the_backup_directory_are_left_alone() {
+        let dir = backup_dir("other-files", &["2025-03-04 09:00:00", "2025-03-03 09:00:00"]);
+        fs::write(dir.join("pre-restore-20250301-120000.db"), b"").unwrap();
+        fs::write(dir.join(format!("{}notes.txt", BACKUP_PREFIX)), b"").unwrap();
+        apply_retention(&dir, 1, 0).unwrap();
+
+        assert_eq!(remaining(&dir), ["2025-03-04 09:00:00"]);
+        assert!(dir.join("pre-restore-20250301-120000.db").exists());
+        assert!(dir.join(format!("{}notes.txt", BACKUP_PREFIX)).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_missing_directory_has_nothing_to_remove() {
+        let dir = std::env::temp_dir().join(format!("backup-test-missing-{}", std::process::id()));
+        assert!(apply_retention(&dir, 7, 4).unwrap().is_empty());
+    }
+}
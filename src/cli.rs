@@ -0,0 +1,570 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use rusqlite::Connection;
+use slint_rust_template::audit;
+use slint_rust_template::backup::{self, BackupSettings};
+use slint_rust_template::config::{self, Config};
+use slint_rust_template::encryption;
+use slint_rust_template::export::{self, ExportFilter, ExportFormat, ExportTable};
+use slint_rust_template::filter;
+use slint_rust_template::label::{self, LabelFormat, PrintTarget, Symbology};
+use slint_rust_template::operator::{self, Permission, Role};
+use slint_rust_template::photo::{self, PhotoStage};
+use slint_rust_template::privacy;
+use slint_rust_template::property;
+use slint_rust_template::report;
+use slint_rust_template::tenancy;
+use slint_rust_template::trash;
+use slint_rust_template::unit;
+
+const USAGE: &str = "Usage:
+  packout export <packages|residents|logs> [--format csv|json] [--out PATH]
+                 [--from YYYY-MM-DD] [--to YYYY-MM-DD] [--status STATUS] [--apt APT]
+                 [--carrier CARRIER] [--operator NAME]
+  packout report [--from YYYY-MM-DD] [--to YYYY-MM-DD] [--dir DIR]   (package report as HTML and PDF, today by default)
+  packout backup [--dir DIR]
+  packout backups [--dir DIR]
+  packout restore <BACKUP_FILE> [--dir DIR]
+  packout verify-log
+  packout purge [--days N]   (permanently delete records removed more than N days ago)
+  packout encrypt-db   (convert a plaintext database, with the key from PACKOUT_DB_KEY or database.key_file)
+  packout retention   (anonymise log entries past the configured retention period)
+  packout operator list
+  packout operator add <USERNAME> --password PASSWORD [--name DISPLAY_NAME] [--role admin|concierge|read_only]
+  packout operator passwd <USERNAME> --password PASSWORD
+  packout operator role <USERNAME> <admin|concierge|read_only>
+  packout operator <enable|disable> <USERNAME>
+  packout operator building <USERNAME> <BUILDING|all>
+  packout unit list
+  packout unit add <APARTMENTS> [--building NAME] [--floor N]   (e.g. 101-120,201,3A)
+  packout unit <enable|disable> <APARTMENT>
+  packout resident move-in <APT> <TENANT>... [--date YYYY-MM-DD]   (each tenant as 'First Last')
+  packout resident move-out <RESIDENT_ID>
+  packout resident erase <RESIDENT_ID>   (erase a former resident's personal data)
+  packout resident access <RESIDENT_ID> [--dir DIR]   (everything stored about a resident, as JSON and HTML)
+  packout package return <PACKAGE_ID> [--reason TEXT]
+  packout package reassign <PACKAGE_ID> <APT>
+  packout package shelf <PACKAGE_ID> <SHELF>
+  packout package label <PACKAGE_ID>... [--format zpl|escpos|pdf] [--barcode code128|qr]
+                 [--output DIR|tcp://HOST:PORT]   (print package labels, and notices if enabled)
+  packout package photo <PACKAGE_ID> <IMAGE>... [--stage intake|collection]
+  packout package damaged <PACKAGE_ID> [--note TEXT]
+  packout package undamaged <PACKAGE_ID>
+  packout property list
+  packout property add <NAME> [--address ADDRESS]
+  packout property <allow|deny> <BUILDING> <CARD_BUILDING>   (accept cards issued in CARD_BUILDING)
+
+Commands that change data or export it run as an operator:
+  --user USERNAME --user-password PASSWORD  (or PACKOUT_USER / PACKOUT_PASSWORD)
+and work in the configured building unless --property NAME is given";
+
+// Minimal flag parser: `--name value` pairs after the positional arguments
+struct Flags {
+    positional: Vec<String>,
+    named: Vec<(String, String)>,
+}
+
+impl Flags {
+    fn parse(args: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut positional = Vec::new();
+        let mut named = Vec::new();
+        let mut iter = args.iter();
+
+        while let Some(arg) = iter.next() {
+            if let Some(name) = arg.strip_prefix("--") {
+                let value = iter.next().ok_or_else(|| format!("Missing value for --{}", name))?;
+                named.push((name.to_string(), value.clone()));
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+
+        Ok(Flags { positional, named })
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.named.iter().rev().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+}
+
+/// Runs a command-line command. Returns `None` when no command was given
+/// and the UI should start instead.
+pub fn run(args: &[String]) -> Option<Result<(), Box<dyn Error>>> {
+    let command = args.first()?;
+
+    let result = match command.as_str() {
+        "export" => run_export(&args[1..]),
+        "report" => run_report(&args[1..]),
+        "backup" => run_backup(&args[1..]),
+        "backups" => run_list_backups(&args[1..]),
+        "restore" => run_restore(&args[1..]),
+        "operator" => run_operator(&args[1..]),
+        "unit" => run_unit(&args[1..]),
+        "property" => run_property(&args[1..]),
+        "resident" => run_resident(&args[1..]),
+        "package" => run_package(&args[1..]),
+        "verify-log" => run_verify_log(),
+        "purge" => run_purge(&args[1..]),
+        "retention" => run_retention(),
+        "encrypt-db" => run_encrypt_db(),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        other => Err(format!("Unknown command '{}'\n{}", other, USAGE).into()),
+    };
+
+    Some(result)
+}
+
+fn run_export(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let flags = Flags::parse(args)?;
+    let app_config = load_config()?;
+
+    let table_name = flags.positional.first().ok_or(USAGE)?;
+    let table = ExportTable::from_name(table_name)
+        .ok_or_else(|| format!("Unknown table '{}'\n{}", table_name, USAGE))?;
+    let format = ExportFormat::from_name(flags.get("format").unwrap_or("csv"))
+        .ok_or("Format must be csv or json")?;
+
+    let mut filter = ExportFilter::from_strings(
+        flags.get("from").unwrap_or(""),
+        flags.get("to").unwrap_or(""),
+        flags.get("status").unwrap_or(""),
+        flags.get("apt").unwrap_or(""),
+    );
+    filter.carrier = flags.get("carrier").and_then(filter::non_empty);
+    filter.operator = flags.get("operator").and_then(filter::non_empty);
+
+    let export_dir = app_config.for_property(flags.get("property").unwrap_or(&app_config.property.current)).export.dir;
+    let path = match flags.get("out") {
+        Some(out) => PathBuf::from(out),
+        None => export::default_export_path(Path::new(&export_dir), table, format),
+    };
+
+    let db = open_session(&flags, &app_config)?;
+    let count = export::export_table(&db, table, &filter, format, &path)?;
+    println!("Exported {} {} to {}", count, table.name(), path.display());
+
+    Ok(())
+}
+
+fn load_config() -> Result<Config, Box<dyn Error>> {
+    let app_config = config::load_config(Path::new(config::CONFIG_PATH))?;
+    encryption::load_key(&app_config.database)?;
+    Ok(app_config)
+}
+
+// Opens the database, logs in the operator given by --user or PACKOUT_USER and
+// switches to the building from --property or the config.
+// Without a user the connection has no session and permission checks fail.
+fn open_session(flags: &Flags, app_config: &Config) -> Result<Connection, Box<dyn Error>> {
+    let db = slint_rust_template::open_db(&app_config.database.path)?;
+
+    let username = flags.get("user").map(String::from).or_else(|| std::env::var("PACKOUT_USER").ok());
+    if let Some(username) = username {
+        let password = flags.get("user-password").map(String::from)
+            .or_else(|| std::env::var("PACKOUT_PASSWORD").ok())
+            .ok_or("Missing --user-password or PACKOUT_PASSWORD")?;
+        let staff = operator::authenticate(&db, &username, &password)?;
+        operator::login(&db, &staff)?;
+    }
+
+    let building = flags.get("property").unwrap_or(&app_config.property.current);
+    if !building.is_empty() {
+        let building = property::find_property(&db, building)?;
+        property::switch_property(&db, building.id)?;
+    }
+
+    Ok(db)
+}
+
+fn backup_settings(flags: &Flags, app_config: &Config) -> BackupSettings {
+    let mut settings = app_config.backup_settings();
+    if let Some(dir) = flags.get("dir") {
+        settings.dir = PathBuf::from(dir);
+    }
+    settings
+}
+
+fn run_backup(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let flags = Flags::parse(args)?;
+    let app_config = load_config()?;
+    let settings = backup_settings(&flags, &app_config);
+
+    let db = slint_rust_template::open_db(&app_config.database.path)?;
+    let path = backup::run_backup(&db, &settings)?;
+    println!("Backup created: {}", path.display());
+
+    Ok(())
+}
+
+fn run_list_backups(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let flags = Flags::parse(args)?;
+    let settings = backup_settings(&flags, &load_config()?);
+
+    for info in backup::list_backups(&settings.dir)? {
+        let status = match backup::verify_backup(&info.path) {
+            Ok(version) => format!("ok, schema v{}", version),
+            Err(e) => format!("INVALID: {}", e),
+        };
+        println!("{}  {}  ({})", info.created, info.path.display(), status);
+    }
+
+    Ok(())
+}
+
+fn run_restore(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let flags = Flags::parse(args)?;
+    let app_config = load_config()?;
+    let settings = backup_settings(&flags, &app_config);
+    let backup_file = flags.positional.first().ok_or(USAGE)?;
+
+    // Restoring replaces every record, so it needs the same rights as changing settings
+    let db = open_session(&flags, &app_config)?;
+    operator::require_permission(&db, Permission::ChangeSettings)?;
+    drop(db);
+
+    backup::restore_backup(Path::new(backup_file), Path::new(&app_config.database.path), &settings)?;
+    println!("Database restored from {}", backup_file);
+
+    Ok(())
+}
+
+fn run_report(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let flags = Flags::parse(args)?;
+    let app_config = load_config()?;
+    let mut settings = app_config.report_settings();
+    if let Some(dir) = flags.get("dir") {
+        settings.dir = PathBuf::from(dir);
+    }
+    let (from, to) = report::parse_range(flags.get("from").unwrap_or(""), flags.get("to").unwrap_or(""))?;
+
+    let db = open_session(&flags, &app_config)?;
+    let package_report = report::package_report(&db, from, to, settings.overdue_after_days)?;
+    let (html_path, pdf_path) = report::write_report(&package_report, &settings.dir)?;
+    for line in package_report.summary() {
+        println!("{}", line);
+    }
+    println!("Saved {} and {}", html_path.display(), pdf_path.display());
+    Ok(())
+}
+
+fn run_verify_log() -> Result<(), Box<dyn Error>> {
+    let db = slint_rust_template::open_db(&load_config()?.database.path)?;
+    let report = audit::verify_chain(&db)?;
+    println!("{}", report.summary());
+
+    if report.is_intact() {
+        Ok(())
+    } else {
+        Err("Audit log failed verification".into())
+    }
+}
+
+fn run_purge(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let flags = Flags::parse(args)?;
+    let app_config = load_config()?;
+    let days = match flags.get("days") {
+        Some(days) => days.parse().map_err(|_| format!("Invalid number of days '{}'", days))?,
+        None => app_config.retention.purge_deleted_after_days,
+    };
+
+    let db = open_session(&flags, &app_config)?;
+    println!("{}", trash::purge_deleted(&db, days, &app_config.photo_settings())?.summary());
+    Ok(())
+}
+
+fn run_encrypt_db() -> Result<(), Box<dyn Error>> {
+    let app_config = load_config()?;
+    let path = Path::new(&app_config.database.path);
+    let plaintext_copy = encryption::encrypt_database(path)?;
+    println!("Encrypted {}", path.display());
+    println!("The plaintext original was kept as {}.", plaintext_copy.display());
+    println!("Delete it, and any older backups, once the app opens the encrypted database.");
+    Ok(())
+}
+
+fn run_retention() -> Result<(), Box<dyn Error>> {
+    let app_config = load_config()?;
+    let db = slint_rust_template::open_db(&app_config.database.path)?;
+    let count = privacy::apply_retention(&db, &app_config.retention)?;
+    println!("Anonymised {} log entries", count);
+    Ok(())
+}
+
+fn run_operator(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let flags = Flags::parse(args)?;
+    let action = flags.positional.first().ok_or(USAGE)?;
+    let db = open_session(&flags, &load_config()?)?;
+
+    if action == "list" {
+        let buildings = property::get_properties(&db)?;
+        for staff in operator::get_operators(&db)? {
+            let building = staff.property_id
+                .and_then(|id| buildings.iter().find(|b| b.id == id))
+                .map_or("all buildings", |b| b.name.as_str());
+            println!(
+                "{:>4}  {:<16} {:<24} {:<10} {:<20} {}{}",
+                staff.id,
+                staff.username,
+                staff.display_name,
+                staff.role.name(),
+                building,
+                if staff.active { "active" } else { "disabled" },
+                if staff.has_card { ", staff card" } else { "" },
+            );
+        }
+        return Ok(());
+    }
+
+    let username = flags.positional.get(1).ok_or(USAGE)?;
+    match action.as_str() {
+        "add" => {
+            let password = flags.get("password").ok_or("Missing --password")?;
+            let role = parse_role(flags.get("role").unwrap_or("concierge"))?;
+            operator::create_operator(&db, username, flags.get("name").unwrap_or(""), password, role)?;
+            println!("Operator '{}' created as {}", username, role.name());
+        }
+        "role" => {
+            let role = parse_role(flags.positional.get(2).ok_or(USAGE)?)?;
+            let staff = operator::find_operator(&db, username)?;
+            operator::set_role(&db, staff.id, role)?;
+            println!("Operator '{}' is now {}", staff.username, role.name());
+        }
+        "passwd" => {
+            let password = flags.get("password").ok_or("Missing --password")?;
+            let staff = operator::find_operator(&db, username)?;
+            operator::set_password(&db, staff.id, password)?;
+            println!("Password changed for '{}'", staff.username);
+        }
+        "enable" | "disable" => {
+            let staff = operator::find_operator(&db, username)?;
+            operator::set_active(&db, staff.id, action == "enable")?;
+            println!("Operator '{}' {}d", staff.username, action);
+        }
+        "building" => {
+            let staff = operator::find_operator(&db, username)?;
+            match flags.positional.get(2).ok_or(USAGE)?.as_str() {
+                "all" => {
+                    property::set_operator_property(&db, staff.id, None)?;
+                    println!("Operator '{}' can work in all buildings", staff.username);
+                }
+                name => {
+                    let building = property::find_property(&db, name)?;
+                    property::set_operator_property(&db, staff.id, Some(building.id))?;
+                    println!("Operator '{}' is limited to {}", staff.username, building.name);
+                }
+            }
+        }
+        other => return Err(format!("Unknown operator action '{}'\n{}", other, USAGE).into()),
+    }
+
+    Ok(())
+}
+
+fn parse_role(name: &str) -> Result<Role, Box<dyn Error>> {
+    Role::from_name(name).ok_or_else(|| format!("Unknown role '{}', use admin, concierge or read_only", name).into())
+}
+
+fn run_unit(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let flags = Flags::parse(args)?;
+    let action = flags.positional.first().ok_or(USAGE)?;
+    let db = open_session(&flags, &load_config()?)?;
+
+    if action == "list" {
+        for apartment in unit::get_units(&db)? {
+            println!(
+                "{:>6}  {:<12} {:>5}  {}",
+                apartment.number,
+                apartment.building,
+                apartment.floor.map(|f| f.to_string()).unwrap_or_default(),
+                apartment.status,
+            );
+        }
+        return Ok(());
+    }
+
+    let apartments = flags.positional.get(1).ok_or(USAGE)?;
+    let building = flags.get("building").unwrap_or("");
+    match action.as_str() {
+        "add" => match flags.get("floor") {
+            Some(floor) => {
+                let floor = floor.parse().map_err(|_| format!("Invalid floor '{}'", floor))?;
+                unit::add_unit(&db, building, apartments, Some(floor))?;
+                println!("Apartment {} registered", unit::normalize_apt(apartments));
+            }
+            None => {
+                let added = unit::add_units_from_spec(&db, building, apartments)?;
+                println!("{} apartments registered", added);
+            }
+        },
+        "enable" | "disable" => {
+            unit::set_unit_active(&db, apartments, action == "enable")?;
+            println!("Apartment {} {}d", unit::normalize_apt(apartments), action);
+        }
+        other => return Err(format!("Unknown unit action '{}'\n{}", other, USAGE).into()),
+    }
+
+    Ok(())
+}
+
+fn run_resident(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let flags = Flags::parse(args)?;
+    let action = flags.positional.first().ok_or(USAGE)?;
+    let app_config = load_config()?;
+    let db = open_session(&flags, &app_config)?;
+
+    match action.as_str() {
+        "move-in" => {
+            let apt = flags.positional.get(1).ok_or(USAGE)?;
+            let tenants: Vec<(&str, &str)> = flags.positional[2..].iter()
+                .map(|name| name.trim().rsplit_once(char::is_whitespace).unwrap_or((name.as_str(), "")))
+                .collect();
+            let ids = tenancy::move_in(&db, apt, &tenants, flags.get("date").unwrap_or(""))?;
+            println!("{} resident(s) moved into Apt {}", ids.len(), unit::normalize_apt(apt));
+        }
+        "move-out" => {
+            let id = flags.positional.get(1).ok_or(USAGE)?;
+            let id = id.parse().map_err(|_| format!("Invalid resident id '{}'", id))?;
+            let moved = tenancy::move_out(&db, id)?;
+            println!(
+                "{} {} moved out of Apt {}, {} card(s) revoked",
+                moved.resident.first_name, moved.resident.last_name, moved.resident.apt, moved.revoked_cards
+            );
+            for package in &moved.pending_packages {
+                println!("  pending  {:>6}  #{:<8} {:<12} {}", package.id, package.package_number,
+                    package.carrier.as_deref().unwrap_or(""), package.date_time);
+            }
+            if moved.remaining_residents > 0 {
+                println!("{} other resident(s) still live in Apt {}", moved.remaining_residents, moved.resident.apt);
+            }
+        }
+        "erase" => {
+            let id = flags.positional.get(1).ok_or(USAGE)?;
+            let id = id.parse().map_err(|_| format!("Invalid resident id '{}'", id))?;
+            println!("{}", privacy::erase_resident(&db, id, &app_config.photo_settings())?.summary());
+        }
+        "access" => {
+            let id = flags.positional.get(1).ok_or(USAGE)?;
+            let id = id.parse().map_err(|_| format!("Invalid resident id '{}'", id))?;
+            let dir = match flags.get("dir") {
+                Some(dir) => dir.to_string(),
+                None => app_config.for_property(flags.get("property").unwrap_or(&app_config.property.current)).export.dir,
+            };
+            let (json_path, html_path) = privacy::export_subject_access(&db, id, Path::new(&dir), &app_config.photo_settings())?;
+            println!("Saved {} and {}", json_path.display(), html_path.display());
+        }
+        other => return Err(format!("Unknown resident action '{}'\n{}", other, USAGE).into()),
+    }
+
+    Ok(())
+}
+
+fn run_package(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let flags = Flags::parse(args)?;
+    let action = flags.positional.first().ok_or(USAGE)?;
+    let app_config = load_config()?;
+    let db = open_session(&flags, &app_config)?;
+
+    let parse_id = |id: &String| id.parse::<u32>().map_err(|_| format!("Invalid package id '{}'", id));
+    let id = parse_id(flags.positional.get(1).ok_or(USAGE)?)?;
+    match action.as_str() {
+        "return" => {
+            tenancy::return_package(&db, id, flags.get("reason").unwrap_or(""))?;
+            println!("Package {} returned", id);
+        }
+        "reassign" => {
+            let apt = flags.positional.get(2).ok_or(USAGE)?;
+            tenancy::reassign_package(&db, id, apt)?;
+            println!("Package {} reassigned to Apt {}", id, unit::normalize_apt(apt));
+        }
+        "shelf" => {
+            let shelf = flags.positional.get(2).ok_or(USAGE)?;
+            label::set_shelf(&db, id, shelf)?;
+            println!("Package {} is on shelf {}", id, shelf.trim());
+        }
+        "label" => {
+            let ids = flags.positional[1..].iter().map(parse_id).collect::<Result<Vec<_>, _>>()?;
+            let mut settings = app_config.label_settings();
+            if let Some(format) = flags.get("format") {
+                settings.format = LabelFormat::from_name(format).ok_or_else(|| format!("Unknown label format '{}'", format))?;
+            }
+            if let Some(barcode) = flags.get("barcode") {
+                settings.symbology = Symbology::from_name(barcode).ok_or_else(|| format!("Unknown barcode '{}'", barcode))?;
+            }
+            if let Some(output) = flags.get("output") {
+                settings.target = PrintTarget::parse(output);
+            }
+            let target = label::print_labels(&db, &ids, &settings)?;
+            println!("{} label(s) sent to {}", ids.len(), target);
+        }
+        "photo" => {
+            let images = flags.positional.get(2..).filter(|images| !images.is_empty()).ok_or(USAGE)?;
+            let stage = match flags.get("stage") {
+                Some(stage) => PhotoStage::from_name(stage).ok_or_else(|| format!("Unknown photo stage '{}'", stage))?,
+                None => PhotoStage::Intake,
+            };
+            let settings = app_config.photo_settings();
+            for image in images {
+                let added = photo::attach_photo(&db, &settings, id, Path::new(image), stage)?;
+                println!("{} added to package {} as {}", image, id, added.path.display());
+            }
+        }
+        "damaged" | "undamaged" => {
+            photo::set_damaged(&db, id, action == "damaged", flags.get("note").unwrap_or(""))?;
+            println!("Package {} {}", id, if action == "damaged" { "marked damaged on arrival" } else { "no longer marked damaged" });
+        }
+        other => return Err(format!("Unknown package action '{}'\n{}", other, USAGE).into()),
+    }
+
+    Ok(())
+}
+
+fn run_property(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let flags = Flags::parse(args)?;
+    let action = flags.positional.first().ok_or(USAGE)?;
+    let db = open_session(&flags, &load_config()?)?;
+
+    if action == "list" {
+        let current = property::current_property_id(&db)?;
+        for building in property::get_properties(&db)? {
+            let accepts: Vec<String> = property::card_access(&db, building.id)?
+                .into_iter()
+                .map(|other| other.name)
+                .collect();
+            println!(
+                "{} {:>4}  {:<24} {:<32} {}{}",
+                if building.id == current { "*" } else { " " },
+                building.id,
+                building.name,
+                building.address,
+                building.status,
+                if accepts.is_empty() { String::new() } else { format!(", accepts cards from {}", accepts.join(", ")) },
+            );
+        }
+        return Ok(());
+    }
+
+    let name = flags.positional.get(1).ok_or(USAGE)?;
+    match action.as_str() {
+        "add" => {
+            property::add_property(&db, name, flags.get("address").unwrap_or(""))?;
+            println!("Building '{}' added", name.trim());
+        }
+        "allow" | "deny" => {
+            let building = property::find_property(&db, name)?;
+            let card_building = property::find_property(&db, flags.positional.get(2).ok_or(USAGE)?)?;
+            property::set_card_access(&db, building.id, card_building.id, action == "allow")?;
+            println!(
+                "{} {} cards from {}",
+                building.name,
+                if action == "allow" { "accepts" } else { "no longer accepts" },
+                card_building.name,
+            );
+        }
+        other => return Err(format!("Unknown property action '{}'\n{}", other, USAGE).into()),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,582 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::NaiveTime;
+
+use crate::backup::BackupSettings;
+use crate::label::{LabelFormat, LabelSettings, PrintTarget, Symbology};
+use crate::photo::PhotoSettings;
+use crate::report::ReportSettings;
+use crate::scanner::{ScanSource, Terminator, WedgeSettings};
+use crate::signature::SignaturePolicy;
+use crate::add_log_with_payload;
+use crate::operator::{Permission, require_permission};
+
+/// Default location of the configuration file
+pub const CONFIG_PATH: &str = "packout.toml";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub database: DatabaseConfig,
+    pub reader: ReaderConfig,
+    pub nfc: NfcConfig,
+    pub backup: BackupConfig,
+    pub export: ExportConfig,
+    pub security: SecurityConfig,
+    pub property: PropertyConfig,
+    pub retention: RetentionConfig,
+    pub report: ReportConfig,
+    pub label: LabelConfig,
+    pub collection: CollectionConfig,
+    pub scanner: ScannerConfig,
+    pub photo: PhotoConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    pub path: String,
+    /// File holding the passphrase of an encrypted database. Empty means
+    /// the database is plaintext unless `PACKOUT_DB_KEY` is set.
+    pub key_file: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReaderConfig {
+    /// Exact reader name to use. Empty means pick one automatically.
+    pub name: String,
+    /// When picking automatically, prefer a reader whose name contains this
+    pub prefer: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NfcConfig {
+    /// How long to wait for a card when linking or collecting
+    pub wait_timeout_secs: u64,
+    /// Delay between reader polls in the verification loop
+    pub poll_interval_ms: u64,
+    /// First NTAG page holding the card hash (16 pages are used). Linked cards keep
+    /// their hash where it was written, so after a change they have to be linked again.
+    pub ntag_start_page: u8,
+    pub led_success_ms: u16,
+    pub led_error_ms: u16,
+    pub led_processing_ms: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    pub enabled: bool,
+    pub dir: String,
+    pub interval_hours: u64,
+    pub keep_daily: usize,
+    pub keep_weekly: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportConfig {
+    pub dir: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecurityConfig {
+    /// Lock the session after this many minutes without input. 0 disables auto-lock.
+    pub auto_lock_minutes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// Removed residents, cards and packages are kept this long before a purge deletes them
+    pub purge_deleted_after_days: u32,
+    /// Log entries of `anonymise_log_types` older than this lose names, card hashes
+    /// and resident links. 0 keeps them as they are.
+    pub anonymise_log_after_months: u32,
+    pub anonymise_log_types: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReportConfig {
+    /// Write each building's daily package report to `dir` at `daily_at`,
+    /// and again after midnight with the rest of the day
+    pub enabled: bool,
+    pub dir: String,
+    /// Local time as HH:MM
+    pub daily_at: String,
+    /// Packages waiting longer than this many days are listed as overdue
+    pub overdue_after_days: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LabelConfig {
+    /// Print labels for packages as they are saved
+    pub auto_print: bool,
+    /// `zpl`, `escpos` or `pdf`
+    pub format: String,
+    /// `code128` or `qr`
+    pub barcode: String,
+    /// Folder to write print jobs to, or `tcp://host:port` of a raw printer socket
+    pub output: String,
+    /// Also print a notice slip for each apartment's mailbox
+    pub notices: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CollectionConfig {
+    /// At pickup, every box must be scanned before it can be handed over
+    pub confirm_by_scan: bool,
+    /// Signature pad at pickup: `off`, `optional` or `required`
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhotoConfig {
+    /// Where package photos are stored
+    pub dir: String,
+    /// Folder watched for new photos to attach, e.g. a network share. Empty turns it off.
+    pub watch_dir: String,
+}
+
+/// Keyboard-wedge barcode scanner. Read at startup.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScannerConfig {
+    /// `off` to scan into the focused field only, `global` to catch scans from any
+    /// keyboard in any tab, or `device` to read the scanner's own input device
+    pub mode: String,
+    /// Scanner input device for `device` mode, e.g. /dev/input/by-id/usb-...-event-kbd
+    pub device: String,
+    /// Longest pause between two characters of one scan
+    pub max_gap_ms: u64,
+    /// Shortest code taken for a scan
+    pub min_length: usize,
+    /// Key the scanner sends after each code: `enter` or `tab`
+    pub terminator: String,
+}
+
+/// Building this desk starts in, and settings that differ per building
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PropertyConfig {
+    /// Building name to open at startup. Empty means the first one.
+    pub current: String,
+    /// Per-building overrides, keyed by building name
+    pub overrides: BTreeMap<String, PropertyOverrides>,
+}
+
+/// Sections replaced while working in one building. Missing sections use the main settings.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PropertyOverrides {
+    pub reader: Option<ReaderConfig>,
+    pub export: Option<ExportConfig>,
+    pub security: Option<SecurityConfig>,
+    pub collection: Option<CollectionConfig>,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig { path: crate::DB_PATH.to_string(), key_file: String::new() }
+    }
+}
+
+impl Default for ReaderConfig {
+    fn default() -> Self {
+        ReaderConfig {
+            name: String::new(),
+            prefer: "Contactless".to_string(),
+        }
+    }
+}
+
+impl Default for NfcConfig {
+    fn default() -> Self {
+        NfcConfig {
+            wait_timeout_secs: 15,
+            poll_interval_ms: 300,
+            ntag_start_page: 5,
+            led_success_ms: 2000,
+            led_error_ms: 2000,
+            led_processing_ms: 1000,
+        }
+    }
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        let settings = BackupSettings::default();
+        BackupConfig {
+            enabled: true,
+            dir: settings.dir.to_string_lossy().into_owned(),
+            interval_hours: settings.interval.as_secs() / 3600,
+            keep_daily: settings.keep_daily,
+            keep_weekly: settings.keep_weekly,
+        }
+    }
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        ExportConfig { dir: "exports".to_string() }
+    }
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        SecurityConfig { auto_lock_minutes: 5 }
+    }
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig {
+            purge_deleted_after_days: 30,
+            anonymise_log_after_months: 0,
+            anonymise_log_types: vec!["verified".to_string()],
+        }
+    }
+}
+
+impl Default for LabelConfig {
+    fn default() -> Self {
+        LabelConfig {
+            auto_print: false,
+            format: "pdf".to_string(),
+            barcode: "code128".to_string(),
+            output: "labels".to_string(),
+            notices: false,
+        }
+    }
+}
+
+impl Default for CollectionConfig {
+    fn default() -> Self {
+        CollectionConfig { confirm_by_scan: false, signature: "off".to_string() }
+    }
+}
+
+impl Default for PhotoConfig {
+    fn default() -> Self {
+        PhotoConfig { dir: "photos".to_string(), watch_dir: String::new() }
+    }
+}
+
+impl Default for ScannerConfig {
+    fn default() -> Self {
+        ScannerConfig {
+            mode: "off".to_string(),
+            device: String::new(),
+            max_gap_ms: 50,
+            min_length: 4,
+            terminator: "enter".to_string(),
+        }
+    }
+}
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        ReportConfig {
+            enabled: false,
+            dir: "reports".to_string(),
+            daily_at: "22:00".to_string(),
+            overdue_after_days: 7,
+        }
+    }
+}
+
+impl ReaderConfig {
+    /// Picks a reader from the ones the system reports:
+    /// the configured name, then one containing `prefer`, then the first.
+    pub fn choose<'a>(&self, readers: &'a [String]) -> Option<&'a String> {
+        if !self.name.is_empty() {
+            return readers.iter().find(|r| **r == self.name);
+        }
+
+        if !self.prefer.is_empty() {
+            if let Some(preferred) = readers.iter().find(|r| r.contains(&self.prefer)) {
+                return Some(preferred);
+            }
+        }
+
+        readers.first()
+    }
+}
+
+impl Config {
+    /// Settings in effect while working in `property_name`
+    pub fn for_property(&self, property_name: &str) -> Config {
+        let mut config = self.clone();
+        let overrides = self.property.overrides.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(property_name))
+            .map(|(_, overrides)| overrides);
+
+        if let Some(overrides) = overrides {
+            if let Some(reader) = &overrides.reader {
+                config.reader = reader.clone();
+            }
+            if let Some(export) = &overrides.export {
+                config.export = export.clone();
+            }
+            if let Some(security) = &overrides.security {
+                config.security = security.clone();
+            }
+            if let Some(collection) = &overrides.collection {
+                config.collection = collection.clone();
+            }
+        }
+        config
+    }
+
+    /// Settings in effect for the building this desk is working in
+    pub fn effective(&self) -> Config {
+        self.for_property(&self.property.current)
+    }
+
+    /// Checks that all values are usable, returning every problem found
+    pub fn validate(&self) -> Result<(), String> {
+        let mut problems = Vec::new();
+
+        if self.database.path.trim().is_empty() {
+            problems.push("database.path must not be empty".to_string());
+        }
+        if !(1..=300).contains(&self.nfc.wait_timeout_secs) {
+            problems.push("nfc.wait_timeout_secs must be between 1 and 300".to_string());
+        }
+        if !(50..=10_000).contains(&self.nfc.poll_interval_ms) {
+            problems.push("nfc.poll_interval_ms must be between 50 and 10000".to_string());
+        }
+        // NTAG213 user memory is pages 4..=39 and the hash takes 16 pages
+        if !(4..=24).contains(&self.nfc.ntag_start_page) {
+            problems.push("nfc.ntag_start_page must be between 4 and 24".to_string());
+        }
+        // The reader counts LED time in 100 ms units, up to 255
+        for (name, value) in [
+            ("led_success_ms", self.nfc.led_success_ms),
+            ("led_error_ms", self.nfc.led_error_ms),
+            ("led_processing_ms", self.nfc.led_processing_ms),
+        ] {
+            if value > 25_500 {
+                problems.push(format!("nfc.{} must be at most 25500", name));
+            }
+        }
+        if self.backup.enabled {
+            if self.backup.dir.trim().is_empty() {
+                problems.push("backup.dir must not be empty".to_string());
+            }
+            if self.backup.interval_hours == 0 {
+                problems.push("backup.interval_hours must be at least 1".to_string());
+            }
+            if self.backup.keep_daily + self.backup.keep_weekly == 0 {
+                problems.push("backup must keep at least one daily or weekly copy".to_string());
+            }
+        }
+        if self.export.dir.trim().is_empty() {
+            problems.push("export.dir must not be empty".to_string());
+        }
+        if self.security.auto_lock_minutes > 240 {
+            problems.push("security.auto_lock_minutes must be at most 240".to_string());
+        }
+        if !(1..=3650).contains(&self.retention.purge_deleted_after_days) {
+            problems.push("retention.purge_deleted_after_days must be between 1 and 3650".to_string());
+        }
+        if self.retention.anonymise_log_after_months > 1200 {
+            problems.push("retention.anonymise_log_after_months must be at most 1200".to_string());
+        }
+        if self.report.enabled && self.report.dir.trim().is_empty() {
+            problems.push("report.dir must not be empty".to_string());
+        }
+        if NaiveTime::parse_from_str(self.report.daily_at.trim(), "%H:%M").is_err() {
+            problems.push("report.daily_at must be a time like 22:00".to_string());
+        }
+        if !(1..=365).contains(&self.report.overdue_after_days) {
+            problems.push("report.overdue_after_days must be between 1 and 365".to_string());
+        }
+        if LabelFormat::from_name(&self.label.format).is_none() {
+            problems.push("label.format must be zpl, escpos or pdf".to_string());
+        }
+        if Symbology::from_name(&self.label.barcode).is_none() {
+            problems.push("label.barcode must be code128 or qr".to_string());
+        }
+        if self.label.output.trim().is_empty() || self.label.output.trim() == "tcp://" {
+            problems.push("label.output must be a folder or tcp://host:port".to_string());
+        }
+        if SignaturePolicy::from_name(&self.collection.signature).is_none() {
+            problems.push("collection.signature must be off, optional or required".to_string());
+        }
+        if self.photo.dir.trim().is_empty() {
+            problems.push("photo.dir must not be empty".to_string());
+        }
+        if !self.photo.watch_dir.trim().is_empty() && self.photo.watch_dir.trim() == self.photo.dir.trim() {
+            problems.push("photo.watch_dir must not be the photo store itself".to_string());
+        }
+        match self.scanner.mode.trim() {
+            "off" | "global" => {}
+            "device" if self.scanner.device.trim().is_empty() => {
+                problems.push("scanner.device must be set when scanner.mode is device".to_string());
+            }
+            "device" => {}
+            _ => problems.push("scanner.mode must be off, global or device".to_string()),
+        }
+        if !(5..=500).contains(&self.scanner.max_gap_ms) {
+            problems.push("scanner.max_gap_ms must be between 5 and 500".to_string());
+        }
+        if !(1..=64).contains(&self.scanner.min_length) {
+            problems.push("scanner.min_length must be between 1 and 64".to_string());
+        }
+        if Terminator::from_name(&self.scanner.terminator).is_none() {
+            problems.push("scanner.terminator must be enter or tab".to_string());
+        }
+        for (name, overrides) in &self.property.overrides {
+            if overrides.export.as_ref().is_some_and(|e| e.dir.trim().is_empty()) {
+                problems.push(format!("property.overrides.{}.export.dir must not be empty", name));
+            }
+            if overrides.security.as_ref().is_some_and(|s| s.auto_lock_minutes > 240) {
+                problems.push(format!("property.overrides.{}.security.auto_lock_minutes must be at most 240", name));
+            }
+            if overrides.collection.as_ref()
+                .is_some_and(|c| SignaturePolicy::from_name(&c.signature).is_none()) {
+                problems.push(format!("property.overrides.{}.collection.signature must be off, optional or required", name));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("; "))
+        }
+    }
+
+    pub fn backup_settings(&self) -> BackupSettings {
+        BackupSettings {
+            dir: PathBuf::from(&self.backup.dir),
+            keep_daily: self.backup.keep_daily,
+            keep_weekly: self.backup.keep_weekly,
+            interval: Duration::from_secs(self.backup.interval_hours * 3600),
+        }
+    }
+
+    pub fn label_settings(&self) -> LabelSettings {
+        LabelSettings {
+            format: LabelFormat::from_name(&self.label.format).unwrap_or(LabelFormat::Pdf),
+            symbology: Symbology::from_name(&self.label.barcode).unwrap_or(Symbology::Code128),
+            target: PrintTarget::parse(&self.label.output),
+            notices: self.label.notices,
+        }
+    }
+
+    pub fn photo_settings(&self) -> PhotoSettings {
+        let watch_dir = self.photo.watch_dir.trim();
+        PhotoSettings {
+            dir: PathBuf::from(self.photo.dir.trim()),
+            watch_dir: (!watch_dir.is_empty()).then(|| PathBuf::from(watch_dir)),
+        }
+    }
+
+    /// Scanner listener settings, or `None` when scans only go to the focused field
+    pub fn wedge_settings(&self) -> Option<WedgeSettings> {
+        let source = match self.scanner.mode.trim() {
+            "global" => ScanSource::Global,
+            "device" => ScanSource::Device(PathBuf::from(self.scanner.device.trim())),
+            _ => return None,
+        };
+        Some(WedgeSettings {
+            source,
+            max_gap: Duration::from_millis(self.scanner.max_gap_ms),
+            min_length: self.scanner.min_length,
+            terminator: Terminator::from_name(&self.scanner.terminator).unwrap_or(Terminator::Enter),
+        })
+    }
+
+    pub fn signature_policy(&self) -> SignaturePolicy {
+        SignaturePolicy::from_name(&self.collection.signature).unwrap_or_default()
+    }
+
+    pub fn report_settings(&self) -> ReportSettings {
+        ReportSettings {
+            dir: PathBuf::from(&self.report.dir),
+            daily_at: NaiveTime::parse_from_str(self.report.daily_at.trim(), "%H:%M").unwrap_or_default(),
+            overdue_after_days: self.report.overdue_after_days,
+        }
+    }
+}
+
+/// Loads and validates the config file. A missing file is created with the defaults.
+pub fn load_config(path: &Path) -> Result<Config, Box<dyn Error>> {
+    if !path.exists() {
+        let config = Config::default();
+        save_config(path, &config)?;
+        println!("📝 Created default config at {}", path.display());
+        return Ok(config);
+    }
+    read_config(path)
+}
+
+// Reads and validates an existing config file
+fn read_config(path: &Path) -> Result<Config, Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    let config: Config = toml::from_str(&text)
+        .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?;
+    config.validate().map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?;
+
+    Ok(config)
+}
+
+/// Validates and writes the config file
+pub fn save_config(path: &Path, config: &Config) -> Result<(), Box<dyn Error>> {
+    config.validate()?;
+
+    let text = toml::to_string_pretty(config)?;
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    fs::write(path, text)?;
+
+    Ok(())
+}
+
+/// Linked cards `new` would no longer read because it moves the NTAG start page
+pub fn unreadable_cards(db: &rusqlite::Connection, current: &Config, new: &Config) -> Result<u32, rusqlite::Error> {
+    if current.nfc.ntag_start_page == new.nfc.ntag_start_page {
+        return Ok(0);
+    }
+    db.query_row("SELECT COUNT(*) FROM card WHERE status = 'active' AND deleted_at IS NULL", [], |row| row.get(0))
+}
+
+/// Saves settings changed by the logged-in operator and records the change in the log.
+/// Moving the NTAG start page while cards are linked needs `relink_confirmed`.
+pub fn update_config(db: &rusqlite::Connection, path: &Path, config: &Config, relink_confirmed: bool) -> Result<(), Box<dyn Error>> {
+    require_permission(db, Permission::ChangeSettings)?;
+    if !relink_confirmed {
+        // Without a file the defaults were in use
+        let current = if path.exists() { read_config(path).ok() } else { Some(Config::default()) };
+        if let Some(current) = current {
+            let cards = unreadable_cards(db, &current, config)?;
+            if cards > 0 {
+                return Err(format!(
+                    "nfc.ntag_start_page can't change while {} cards are linked at page {} without confirmation",
+                    cards, current.nfc.ntag_start_page
+                ).into());
+            }
+        }
+    }
+    save_config(path, config)?;
+    add_log_with_payload(
+        db, "settings", &format!("Settings saved to {}", path.display()),
+        serde_json::json!({ "path": path.display().to_string() }),
+    )?;
+    Ok(())
+}
@@ -0,0 +1,159 @@
+use rusqlite::{Connection, Error, OpenFlags};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use chrono::Local;
+
+use crate::app_error;
+use crate::config::DatabaseConfig;
+
+/// Environment variable holding the database passphrase; takes precedence over `database.key_file`
+pub const KEY_ENV: &str = "PACKOUT_DB_KEY";
+
+// Every plaintext SQLite file starts with this; an encrypted one looks random
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+// Passphrase for every connection this process opens. SQLCipher derives the page key from it.
+static DATABASE_KEY: OnceLock<String> = OnceLock::new();
+
+/// Whether this build was compiled with SQLCipher (`--features encryption`)
+pub fn is_supported() -> bool {
+    cfg!(feature = "encryption")
+}
+
+/// Reads the passphrase from `PACKOUT_DB_KEY` or the configured key file, if either is set.
+/// Call once at startup, before the database is opened.
+pub fn load_key(config: &DatabaseConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let key = match std::env::var(KEY_ENV) {
+        Ok(key) => key,
+        Err(_) if !config.key_file.trim().is_empty() => fs::read_to_string(config.key_file.trim())
+            .map_err(|e| format!("Could not read database key file {}: {}", config.key_file, e))?,
+        Err(_) => return Ok(()),
+    };
+    let key = key.trim().to_string();
+    if key.is_empty() {
+        return Err("The database key is empty".into());
+    }
+    if !is_supported() {
+        return Err("A database key is set, but this build has no encryption support; rebuild with --features encryption".into());
+    }
+    if DATABASE_KEY.get().is_some_and(|current| *current != key) {
+        return Err("A different database key was already loaded".into());
+    }
+    let _ = DATABASE_KEY.set(key);
+    Ok(())
+}
+
+/// Whether a key was loaded, i.e. databases are opened encrypted
+pub fn is_enabled() -> bool {
+    DATABASE_KEY.get().is_some()
+}
+
+/// Whether `path` exists and is not a plaintext SQLite file
+pub fn is_encrypted(path: &Path) -> bool {
+    let mut header = [0u8; 16];
+    match fs::File::open(path).and_then(|mut file| file.read_exact(&mut header)) {
+        Ok(()) => &header != SQLITE_HEADER,
+        Err(_) => false,
+    }
+}
+
+/// Opens a database file with the loaded key. New files are created encrypted.
+/// Plaintext files are refused while a key is loaded, so nothing is written to them by mistake.
+pub fn open_connection(path: &Path, flags: OpenFlags) -> Result<Connection, Error> {
+    let has_data = fs::metadata(path).map(|m| m.len() > 0).unwrap_or(false);
+    let Some(key) = DATABASE_KEY.get() else {
+        if has_data && is_encrypted(path) {
+            return Err(app_error(format!(
+                "{} is encrypted; set {} or database.key_file to open it", path.display(), KEY_ENV
+            )));
+        }
+        return Connection::open_with_flags(path, flags);
+    };
+    if has_data && !is_encrypted(path) {
+        return Err(app_error(format!(
+            "{} is not encrypted yet; run `packout encrypt-db` to convert it", path.display()
+        )));
+    }
+
+    let db = Connection::open_with_flags(path, flags)?;
+    db.pragma_update(None, "key", key)?;
+    // The key is only checked on the first read
+    db.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0))
+        .map_err(|_| app_error(format!("Wrong key for {}", path.display())))?;
+    Ok(db)
+}
+
+/// `open_connection` with the default read-write flags
+pub fn open(path: &Path) -> Result<Connection, Error> {
+    open_connection(path, OpenFlags::default())
+}
+
+/// Converts the plaintext database at `path` to an encrypted one with the loaded key.
+/// The encrypted copy is checked before it replaces the original, which is kept
+/// as `<path>.plaintext-<time>` and returned; delete it once the new file works.
+pub fn encrypt_database(path: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let key = DATABASE_KEY.get()
+        .ok_or_else(|| format!("Set {} or database.key_file to the passphrase to encrypt with", KEY_ENV))?;
+    if !path.exists() {
+        return Err(format!("No database at {}", path.display()).into());
+    }
+    if is_encrypted(path) {
+        return Err(format!("{} is already encrypted", path.display()).into());
+    }
+
+    // ATTACH creates the encrypted file with this connection's open flags
+    let plain = Connection::open(path)?;
+    let integrity: String = plain.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    if integrity != "ok" {
+        return Err(format!("Integrity check failed: {}", integrity).into());
+    }
+    let version = crate::get_schema_version(&plain)?;
+
+    let encrypted_path = path.with_extension("encrypting");
+    if encrypted_path.exists() {
+        fs::remove_file(&encrypted_path)?;
+    }
+    let export = (|| -> Result<(), Error> {
+        plain.execute(
+            "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+            rusqlite::params![encrypted_path.to_string_lossy(), key],
+        )?;
+        plain.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+        plain.pragma_update(Some("encrypted"), "user_version", version)?;
+        plain.execute_batch("DETACH DATABASE encrypted")
+    })();
+    drop(plain);
+    if let Err(e) = export {
+        let _ = fs::remove_file(&encrypted_path);
+        return Err(format!("Could not encrypt the database: {}", e).into());
+    }
+
+    // The copy must open with the key and hold the same rows before it replaces the original
+    let check = (|| -> Result<(), Box<dyn std::error::Error>> {
+        crate::backup::verify_backup(&encrypted_path)?;
+        let encrypted = open_connection(&encrypted_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let plain = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        for table in ["resident", "card", "package", "log", "operator"] {
+            let sql = format!("SELECT COUNT(*) FROM {}", table);
+            let before: i64 = plain.query_row(&sql, [], |row| row.get(0))?;
+            let after: i64 = encrypted.query_row(&sql, [], |row| row.get(0))?;
+            if before != after {
+                return Err(format!("{} has {} rows in the encrypted copy, {} expected", table, after, before).into());
+            }
+        }
+        Ok(())
+    })();
+    if let Err(e) = check {
+        let _ = fs::remove_file(&encrypted_path);
+        return Err(format!("Encrypted copy failed verification: {}", e).into());
+    }
+
+    let plaintext_copy = PathBuf::from(format!(
+        "{}.plaintext-{}", path.display(), Local::now().format("%Y%m%d-%H%M%S")
+    ));
+    fs::rename(path, &plaintext_copy)?;
+    fs::rename(&encrypted_path, path)?;
+    Ok(plaintext_copy)
+}
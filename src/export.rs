@@ -0,0 +1,307 @@
+use rusqlite::{Connection, types::Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use chrono::Local;
+
+use crate::filter::{self, RecordFilter, WhereBuilder};
+use crate::operator::{Permission, require_permission};
+use crate::property::CURRENT_PROPERTY;
+use crate::unit;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "csv" => Some(ExportFormat::Csv),
+            "json" => Some(ExportFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportTable {
+    Packages,
+    Residents,
+    Logs,
+}
+
+impl ExportTable {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "packages" | "package" => Some(ExportTable::Packages),
+            "residents" | "resident" => Some(ExportTable::Residents),
+            "logs" | "log" => Some(ExportTable::Logs),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportTable::Packages => "packages",
+            ExportTable::Residents => "residents",
+            ExportTable::Logs => "logs",
+        }
+    }
+}
+
+/// Filters applied to an export, see `RecordFilter`
+pub type ExportFilter = RecordFilter;
+
+/// Rows read from the database, ready to be written as CSV or JSON
+pub struct ExportData {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+pub(crate) fn query_export_data(db: &Connection, sql: &str, params: &[String]) -> Result<ExportData, rusqlite::Error> {
+    let mut stmt = db.prepare(sql)?;
+    let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    let column_count = columns.len();
+
+    let rows = stmt.query_map(rusqlite::params_from_iter(params.iter()), |row| {
+        (0..column_count)
+            .map(|i| row.get::<_, Value>(i))
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+
+    Ok(ExportData {
+        columns,
+        rows: rows.collect::<Result<Vec<_>, _>>()?,
+    })
+}
+
+pub fn get_packages_export(db: &Connection, filter: &ExportFilter) -> Result<ExportData, Box<dyn std::error::Error>> {
+    filter.validate()?;
+
+    let query = filter::package_query(filter);
+    let sql = query.sql(
+        "p.id, p.apt, p.package_number, p.barcode, p.comment, p.carrier, p.date_time, p.status,
+         p.collection_time, p.collected_by, o.display_name AS received_by"
+    );
+    Ok(query_export_data(db, &sql, &query.builder.params)?)
+}
+
+pub fn get_residents_export(db: &Connection, filter: &ExportFilter) -> Result<ExportData, Box<dyn std::error::Error>> {
+    filter.validate()?;
+
+    let mut builder = WhereBuilder::new();
+    if let Some(status) = &filter.status {
+        match status.to_lowercase().as_str() {
+            "linked" => builder.push("linked = 1", &[]),
+            "unlinked" => builder.push("linked = 0", &[]),
+            "active" | "moved_out" => builder.push("status = {}", &[&status.to_lowercase()]),
+            other => return Err(format!(
+                "Unknown resident status '{}', use linked, unlinked, active or moved_out", other
+            ).into()),
+        }
+    }
+    if let Some(apt) = &filter.apt {
+        builder.push("apt = {}", &[&unit::normalize_apt(apt)]);
+    }
+    // Residents who lived here at some point in the range
+    if let Some(from) = &filter.from_date {
+        builder.push("(moved_out IS NULL OR substr(moved_out, 1, 10) >= {})", &[from]);
+    }
+    if let Some(to) = &filter.to_date {
+        builder.push("(moved_in IS NULL OR substr(moved_in, 1, 10) <= {})", &[to]);
+    }
+
+    builder.push(&format!("property_id = {}", CURRENT_PROPERTY), &[]);
+    builder.push("deleted_at IS NULL", &[]);
+    let sql = format!(
+        "SELECT id, apt, first_name, last_name, linked, status, moved_in, moved_out
+         FROM resident{} ORDER BY apt, last_name",
+        builder.sql()
+    );
+    Ok(query_export_data(db, &sql, &builder.params)?)
+}
+
+pub fn get_logs_export(db: &Connection, filter: &ExportFilter) -> Result<ExportData, Box<dyn std::error::Error>> {
+    filter.validate()?;
+
+    let query = filter::log_query(filter);
+    let sql = query.sql(
+        "l.id AS id, l.action_type, l.action, l.date_time, o.display_name AS operator,
+         l.operator_id, l.resident_id, l.package_id, l.card_id, l.payload, l.prev_hash, l.hash"
+    );
+    Ok(query_export_data(db, &sql, &query.builder.params)?)
+}
+
+fn csv_field(value: &Value) -> String {
+    let text = display_value(value);
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+/// A value as plain text; NULL is empty
+pub(crate) fn display_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Integer(i) => i.to_string(),
+        Value::Real(f) => f.to_string(),
+        Value::Text(s) => s.clone(),
+        Value::Blob(b) => b.iter().map(|byte| format!("{:02x}", byte)).collect(),
+    }
+}
+
+fn json_value(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Integer(i) => serde_json::Value::from(*i),
+        Value::Real(f) => serde_json::Value::from(*f),
+        Value::Text(s) => serde_json::Value::from(s.as_str()),
+        Value::Blob(b) => serde_json::Value::from(b.iter().map(|byte| format!("{:02x}", byte)).collect::<String>()),
+    }
+}
+
+pub fn to_csv(data: &ExportData) -> String {
+    let mut out = String::new();
+    out.push_str(&data.columns.join(","));
+    out.push_str("\r\n");
+
+    for row in &data.rows {
+        let fields: Vec<String> = row.iter().map(csv_field).collect();
+        out.push_str(&fields.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// Rows as JSON objects keyed by column name
+pub(crate) fn json_rows(data: &ExportData) -> Vec<serde_json::Value> {
+    data.rows.iter().map(|row| {
+        let object: serde_json::Map<String, serde_json::Value> = data.columns.iter()
+            .cloned()
+            .zip(row.iter().map(json_value))
+            .collect();
+        serde_json::Value::Object(object)
+    }).collect()
+}
+
+pub fn to_json(data: &ExportData) -> String {
+    serde_json::to_string_pretty(&json_rows(data)).unwrap_or_else(|_| "[]".to_string())
+}
+
+pub(crate) fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// `bytes` as a base64 `data:` URI of type `mime`, for embedding images in HTML
+pub(crate) fn data_uri(mime: &str, bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = format!("data:{};base64,", mime);
+    encoded.reserve(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let padded = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let group = (u32::from(padded[0]) << 16) | (u32::from(padded[1]) << 8) | u32::from(padded[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i)) as usize & 0x3F] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Start of a self-contained HTML document with `title` as its heading.
+/// The caller appends the body and closes it with `</body></html>`.
+pub(crate) fn html_page_start(title: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title>\n\
+         <style>body{{font-family:sans-serif;margin:2em}}table{{border-collapse:collapse;margin-bottom:2em}}\
+         th,td{{border:1px solid #ccc;padding:4px 8px;text-align:left;vertical-align:top;font-size:13px}}\
+         th{{background:#f0f0f0}}</style></head><body>\n<h1>{title}</h1>\n",
+        title = html_escape(title),
+    )
+}
+
+/// Rows as an HTML table, or "None" when there are none
+pub(crate) fn html_table(data: &ExportData) -> String {
+    if data.rows.is_empty() {
+        return "<p>None</p>\n".to_string();
+    }
+    let mut html = String::from("<table>\n<tr>");
+    for column in &data.columns {
+        html.push_str(&format!("<th>{}</th>", html_escape(column)));
+    }
+    html.push_str("</tr>\n");
+    for row in &data.rows {
+        html.push_str("<tr>");
+        for value in row {
+            html.push_str(&format!("<td>{}</td>", html_escape(&display_value(value))));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+/// Default file name for an export, e.g. `exports/packages-20250101-173000.csv`
+pub fn default_export_path(dir: &Path, table: ExportTable, format: ExportFormat) -> PathBuf {
+    let stamp = Local::now().format("%Y%m%d-%H%M%S");
+    dir.join(format!("{}-{}.{}", table.name(), stamp, format.extension()))
+}
+
+/// Exports one table to `path` and returns the number of rows written
+pub fn export_table(
+    db: &Connection,
+    table: ExportTable,
+    filter: &ExportFilter,
+    format: ExportFormat,
+    path: &Path,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    require_permission(db, Permission::Export)?;
+
+    let data = match table {
+        ExportTable::Packages => get_packages_export(db, filter)?,
+        ExportTable::Residents => get_residents_export(db, filter)?,
+        ExportTable::Logs => get_logs_export(db, filter)?,
+    };
+
+    let contents = match format {
+        ExportFormat::Csv => to_csv(&data),
+        ExportFormat::Json => to_json(&data),
+    };
+
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    fs::write(path, contents)?;
+
+    Ok(data.rows.len())
+}
+
+pub fn export_packages(db: &Connection, filter: &ExportFilter, format: ExportFormat, path: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+    export_table(db, ExportTable::Packages, filter, format, path)
+}
+
+pub fn export_residents(db: &Connection, filter: &ExportFilter, format: ExportFormat, path: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+    export_table(db, ExportTable::Residents, filter, format, path)
+}
+
+pub fn export_logs(db: &Connection, filter: &ExportFilter, format: ExportFormat, path: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+    export_table(db, ExportTable::Logs, filter, format, path)
+}
@@ -0,0 +1,345 @@
+use chrono::NaiveDate;
+
+use crate::property::CURRENT_PROPERTY;
+use crate::unit::normalize_apt;
+
+/// Structured filter shared by the package and log searches and by exports.
+/// Empty fields are ignored.
+/// - `from_date` / `to_date`: inclusive `YYYY-MM-DD` bounds; residents match when they
+///   lived here at some point in the range
+/// - `status`: package status, log action type, or "linked"/"unlinked" for residents
+/// - `apt`: exact apartment match
+/// - `carrier`: package carrier, case-insensitive
+/// - `operator`: part of the operator's display name or username
+/// - `text`: free text matched against the main columns, like the search box
+#[derive(Debug, Clone, Default)]
+pub struct RecordFilter {
+    pub from_date: Option<String>,
+    pub to_date: Option<String>,
+    pub status: Option<String>,
+    pub apt: Option<String>,
+    pub carrier: Option<String>,
+    pub operator: Option<String>,
+    pub text: Option<String>,
+}
+
+/// Trims a UI/CLI value, treating blanks as "no filter"
+pub fn non_empty(s: &str) -> Option<String> {
+    let s = s.trim();
+    if s.is_empty() { None } else { Some(s.to_string()) }
+}
+
+impl RecordFilter {
+    /// Builds a filter from UI/CLI strings, treating blank values as "no filter"
+    pub fn from_strings(from_date: &str, to_date: &str, status: &str, apt: &str) -> Self {
+        RecordFilter {
+            from_date: non_empty(from_date),
+            to_date: non_empty(to_date),
+            status: non_empty(status),
+            apt: non_empty(apt),
+            ..Default::default()
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for date in [&self.from_date, &self.to_date].into_iter().flatten() {
+            if NaiveDate::parse_from_str(date, "%Y-%m-%d").is_err() {
+                return Err(format!("Invalid date '{}', expected YYYY-MM-DD", date));
+            }
+        }
+        if let (Some(from), Some(to)) = (&self.from_date, &self.to_date) {
+            if from > to {
+                return Err(format!("Start date {} is after end date {}", from, to));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Collects WHERE clauses and their parameters
+pub(crate) struct WhereBuilder {
+    clauses: Vec<String>,
+    pub(crate) params: Vec<String>,
+}
+
+impl WhereBuilder {
+    pub(crate) fn new() -> Self {
+        WhereBuilder { clauses: Vec::new(), params: Vec::new() }
+    }
+
+    /// Adds a clause, replacing each `{}` with the next numbered parameter
+    pub(crate) fn push(&mut self, clause: &str, values: &[&str]) {
+        let mut clause = clause.to_string();
+        for value in values {
+            self.params.push(value.to_string());
+            clause = clause.replacen("{}", &format!("?{}", self.params.len()), 1);
+        }
+        self.clauses.push(clause);
+    }
+
+    /// Adds a clause where every `{}` refers to the same parameter
+    pub(crate) fn push_repeated(&mut self, clause: &str, value: &str) {
+        self.params.push(value.to_string());
+        self.clauses.push(clause.replace("{}", &format!("?{}", self.params.len())));
+    }
+
+    pub(crate) fn sql(&self) -> String {
+        if self.clauses.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", self.clauses.join(" AND "))
+        }
+    }
+
+    pub(crate) fn push_date_range(&mut self, column: &str, filter: &RecordFilter) {
+        if let Some(from) = &filter.from_date {
+            self.push(&format!("substr({}, 1, 10) >= {{}}", column), &[from]);
+        }
+        if let Some(to) = &filter.to_date {
+            self.push(&format!("substr({}, 1, 10) <= {{}}", column), &[to]);
+        }
+    }
+}
+
+/// `%value%` pattern for a case-insensitive LIKE
+pub(crate) fn like_pattern(value: &str) -> String {
+    format!("%{}%", value.to_lowercase())
+}
+
+/// Turns search box text into an FTS5 query: every term must match, and the last
+/// word of each term may be a prefix (`smi` finds `Smith`). Returns `None` when the
+/// text has nothing to search for.
+pub fn fts_query(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .filter(|term| term.chars().any(char::is_alphanumeric))
+        .map(|term| format!("\"{}\"*", term.replace('"', "\"\"")))
+        .collect();
+
+    if terms.is_empty() { None } else { Some(terms.join(" ")) }
+}
+
+/// FROM, WHERE and ORDER BY parts of a filtered query
+pub(crate) struct FilterQuery {
+    pub(crate) from: String,
+    pub(crate) builder: WhereBuilder,
+    /// Unique row id, the tie-breaker for every sort
+    pub(crate) id: &'static str,
+    /// Sort used when the caller doesn't choose a column
+    pub(crate) default_key: String,
+    pub(crate) default_descending: bool,
+}
+
+impl FilterQuery {
+    pub(crate) fn sql(&self, columns: &str) -> String {
+        let direction = if self.default_descending { "DESC" } else { "ASC" };
+        format!(
+            "SELECT {} FROM {}{} ORDER BY {} {dir}, {} {dir}",
+            columns, self.from, self.builder.sql(), self.default_key, self.id, dir = direction
+        )
+    }
+}
+
+// Free text goes through the table's FTS index and ranks the results;
+// otherwise the default order applies.
+fn text_search_query(
+    from: &str,
+    fts_table: &str,
+    id: &'static str,
+    fts_key: &str,
+    default_order: (&str, bool),
+    text: Option<&String>,
+    mut builder: WhereBuilder,
+) -> FilterQuery {
+    match text.and_then(|text| fts_query(text)) {
+        Some(query) => {
+            builder.push(&format!("{} MATCH {{}}", fts_table), &[&query]);
+            FilterQuery {
+                from: format!("{} JOIN {} ON {}.rowid = {}", from, fts_table, fts_table, fts_key),
+                builder,
+                id,
+                default_key: format!("{}.rank", fts_table),
+                default_descending: false,
+            }
+        }
+        None => FilterQuery {
+            from: from.to_string(),
+            builder,
+            id,
+            default_key: default_order.0.to_string(),
+            default_descending: default_order.1,
+        },
+    }
+}
+
+// Every query is limited to the building the connection is working in
+fn current_property(column: &str) -> WhereBuilder {
+    let mut builder = WhereBuilder::new();
+    builder.push(&format!("{} = {}", column, CURRENT_PROPERTY), &[]);
+    builder
+}
+
+/// Residents query, aliased as `resident r`. Only the text filter applies.
+pub(crate) fn resident_query(filter: &RecordFilter) -> FilterQuery {
+    let mut builder = current_property("r.property_id");
+    builder.push("r.deleted_at IS NULL", &[]);
+    text_search_query(
+        "resident r", "resident_fts", "r.id", "r.id", ("r.id", false), filter.text.as_ref(), builder,
+    )
+}
+
+/// Cards query, aliased as `card c` and `resident r`. Text matches the card's resident.
+pub(crate) fn card_query(filter: &RecordFilter) -> FilterQuery {
+    let mut builder = current_property("r.property_id");
+    builder.push("c.deleted_at IS NULL", &[]);
+    text_search_query(
+        "card c JOIN resident r ON r.id = c.resident_id", "resident_fts", "c.id", "c.resident_id",
+        ("c.id", false), filter.text.as_ref(), builder,
+    )
+}
+
+/// Packages query, aliased as `package p` and `operator o` (who received it)
+pub(crate) fn package_query(filter: &RecordFilter) -> FilterQuery {
+    let mut builder = current_property("p.property_id");
+    builder.push("p.deleted_at IS NULL", &[]);
+    builder.push_date_range("p.date_time", filter);
+    if let Some(status) = &filter.status {
+        builder.push("LOWER(p.status) = LOWER({})", &[status]);
+    }
+    if let Some(apt) = &filter.apt {
+        builder.push("p.apt = {}", &[&normalize_apt(apt)]);
+    }
+    if let Some(carrier) = &filter.carrier {
+        builder.push("LOWER(p.carrier) = LOWER({})", &[carrier]);
+    }
+    if let Some(operator) = &filter.operator {
+        builder.push_repeated("(LOWER(o.display_name) LIKE {} OR LOWER(o.username) LIKE {})", &like_pattern(operator));
+    }
+
+    text_search_query(
+        "package p LEFT JOIN operator o ON o.id = p.received_by", "package_fts", "p.id", "p.id",
+        ("p.date_time", true), filter.text.as_ref(), builder,
+    )
+}
+
+/// Log query, aliased as `log l` and `operator o`
+pub(crate) fn log_query(filter: &RecordFilter) -> FilterQuery {
+    let mut builder = current_property("l.property_id");
+    builder.push_date_range("l.date_time", filter);
+    if let Some(status) = &filter.status {
+        builder.push("LOWER(l.action_type) = LOWER({})", &[status]);
+    }
+    if let Some(apt) = &filter.apt {
+        let apt = &normalize_apt(apt);
+        // Older entries only carry the apartment inside the action text
+        let patterns = apt_text_patterns(apt);
+        builder.push(
+            "(json_extract(l.payload, '$.apt') = {} OR (json_extract(l.payload, '$.apt') IS NULL
+              AND ((UPPER(l.action) || ' ') GLOB {} OR (UPPER(l.action) || ' ') GLOB {} OR (UPPER(l.action) || ' ') GLOB {})))",
+            &[apt, &patterns[0], &patterns[1], &patterns[2]],
+        );
+    }
+    if let Some(carrier) = &filter.carrier {
+        builder.push("LOWER(json_extract(l.payload, '$.carrier')) = LOWER({})", &[carrier]);
+    }
+    if let Some(operator) = &filter.operator {
+        builder.push_repeated("(LOWER(o.display_name) LIKE {} OR LOWER(o.username) LIKE {})", &like_pattern(operator));
+    }
+
+    // The index doesn't cover timestamps, so dates and times in the search box
+    // (`2025-01-03`, `14:30`) match the entry's time directly
+    let mut words = Vec::new();
+    for term in filter.text.iter().flat_map(|text| text.split_whitespace()) {
+        if is_date_or_time(term) {
+            builder.push("l.date_time LIKE {}", &[&like_pattern(term)]);
+        } else {
+            words.push(term);
+        }
+    }
+    let words = non_empty(&words.join(" "));
+
+    text_search_query(
+        "log l LEFT JOIN operator o ON o.id = l.operator_id", "log_fts", "l.id", "l.id",
+        ("l.date_time", true), words.as_ref(), builder,
+    )
+}
+
+// GLOB patterns for a whole apartment number in the upper-cased action text, written as
+// `Apt 101`, `Apt: 101` or `Apt=101`. The text gets a trailing space, so the number must be
+// followed by something other than a letter or digit: `10` doesn't match `Apt 101`.
+fn apt_text_patterns(apt: &str) -> [String; 3] {
+    let apt: String = apt.to_uppercase().chars()
+        .map(|c| match c {
+            '*' | '?' | '[' => format!("[{}]", c),
+            c => c.to_string(),
+        })
+        .collect();
+    ["APT ", "APT: ", "APT="].map(|prefix| format!("*{}{}[^0-9A-Z]*", prefix, apt))
+}
+
+// e.g. `2025-01`, `2025-01-03` or `14:30`; a bare number could just as well be an apartment
+fn is_date_or_time(term: &str) -> bool {
+    term.contains(['-', ':'])
+        && term.chars().any(|c| c.is_ascii_digit())
+        && term.chars().all(|c| c.is_ascii_digit() || c == '-' || c == ':')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn apt_filter(apt: &str) -> RecordFilter {
+        RecordFilter { apt: Some(apt.to_string()), ..Default::default() }
+    }
+
+    // Actions of the log entries the apartment filter finds
+    fn matching_actions(db: &rusqlite::Connection, apt: &str) -> Vec<String> {
+        let mut actions: Vec<String> = crate::filter_logs(db, &apt_filter(apt)).unwrap()
+            .into_iter()
+            .map(|entry| entry.action)
+            .collect();
+        actions.sort();
+        actions
+    }
+
+    #[test]
+    fn apt_filter_matches_whole_apartment_numbers_in_old_entries() {
+        let db = crate::open_db(":memory:").unwrap();
+        for action in ["Access granted: Ann (Apt: 10)", "Package for Apt 101", "Package for Apt 1010",
+                       "Card linked: Apt=10, Resident ID=3", "Package for apt 10b", "Package for Apt 10"] {
+            crate::add_log(&db, "test", action).unwrap();
+        }
+
+        assert_eq!(matching_actions(&db, "10"), vec![
+            "Access granted: Ann (Apt: 10)", "Card linked: Apt=10, Resident ID=3", "Package for Apt 10",
+        ]);
+        assert_eq!(matching_actions(&db, "10B"), vec!["Package for apt 10b"]);
+        assert_eq!(matching_actions(&db, "#0101"), vec!["Package for Apt 101"]);
+    }
+
+    #[test]
+    fn apt_filter_prefers_the_payload_over_the_text() {
+        let db = crate::open_db(":memory:").unwrap();
+        crate::add_log_with_payload(&db, "test", "Moved from Apt 10", json!({ "apt": "22" })).unwrap();
+        crate::add_log_with_payload(&db, "test", "Package received", json!({ "apt": "10" })).unwrap();
+
+        assert_eq!(matching_actions(&db, "10"), vec!["Package received"]);
+        assert_eq!(matching_actions(&db, "22"), vec!["Moved from Apt 10"]);
+    }
+
+    #[test]
+    fn apt_text_patterns_escape_glob_characters() {
+        assert_eq!(apt_text_patterns("1*")[0], "*APT 1[*][^0-9A-Z]*");
+        assert_eq!(apt_text_patterns("b?")[2], "*APT=B[?][^0-9A-Z]*");
+    }
+
+    #[test]
+    fn validate_rejects_bad_and_reversed_dates() {
+        let filter = RecordFilter::from_strings("2025-01-31", "2025-01-01", "", "");
+        assert!(filter.validate().is_err());
+        assert!(RecordFilter::from_strings("2025-13-01", "", "", "").validate().is_err());
+        assert!(RecordFilter::from_strings("2025-01-01", "2025-01-01", "", "").validate().is_ok());
+        assert!(RecordFilter::from_strings(" ", "", "", "").validate().is_ok());
+    }
+}
@@ -0,0 +1,658 @@
+use rusqlite::{Connection, Error};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::Local;
+
+use crate::app_error;
+use crate::operator::{Permission, require_permission};
+use crate::pdf::{PdfWriter, Style};
+use crate::property::{self, CURRENT_PROPERTY};
+
+/// Start of the code printed on internal package labels, followed by the zero-padded package id
+pub const LABEL_PREFIX: &str = "PKG-";
+
+// Print targets starting with this are sent to a raw printer socket instead of a folder
+const SOCKET_SCHEME: &str = "tcp://";
+
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Label stock for the PDF output: 4 x 2.5 inches, in points
+const PDF_LABEL_WIDTH: f32 = 288.0;
+const PDF_LABEL_HEIGHT: f32 = 180.0;
+const PDF_LABEL_MARGIN: f32 = 14.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelFormat {
+    /// Zebra and compatible thermal label printers
+    Zpl,
+    /// Receipt printers
+    EscPos,
+    Pdf,
+}
+
+impl LabelFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "zpl" => Some(LabelFormat::Zpl),
+            "escpos" | "esc/pos" => Some(LabelFormat::EscPos),
+            "pdf" => Some(LabelFormat::Pdf),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            LabelFormat::Zpl => "zpl",
+            LabelFormat::EscPos => "bin",
+            LabelFormat::Pdf => "pdf",
+        }
+    }
+}
+
+/// Barcode printed on package labels. PDF labels always use Code128,
+/// which is drawn directly; QR codes are left to the printer's own encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Symbology {
+    Code128,
+    Qr,
+}
+
+impl Symbology {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "code128" => Some(Symbology::Code128),
+            "qr" => Some(Symbology::Qr),
+            _ => None,
+        }
+    }
+}
+
+/// Where printed labels go
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrintTarget {
+    /// Files in a folder, e.g. one a print queue watches
+    Dir(PathBuf),
+    /// `host:port` of a printer accepting raw jobs, usually port 9100
+    Socket(String),
+}
+
+impl PrintTarget {
+    /// `tcp://host:port` for a printer socket, anything else is a folder
+    pub fn parse(target: &str) -> Self {
+        match target.trim().strip_prefix(SOCKET_SCHEME) {
+            Some(address) => PrintTarget::Socket(address.to_string()),
+            None => PrintTarget::Dir(PathBuf::from(target.trim())),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LabelSettings {
+    pub format: LabelFormat,
+    pub symbology: Symbology,
+    pub target: PrintTarget,
+    /// Also print a notice slip for each apartment's mailbox
+    pub notices: bool,
+}
+
+impl Default for LabelSettings {
+    fn default() -> Self {
+        LabelSettings {
+            format: LabelFormat::Pdf,
+            symbology: Symbology::Code128,
+            target: PrintTarget::Dir(PathBuf::from("labels")),
+            notices: false,
+        }
+    }
+}
+
+/// What goes on one package label
+#[derive(Debug, Clone)]
+pub struct PackageLabel {
+    pub package_id: u32,
+    pub package_number: String,
+    pub apt: String,
+    pub shelf: String,
+    /// Day received, `YYYY-MM-DD`
+    pub received: String,
+    pub carrier: String,
+    pub building: String,
+}
+
+impl PackageLabel {
+    pub fn code(&self) -> String {
+        label_code(self.package_id)
+    }
+
+    // Building and carrier, e.g. "Main Building - UPS"
+    fn origin(&self) -> String {
+        if self.carrier.is_empty() {
+            self.building.clone()
+        } else {
+            format!("{} - {}", self.building, self.carrier)
+        }
+    }
+}
+
+/// Mailbox slip telling an apartment that packages are waiting
+#[derive(Debug, Clone)]
+pub struct NoticeSlip {
+    pub apt: String,
+    pub building: String,
+    pub packages: usize,
+    /// Shelves holding the packages, in order, without repeats
+    pub shelves: Vec<String>,
+    pub received: String,
+}
+
+/// The code printed on a package's label, e.g. `PKG-000042`
+pub fn label_code(package_id: u32) -> String {
+    format!("{}{:06}", LABEL_PREFIX, package_id)
+}
+
+/// The package id in a scanned label code, if it is one
+pub fn parse_label_code(code: &str) -> Option<u32> {
+    let digits = code.trim().to_uppercase().strip_prefix(LABEL_PREFIX)?.to_string();
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// Position of the package a scanned code belongs to, given each package's id and
+/// carrier barcode. Both the internal label and the carrier's tracking number match.
+pub fn match_scan<'a>(code: &str, packages: impl IntoIterator<Item = (u32, &'a str)>) -> Option<usize> {
+    let code = code.trim();
+    if code.is_empty() {
+        return None;
+    }
+    let label_id = parse_label_code(code);
+    packages.into_iter().position(|(package_id, barcode)| {
+        label_id == Some(package_id) || barcode.trim().eq_ignore_ascii_case(code)
+    })
+}
+
+/// Records where a pending package of the current building was put. An empty shelf clears it.
+pub fn set_shelf(db: &Connection, package_id: u32, shelf: &str) -> Result<(), Error> {
+    require_permission(db, Permission::EditRecords)?;
+    let shelf = shelf.trim();
+    let changed = db.execute(
+        &format!(
+            "UPDATE package SET shelf = NULLIF(?1, '')
+             WHERE id = ?2 AND status = 'pending' AND deleted_at IS NULL AND property_id = {}",
+            CURRENT_PROPERTY
+        ),
+        rusqlite::params![shelf, package_id],
+    )?;
+    if changed == 0 {
+        return Err(app_error(format!("No pending package {} in this building", package_id)));
+    }
+    Ok(())
+}
+
+/// Labels for packages of the current building, in the order given
+pub fn package_labels(db: &Connection, package_ids: &[u32]) -> Result<Vec<PackageLabel>, Error> {
+    let building = property::current_property(db)?.name;
+    let mut stmt = db.prepare(&format!(
+        "SELECT id, package_number, apt, COALESCE(shelf, ''), date(date_time), COALESCE(carrier, '')
+         FROM package WHERE id = ?1 AND deleted_at IS NULL AND property_id = {}",
+        CURRENT_PROPERTY
+    ))?;
+
+    package_ids.iter()
+        .map(|&id| {
+            stmt.query_row([id], |row| Ok(PackageLabel {
+                package_id: row.get(0)?,
+                package_number: row.get(1)?,
+                apt: row.get(2)?,
+                shelf: row.get(3)?,
+                received: row.get(4)?,
+                carrier: row.get(5)?,
+                building: building.clone(),
+            }))
+            .map_err(|e| match e {
+                Error::QueryReturnedNoRows => app_error(format!("No package {} in this building", id)),
+                e => e,
+            })
+        })
+        .collect()
+}
+
+/// One notice slip per apartment, in apartment order
+pub fn notice_slips(labels: &[PackageLabel]) -> Vec<NoticeSlip> {
+    let mut by_apt: BTreeMap<&str, Vec<&PackageLabel>> = BTreeMap::new();
+    for label in labels {
+        by_apt.entry(label.apt.as_str()).or_default().push(label);
+    }
+
+    by_apt.into_iter()
+        .map(|(apt, labels)| {
+            let mut shelves: Vec<String> = Vec::new();
+            for label in &labels {
+                if !label.shelf.is_empty() && !shelves.contains(&label.shelf) {
+                    shelves.push(label.shelf.clone());
+                }
+            }
+            NoticeSlip {
+                apt: apt.to_string(),
+                building: labels[0].building.clone(),
+                packages: labels.len(),
+                shelves,
+                received: labels.iter().map(|label| label.received.as_str()).min().unwrap_or_default().to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Labels followed by notice slips as one print job
+pub fn render(labels: &[PackageLabel], notices: &[NoticeSlip], format: LabelFormat, symbology: Symbology) -> Vec<u8> {
+    match format {
+        LabelFormat::Zpl => {
+            let mut job = String::new();
+            for label in labels {
+                job.push_str(&zpl_label(label, symbology));
+            }
+            for notice in notices {
+                job.push_str(&zpl_notice(notice));
+            }
+            job.into_bytes()
+        }
+        LabelFormat::EscPos => {
+            let mut job = ESC_POS_INIT.to_vec();
+            for label in labels {
+                job.extend(escpos_label(label, symbology));
+            }
+            for notice in notices {
+                job.extend(escpos_notice(notice));
+            }
+            job
+        }
+        LabelFormat::Pdf => pdf_labels(labels, notices),
+    }
+}
+
+/// Sends a print job to the target and returns where it went
+pub fn print(job: &[u8], settings: &LabelSettings) -> Result<String, Box<dyn std::error::Error>> {
+    match &settings.target {
+        PrintTarget::Dir(dir) => {
+            let path = job_path(dir, settings.format);
+            fs::create_dir_all(dir)?;
+            fs::write(&path, job)?;
+            Ok(path.display().to_string())
+        }
+        PrintTarget::Socket(address) => {
+            let socket_address = address.to_socket_addrs()?
+                .next()
+                .ok_or_else(|| format!("Could not resolve printer address {}", address))?;
+            let mut stream = TcpStream::connect_timeout(&socket_address, SOCKET_TIMEOUT)
+                .map_err(|e| format!("Could not reach printer at {}: {}", address, e))?;
+            stream.set_write_timeout(Some(SOCKET_TIMEOUT))?;
+            stream.write_all(job)?;
+            stream.flush()?;
+            Ok(format!("printer at {}", address))
+        }
+    }
+}
+
+/// Labels, and notices if enabled, for the given packages, printed in one job
+pub fn print_labels(db: &Connection, package_ids: &[u32], settings: &LabelSettings) -> Result<String, Box<dyn std::error::Error>> {
+    let labels = package_labels(db, package_ids)?;
+    let notices = if settings.notices { notice_slips(&labels) } else { Vec::new() };
+    print(&render(&labels, &notices, settings.format, settings.symbology), settings)
+}
+
+// e.g. `labels/labels-20250101-093000-123.zpl`; milliseconds keep quick reprints apart
+fn job_path(dir: &Path, format: LabelFormat) -> PathBuf {
+    dir.join(format!("labels-{}.{}", Local::now().format("%Y%m%d-%H%M%S-%3f"), format.extension()))
+}
+
+fn notice_lines(notice: &NoticeSlip) -> Vec<String> {
+    let mut lines = vec![
+        format!("{} package{} waiting at the {} package room", notice.packages,
+                if notice.packages == 1 { "" } else { "s" }, notice.building),
+        format!("Waiting since {}", notice.received),
+    ];
+    if !notice.shelves.is_empty() {
+        lines.push(format!("Shelf {}", notice.shelves.join(", ")));
+    }
+    lines.push("Bring your resident card to collect.".to_string());
+    lines
+}
+
+// ZPL, for a 4 x 2 inch label at 203 dpi
+
+// ZPL reads ^ and ~ as commands anywhere in field data
+fn zpl_text(text: &str) -> String {
+    text.replace(['^', '~'], " ")
+}
+
+fn zpl_label(label: &PackageLabel, symbology: Symbology) -> String {
+    let details = [
+        (!label.shelf.is_empty()).then(|| format!("Shelf {}", label.shelf)),
+        Some(format!("#{}", label.package_number)),
+        Some(label.received.clone()),
+    ];
+    let details: Vec<String> = details.into_iter().flatten().collect();
+    let barcode = match symbology {
+        Symbology::Code128 => format!("^FO30,200^BY2^BCN,110,Y,N,N^FD{}^FS\n", label.code()),
+        Symbology::Qr => format!("^FO590,150^BQN,2,6^FDQA,{}^FS\n^FO30,330^A0N,28,28^FD{}^FS\n", label.code(), label.code()),
+    };
+    format!(
+        "^XA\n^CI28\n^PW812\n^LL406\n\
+         ^FO30,25^A0N,80,80^FDApt {}^FS\n\
+         ^FO30,115^A0N,34,34^FD{}^FS\n\
+         ^FO30,155^A0N,26,26^FD{}^FS\n\
+         {}^XZ\n",
+        zpl_text(&label.apt),
+        zpl_text(&details.join("  |  ")),
+        zpl_text(&label.origin()),
+        barcode,
+    )
+}
+
+fn zpl_notice(notice: &NoticeSlip) -> String {
+    let mut zpl = format!(
+        "^XA\n^CI28\n^PW812\n^LL406\n^FO30,25^A0N,40,40^FDPACKAGE NOTICE^FS\n^FO30,75^A0N,70,70^FDApt {}^FS\n",
+        zpl_text(&notice.apt)
+    );
+    for (i, line) in notice_lines(notice).iter().enumerate() {
+        zpl.push_str(&format!("^FO30,{}^A0N,28,28^FD{}^FS\n", 165 + 40 * i, zpl_text(line)));
+    }
+    zpl.push_str("^XZ\n");
+    zpl
+}
+
+// ESC/POS, for 80 mm receipt printers
+
+const ESC_POS_INIT: &[u8] = b"\x1b@";
+const ESC_POS_LARGE: &[u8] = b"\x1d!\x11";
+const ESC_POS_NORMAL: &[u8] = b"\x1d!\x00";
+// Feed four lines, then a partial cut
+const ESC_POS_CUT: &[u8] = b"\x1bd\x04\x1dVB\x00";
+
+// Receipt printers start in code page 437; anything outside ASCII becomes '?'
+fn escpos_text(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| if c.is_ascii() && !c.is_ascii_control() { c as u8 } else { b'?' })
+        .collect()
+}
+
+fn escpos_line(job: &mut Vec<u8>, text: &str) {
+    job.extend(escpos_text(text));
+    job.push(b'\n');
+}
+
+fn escpos_label(label: &PackageLabel, symbology: Symbology) -> Vec<u8> {
+    let mut job = ESC_POS_LARGE.to_vec();
+    escpos_line(&mut job, &format!("Apt {}", label.apt));
+    job.extend_from_slice(ESC_POS_NORMAL);
+    if !label.shelf.is_empty() {
+        escpos_line(&mut job, &format!("Shelf {}", label.shelf));
+    }
+    escpos_line(&mut job, &format!("Package #{}  {}", label.package_number, label.received));
+    escpos_line(&mut job, &label.origin());
+
+    let code = label.code();
+    match symbology {
+        Symbology::Code128 => {
+            // Height 80 dots, module width 2, human-readable text below, then GS k with code set B
+            job.extend_from_slice(b"\x1dhP\x1dw\x02\x1dH\x02");
+            job.extend_from_slice(&[0x1d, b'k', 73, code.len() as u8 + 2, b'{', b'B']);
+            job.extend_from_slice(code.as_bytes());
+        }
+        Symbology::Qr => {
+            // Model 2, module size 6, error correction M, store the data, print it
+            job.extend_from_slice(b"\x1d(k\x04\x001A2\x00\x1d(k\x03\x001C\x06\x1d(k\x03\x001E1");
+            let length = code.len() + 3;
+            job.extend_from_slice(&[0x1d, b'(', b'k', (length % 256) as u8, (length / 256) as u8, b'1', b'P', b'0']);
+            job.extend_from_slice(code.as_bytes());
+            job.extend_from_slice(b"\x1d(k\x03\x001Q0");
+            job.push(b'\n');
+            escpos_line(&mut job, &code);
+        }
+    }
+    job.push(b'\n');
+    job.extend_from_slice(ESC_POS_CUT);
+    job
+}
+
+fn escpos_notice(notice: &NoticeSlip) -> Vec<u8> {
+    let mut job = Vec::new();
+    escpos_line(&mut job, "PACKAGE NOTICE");
+    job.extend_from_slice(ESC_POS_LARGE);
+    escpos_line(&mut job, &format!("Apt {}", notice.apt));
+    job.extend_from_slice(ESC_POS_NORMAL);
+    for line in notice_lines(notice) {
+        escpos_line(&mut job, &line);
+    }
+    job.extend_from_slice(ESC_POS_CUT);
+    job
+}
+
+// PDF, one label per page
+
+fn pdf_labels(labels: &[PackageLabel], notices: &[NoticeSlip]) -> Vec<u8> {
+    let mut pdf = PdfWriter::with_page_size(PDF_LABEL_WIDTH, PDF_LABEL_HEIGHT, PDF_LABEL_MARGIN);
+    for (i, label) in labels.iter().enumerate() {
+        if i > 0 {
+            pdf.new_page();
+        }
+        pdf.line(Style::Title, &format!("Apt {}", label.apt));
+        let shelf = if label.shelf.is_empty() { String::new() } else { format!("Shelf {}   ", label.shelf) };
+        pdf.line(Style::Heading, &format!("{}#{}   {}", shelf, label.package_number, label.received));
+        pdf.line(Style::Text, &label.origin());
+        pdf.gap(6.0);
+        pdf.bars(&code128(&label.code()), 1.2, 40.0);
+        pdf.line(Style::Mono, &label.code());
+    }
+    for (i, notice) in notices.iter().enumerate() {
+        if i > 0 || !labels.is_empty() {
+            pdf.new_page();
+        }
+        pdf.line(Style::Heading, "PACKAGE NOTICE");
+        pdf.line(Style::Title, &format!("Apt {}", notice.apt));
+        for line in notice_lines(notice) {
+            pdf.line(Style::Text, &line);
+        }
+    }
+    pdf.finish()
+}
+
+// Code128 bar and space widths for each symbol value, 103-105 are the starts
+const CODE128_PATTERNS: [&str; 106] = [
+    "212222", "222122", "222221", "121223", "121322", "131222", "122213", "122312", "132212", "221213",
+    "221312", "231212", "112232", "122132", "122231", "113222", "123122", "123221", "223211", "221132",
+    "221231", "213212", "223112", "312131", "311222", "321122", "321221", "312212", "322112", "322211",
+    "212123", "212321", "232121", "111323", "131123", "131321", "112313", "132113", "132311", "211313",
+    "231113", "231311", "112133", "112331", "132131", "113123", "113321", "133121", "313121", "211331",
+    "231131", "213113", "213311", "213131", "311123", "311321", "331121", "312113", "312311", "332111",
+    "314111", "221411", "431111", "111224", "111422", "121124", "121421", "141122", "141221", "112214",
+    "112412", "122114", "122411", "142112", "142211", "241211", "221114", "413111", "241112", "134111",
+    "111242", "121142", "121241", "114212", "124112", "124211", "411212", "421112", "421211", "212141",
+    "214121", "412121", "111143", "111341", "131141", "114113", "114311", "411113", "411311", "113141",
+    "114131", "311141", "411131", "211412", "211214", "211232",
+];
+const CODE128_START_B: usize = 104;
+const CODE128_STOP: &str = "2331112";
+
+// Code set B widths for printable ASCII text, including the check symbol and stop
+fn code128(text: &str) -> Vec<u8> {
+    let values: Vec<usize> = text.chars()
+        .map(|c| if (' '..='~').contains(&c) { c as usize - 32 } else { '?' as usize - 32 })
+        .collect();
+    let checksum = values.iter().enumerate()
+        .fold(CODE128_START_B, |sum, (i, value)| sum + (i + 1) * value) % 103;
+
+    std::iter::once(CODE128_START_B)
+        .chain(values)
+        .chain([checksum])
+        .map(|value| CODE128_PATTERNS[value])
+        .chain([CODE128_STOP])
+        .flat_map(|pattern| pattern.bytes().map(|b| b - b'0'))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label(package_id: u32, apt: &str, shelf: &str, received: &str) -> PackageLabel {
+        PackageLabel {
+            package_id,
+            package_number: package_id.to_string(),
+            apt: apt.to_string(),
+            shelf: shelf.to_string(),
+            received: received.to_string(),
+            carrier: String::new(),
+            building: "Main Building".to_string(),
+        }
+    }
+
+    #[test]
+    fn label_codes_round_trip() {
+        assert_eq!(label_code(42), "PKG-000042");
+        assert_eq!(label_code(1234567), "PKG-1234567");
+        assert_eq!(parse_label_code(&label_code(42)), Some(42));
+        assert_eq!(parse_label_code(&label_code(1234567)), Some(1234567));
+    }
+
+    #[test]
+    fn label_codes_are_read_in_any_case_with_surrounding_whitespace() {
+        assert_eq!(parse_label_code(" pkg-000007\r\n"), Some(7));
+        assert_eq!(parse_label_code("Pkg-7"), Some(7));
+    }
+
+    #[test]
+    fn other_codes_are_not_label_codes() {
+        for code in ["", "PKG-", "PKG-12A", "PKG--12", "PKG-+12", "PKG-1 2", "ABC-000012", "000012", "1Z999AA10123456784", "PKG-99999999999"] {
+            assert_eq!(parse_label_code(code), None, "{}", code);
+        }
+    }
+
+    #[test]
+    fn scan_matches_a_box_by_its_label_code() {
+        let packages = [(7, "1Z999AA10123456784"), (42, "9400100000000000000000")];
+        assert_eq!(match_scan("PKG-000042", packages), Some(1));
+        assert_eq!(match_scan("pkg-42", packages), Some(1));
+        assert_eq!(match_scan("PKG-000043", packages), None);
+    }
+
+    #[test]
+    fn scan_matches_a_box_by_its_carrier_barcode() {
+        let packages = [(7, "1Z999AA10123456784"), (42, " 9400100000000000000000 ")];
+        assert_eq!(match_scan("1z999aa10123456784", packages), Some(0));
+        assert_eq!(match_scan(" 9400100000000000000000\n", packages), Some(1));
+        assert_eq!(match_scan("1Z999AA1012345678", packages), None);
+    }
+
+    #[test]
+    fn a_label_code_printed_as_another_box_barcode_matches_either() {
+        // The first package in order wins, whichever way it matched
+        let packages = [(3, "PKG-000009"), (9, "")];
+        assert_eq!(match_scan("PKG-000009", packages), Some(0));
+    }
+
+    #[test]
+    fn an_empty_scan_matches_nothing() {
+        let packages = [(7, ""), (8, "  ")];
+        assert_eq!(match_scan("", packages), None);
+        assert_eq!(match_scan("  \n", packages), None);
+    }
+
+    #[test]
+    fn scanning_the_same_box_again_finds_the_same_package() {
+        // The pickup screen relies on this to tell a repeat scan from a new box
+        let packages = [(7, "1Z999AA10123456784"), (42, "")];
+        assert_eq!(match_scan("PKG-000042", packages), match_scan(" pkg-000042 ", packages));
+        assert_eq!(match_scan("1Z999AA10123456784", packages), Some(0));
+        assert_eq!(match_scan("1Z999AA10123456784", packages), Some(0));
+    }
+
+    #[test]
+    fn a_box_for_another_resident_matches_nothing() {
+        let packages = [(7, "1Z999AA10123456784"), (42, "")];
+        assert_eq!(match_scan("PKG-000008", packages), None);
+        assert_eq!(match_scan("1Z999AA10123456785", packages), None);
+        assert_eq!(match_scan("PKG-000042", []), None);
+    }
+
+    #[test]
+    fn notice_slips_group_packages_by_apartment() {
+        let labels = [
+            label(1, "305", "B", "2025-03-04"),
+            label(2, "101", "", "2025-03-05"),
+            label(3, "305", "A", "2025-03-02"),
+            label(4, "305", "B", "2025-03-03"),
+        ];
+        let slips = notice_slips(&labels);
+
+        assert_eq!(slips.iter().map(|slip| slip.apt.as_str()).collect::<Vec<_>>(), ["101", "305"]);
+        assert_eq!(slips[0].packages, 1);
+        assert!(slips[0].shelves.is_empty());
+        assert_eq!(slips[0].received, "2025-03-05");
+        assert_eq!(slips[1].packages, 3);
+        assert_eq!(slips[1].shelves, ["B", "A"]);
+        assert_eq!(slips[1].received, "2025-03-02");
+        assert_eq!(slips[1].building, "Main Building");
+        assert!(notice_slips(&[]).is_empty());
+    }
+
+    #[test]
+    fn zpl_labels_are_framed_and_field_data_cannot_start_commands() {
+        let mut package = label(42, "2^B", "C~1", "2025-03-04");
+        package.carrier = "UPS".to_string();
+        let zpl = String::from_utf8(render(&[package], &[], LabelFormat::Zpl, Symbology::Code128)).unwrap();
+
+        assert!(zpl.starts_with("^XA\n"));
+        assert!(zpl.ends_with("^XZ\n"));
+        assert!(zpl.contains("^FDApt 2 B^FS"));
+        assert!(zpl.contains("^FDShelf C 1  |  #42  |  2025-03-04^FS"));
+        assert!(zpl.contains("^FDMain Building - UPS^FS"));
+        assert!(zpl.contains("^BCN,110,Y,N,N^FDPKG-000042^FS"));
+    }
+
+    #[test]
+    fn zpl_jobs_hold_one_block_per_label_and_notice() {
+        let labels = [label(1, "101", "", "2025-03-04"), label(2, "102", "", "2025-03-04")];
+        let notices = notice_slips(&labels);
+        let zpl = String::from_utf8(render(&labels, &notices, LabelFormat::Zpl, Symbology::Qr)).unwrap();
+
+        assert_eq!(zpl.matches("^XA").count(), 4);
+        assert_eq!(zpl.matches("^XZ").count(), 4);
+        assert!(zpl.contains("^BQN,2,6^FDQA,PKG-000001^FS"));
+        assert!(zpl.contains("^FD1 package waiting at the Main Building package room^FS"));
+    }
+
+    #[test]
+    fn escpos_code128_carries_the_code_length() {
+        let job = render(&[label(42, "101", "", "2025-03-04")], &[], LabelFormat::EscPos, Symbology::Code128);
+        let command = [0x1d, b'k', 73, 12, b'{', b'B'];
+        let start = job.windows(command.len()).position(|window| window == command).unwrap();
+
+        assert!(job.starts_with(ESC_POS_INIT));
+        assert_eq!(&job[start + command.len()..start + command.len() + 10], b"PKG-000042");
+        assert!(job.ends_with(ESC_POS_CUT));
+    }
+
+    #[test]
+    fn escpos_text_replaces_anything_outside_printable_ascii() {
+        assert_eq!(escpos_text("Apt 3é\t~"), b"Apt 3??~");
+    }
+
+    #[test]
+    fn code128_adds_start_checksum_and_stop() {
+        let modules = code128("PKG-000042");
+        // Start, ten symbols and the checksum are 11 modules each, the stop is 13
+        assert_eq!(modules.iter().map(|&width| width as usize).sum::<usize>(), 11 * 12 + 13);
+        assert_eq!(&modules[..6], [2, 1, 1, 2, 1, 4]);
+        assert_eq!(&modules[modules.len() - 7..], [2, 3, 3, 1, 1, 1, 2]);
+
+        // "AB": (104 + 1 * 33 + 2 * 34) % 103 = 102
+        let modules = code128("AB");
+        assert_eq!(&modules[18..24], [4, 1, 1, 1, 3, 1]);
+    }
+}
@@ -1,13 +1,49 @@
 use rusqlite::{Connection, Error};
-use std::{rc::Rc};
+use std::{rc::Rc, path::Path};
 use slint::{VecModel, StandardListViewItem, ModelRc};
 
+use filter::RecordFilter;
+use operator::{Permission, require_permission};
+use signature::SignaturePolicy;
+
+pub mod audit;
+pub mod backup;
+pub mod config;
+pub mod encryption;
+pub mod export;
+pub mod filter;
+pub mod label;
+pub mod operator;
+mod pdf;
+pub mod photo;
+pub mod privacy;
+pub mod property;
+pub mod report;
+pub mod scanner;
+pub mod signature;
+pub mod stats;
+pub mod table_model;
+pub mod tenancy;
+pub mod trash;
+pub mod unit;
+
+/// Default location of the database file
+pub const DB_PATH: &str = "package_room.db";
+
+/// Schema version kept in `PRAGMA user_version`.
+/// Bump together with a new step in `migrate`.
+pub const SCHEMA_VERSION: i32 = 17;
+
 pub struct ResidentData{
     pub id: u32,
     pub apt: String,
     pub first_name: String,
     pub last_name: String,
     pub linked: bool,
+    /// `active`, or `moved_out` once the resident has left
+    pub status: String,
+    pub moved_in: Option<String>,
+    pub moved_out: Option<String>,
 }
 
 pub struct CardData{
@@ -16,6 +52,8 @@ pub struct CardData{
     pub apt: String,
     pub added_date: String,
     pub hash: String,
+    pub status: String,
+    pub revoked_date: Option<String>,
 }
 
 pub struct LogData{
@@ -23,6 +61,8 @@ pub struct LogData{
     pub action_type: String,
     pub action: String,
     pub date_time: String,
+    pub operator: String,
+    pub payload: String,
 }
 
 pub struct PackageData {
@@ -33,17 +73,36 @@ pub struct PackageData {
     pub comment: Option<String>,  // NEW: For additional notes
     pub date_time: String,
     pub status: String,
+    pub carrier: Option<String>,
 }
 
 pub fn connect_to_db()->Connection{
-    let db = Connection::open("package_room.db").expect("Cant connect to database");
+    open_db(DB_PATH).expect("Cant connect to database")
+}
+
+pub fn open_db<P: AsRef<Path>>(path: P) -> Result<Connection, Error> {
+    let db = encryption::open(path.as_ref())?;
     // Enable foreign keys
-    db.execute("PRAGMA foreign_keys = ON", []).expect("Failed to enable foreign keys");
-    create_tables(&db);
-    db
+    db.execute("PRAGMA foreign_keys = ON", [])?;
+    create_tables(&db)?;
+    migrate(&db)?;
+    operator::init_session(&db)?;
+    property::init_session(&db)?;
+    Ok(db)
+}
+
+/// Builds the error used for application-level failures (wrong apartment, bad input, ...)
+pub(crate) fn app_error(message: impl Into<String>) -> Error {
+    Error::SqliteFailure(rusqlite::ffi::Error::new(1), Some(message.into()))
 }
 
-fn create_tables(db: &Connection) {
+pub fn get_schema_version(db: &Connection) -> Result<i32, Error> {
+    db.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+// Tables as they were in the first release. Later changes go into `migrate`
+// so existing databases and new ones end up with the same schema.
+fn create_tables(db: &Connection) -> Result<(), Error> {
     db.execute_batch("
         CREATE TABLE IF NOT EXISTS resident (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -87,12 +146,360 @@ fn create_tables(db: &Connection) {
         CREATE INDEX IF NOT EXISTS idx_package_apt ON package(apt);
         CREATE INDEX IF NOT EXISTS idx_package_status ON package(status);
         CREATE INDEX IF NOT EXISTS idx_package_barcode ON package(barcode);
-    ").expect("Failed to create tables");
+    ")
+}
+
+// External-content FTS5 tables over resident names, package barcodes/comments and log actions.
+// The log is append-only, so it only needs an insert trigger.
+const FTS_SCHEMA: &str = "
+    CREATE VIRTUAL TABLE IF NOT EXISTS resident_fts USING fts5(
+        apt, first_name, last_name,
+        content='resident', content_rowid='id', tokenize='unicode61 remove_diacritics 2'
+    );
+    CREATE TRIGGER IF NOT EXISTS resident_fts_insert AFTER INSERT ON resident BEGIN
+        INSERT INTO resident_fts(rowid, apt, first_name, last_name)
+        VALUES (new.id, new.apt, new.first_name, new.last_name);
+    END;
+    CREATE TRIGGER IF NOT EXISTS resident_fts_delete AFTER DELETE ON resident BEGIN
+        INSERT INTO resident_fts(resident_fts, rowid, apt, first_name, last_name)
+        VALUES ('delete', old.id, old.apt, old.first_name, old.last_name);
+    END;
+    CREATE TRIGGER IF NOT EXISTS resident_fts_update AFTER UPDATE OF apt, first_name, last_name ON resident BEGIN
+        INSERT INTO resident_fts(resident_fts, rowid, apt, first_name, last_name)
+        VALUES ('delete', old.id, old.apt, old.first_name, old.last_name);
+        INSERT INTO resident_fts(rowid, apt, first_name, last_name)
+        VALUES (new.id, new.apt, new.first_name, new.last_name);
+    END;
+
+    CREATE VIRTUAL TABLE IF NOT EXISTS package_fts USING fts5(
+        apt, package_number, barcode, comment, carrier,
+        content='package', content_rowid='id', tokenize='unicode61 remove_diacritics 2'
+    );
+    CREATE TRIGGER IF NOT EXISTS package_fts_insert AFTER INSERT ON package BEGIN
+        INSERT INTO package_fts(rowid, apt, package_number, barcode, comment, carrier)
+        VALUES (new.id, new.apt, new.package_number, new.barcode, new.comment, new.carrier);
+    END;
+    CREATE TRIGGER IF NOT EXISTS package_fts_delete AFTER DELETE ON package BEGIN
+        INSERT INTO package_fts(package_fts, rowid, apt, package_number, barcode, comment, carrier)
+        VALUES ('delete', old.id, old.apt, old.package_number, old.barcode, old.comment, old.carrier);
+    END;
+    CREATE TRIGGER IF NOT EXISTS package_fts_update
+    AFTER UPDATE OF apt, package_number, barcode, comment, carrier ON package BEGIN
+        INSERT INTO package_fts(package_fts, rowid, apt, package_number, barcode, comment, carrier)
+        VALUES ('delete', old.id, old.apt, old.package_number, old.barcode, old.comment, old.carrier);
+        INSERT INTO package_fts(rowid, apt, package_number, barcode, comment, carrier)
+        VALUES (new.id, new.apt, new.package_number, new.barcode, new.comment, new.carrier);
+    END;
+
+    CREATE VIRTUAL TABLE IF NOT EXISTS log_fts USING fts5(
+        action_type, action,
+        content='log', content_rowid='id', tokenize='unicode61 remove_diacritics 2'
+    );
+    CREATE TRIGGER IF NOT EXISTS log_fts_insert AFTER INSERT ON log BEGIN
+        INSERT INTO log_fts(rowid, action_type, action) VALUES (new.id, new.action_type, new.action);
+    END;
+";
+
+fn migrate(db: &Connection) -> Result<(), Error> {
+    let version = get_schema_version(db)?;
+    if version > SCHEMA_VERSION {
+        return Err(app_error(format!(
+            "Database schema version {} is newer than this app supports ({})", version, SCHEMA_VERSION
+        )));
+    }
+
+    if version < 1 {
+        // Version 1 is the original schema from create_tables
+        db.pragma_update(None, "user_version", 1)?;
+    }
+
+    if version < 2 {
+        // Staff accounts; log entries record who was logged in
+        let tx = db.unchecked_transaction()?;
+        tx.execute_batch("
+            CREATE TABLE IF NOT EXISTS operator (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                username TEXT NOT NULL UNIQUE COLLATE NOCASE,
+                display_name TEXT NOT NULL,
+                password_hash TEXT NOT NULL,
+                card_hash TEXT UNIQUE,
+                active BOOLEAN DEFAULT 1,
+                created_date TEXT NOT NULL
+            );
+
+            ALTER TABLE log ADD COLUMN operator_id INTEGER REFERENCES operator(id);
+            CREATE INDEX IF NOT EXISTS idx_log_operator ON log(operator_id);
+        ")?;
+        tx.pragma_update(None, "user_version", 2)?;
+        tx.commit()?;
+    }
+
+    if version < 3 {
+        // Operator roles (the oldest account becomes admin) and card revocation
+        let tx = db.unchecked_transaction()?;
+        tx.execute_batch("
+            ALTER TABLE operator ADD COLUMN role TEXT NOT NULL DEFAULT 'concierge';
+            UPDATE operator SET role = 'admin' WHERE id = (SELECT MIN(id) FROM operator);
+
+            ALTER TABLE card ADD COLUMN status TEXT NOT NULL DEFAULT 'active';
+            ALTER TABLE card ADD COLUMN revoked_date TEXT;
+        ")?;
+        tx.pragma_update(None, "user_version", 3)?;
+        tx.commit()?;
+    }
+
+    if version < 4 {
+        // Hash-chained, append-only audit log
+        let tx = db.unchecked_transaction()?;
+        tx.execute_batch("
+            ALTER TABLE log ADD COLUMN payload TEXT;
+            ALTER TABLE log ADD COLUMN prev_hash TEXT;
+            ALTER TABLE log ADD COLUMN hash TEXT;
+        ")?;
+        audit::backfill_chain(&tx)?;
+        tx.execute_batch(audit::LOG_TRIGGERS)?;
+        tx.pragma_update(None, "user_version", 4)?;
+        tx.commit()?;
+    }
+
+    if version < 5 {
+        // Carrier and receiving operator on packages, for filtering
+        let tx = db.unchecked_transaction()?;
+        tx.execute_batch("
+            ALTER TABLE package ADD COLUMN carrier TEXT;
+            ALTER TABLE package ADD COLUMN received_by INTEGER REFERENCES operator(id);
+            CREATE INDEX IF NOT EXISTS idx_package_date ON package(date_time);
+        ")?;
+        tx.pragma_update(None, "user_version", 5)?;
+        tx.commit()?;
+    }
+
+    if version < 6 {
+        // Full-text indexes, kept in sync with their tables by triggers
+        let tx = db.unchecked_transaction()?;
+        tx.execute_batch(FTS_SCHEMA)?;
+        tx.execute_batch("
+            INSERT INTO resident_fts(resident_fts) VALUES ('rebuild');
+            INSERT INTO package_fts(package_fts) VALUES ('rebuild');
+            INSERT INTO log_fts(log_fts) VALUES ('rebuild');
+        ")?;
+        tx.pragma_update(None, "user_version", 6)?;
+        tx.commit()?;
+    }
+
+    if version < 7 {
+        // Per-resident history: who collected each package, and log entries by resident
+        let tx = db.unchecked_transaction()?;
+        tx.execute_batch("
+            UPDATE package SET collected_by = c.resident_id
+            FROM (
+                SELECT json_extract(payload, '$.package_id') AS package_id,
+                       json_extract(payload, '$.resident_id') AS resident_id,
+                       MAX(id)
+                FROM log WHERE action_type = 'package_out'
+                GROUP BY 1
+            ) c
+            WHERE c.package_id = package.id AND package.collected_by IS NULL
+              AND c.resident_id IN (SELECT id FROM resident);
+
+            CREATE INDEX IF NOT EXISTS idx_package_collected_by ON package(collected_by);
+            CREATE INDEX IF NOT EXISTS idx_log_resident ON log(json_extract(payload, '$.resident_id'));
+        ")?;
+        tx.pragma_update(None, "user_version", 7)?;
+        tx.commit()?;
+    }
+
+    if version < 8 {
+        // Resident, package and card ids on log entries, read from the hashed payload
+        let tx = db.unchecked_transaction()?;
+        tx.execute_batch(audit::LOG_ENTITY_COLUMNS)?;
+        tx.pragma_update(None, "user_version", 8)?;
+        tx.commit()?;
+    }
+
+    if version < 9 {
+        // Apartment registry; existing apartments are normalised and registered
+        let tx = db.unchecked_transaction()?;
+        tx.execute_batch("
+            CREATE TABLE IF NOT EXISTS unit (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                building TEXT NOT NULL DEFAULT '',
+                number TEXT NOT NULL UNIQUE,
+                floor INTEGER,
+                status TEXT NOT NULL DEFAULT 'active'
+            );
+
+            ALTER TABLE resident ADD COLUMN unit_id INTEGER REFERENCES unit(id);
+            ALTER TABLE package ADD COLUMN unit_id INTEGER REFERENCES unit(id);
+            CREATE INDEX IF NOT EXISTS idx_resident_unit ON resident(unit_id);
+            CREATE INDEX IF NOT EXISTS idx_package_unit ON package(unit_id);
+        ")?;
+        unit::register_existing_apartments(&tx)?;
+        tx.pragma_update(None, "user_version", 9)?;
+        tx.commit()?;
+    }
+
+    if version < 10 {
+        // Buildings. Everything so far belongs to the first one. Apartment numbers
+        // become unique per building, which needs the unit table rebuilt; foreign
+        // keys are off meanwhile so residents and packages keep their unit ids.
+        db.pragma_update(None, "foreign_keys", false)?;
+        // Foreign keys come back on whether or not the rebuild went through
+        let rebuilt = (|| -> Result<(), Error> {
+            let tx = db.unchecked_transaction()?;
+            tx.execute_batch("
+                CREATE TABLE IF NOT EXISTS property (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+                    address TEXT NOT NULL DEFAULT '',
+                    status TEXT NOT NULL DEFAULT 'active'
+                );
+                INSERT INTO property (id, name) VALUES (1, 'Main building');
+
+                -- Readers in property_id also accept resident cards from accepts_property_id
+                CREATE TABLE IF NOT EXISTS property_card_access (
+                    property_id INTEGER NOT NULL REFERENCES property(id),
+                    accepts_property_id INTEGER NOT NULL REFERENCES property(id),
+                    PRIMARY KEY (property_id, accepts_property_id)
+                );
+
+                CREATE TABLE unit_new (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    property_id INTEGER NOT NULL DEFAULT 1 REFERENCES property(id),
+                    building TEXT NOT NULL DEFAULT '',
+                    number TEXT NOT NULL,
+                    floor INTEGER,
+                    status TEXT NOT NULL DEFAULT 'active',
+                    UNIQUE (property_id, number)
+                );
+                INSERT INTO unit_new (id, building, number, floor, status)
+                SELECT id, building, number, floor, status FROM unit;
+                DROP TABLE unit;
+                ALTER TABLE unit_new RENAME TO unit;
+
+                ALTER TABLE resident ADD COLUMN property_id INTEGER REFERENCES property(id);
+                ALTER TABLE package ADD COLUMN property_id INTEGER REFERENCES property(id);
+                -- NULL: the operator works in every building
+                ALTER TABLE operator ADD COLUMN property_id INTEGER REFERENCES property(id);
+                UPDATE resident SET property_id = 1;
+                UPDATE package SET property_id = 1;
+                CREATE INDEX IF NOT EXISTS idx_resident_property ON resident(property_id);
+                CREATE INDEX IF NOT EXISTS idx_package_property ON package(property_id, status);
+            ")?;
+            tx.execute_batch(audit::LOG_PROPERTY_COLUMN)?;
+            tx.pragma_update(None, "user_version", 10)?;
+            tx.commit()
+        })();
+        db.pragma_update(None, "foreign_keys", true)?;
+        rebuilt?;
+    }
+
+    if version < 11 {
+        // Move-out keeps the resident row for the history instead of deleting it.
+        // Returned packages get their own status.
+        let tx = db.unchecked_transaction()?;
+        tx.execute_batch("
+            ALTER TABLE resident ADD COLUMN status TEXT NOT NULL DEFAULT 'active';
+            ALTER TABLE resident ADD COLUMN moved_in TEXT;
+            ALTER TABLE resident ADD COLUMN moved_out TEXT;
+            CREATE INDEX IF NOT EXISTS idx_resident_unit_status ON resident(unit_id, status);
+        ")?;
+        tx.pragma_update(None, "user_version", 11)?;
+        tx.commit()?;
+    }
+
+    if version < 12 {
+        // Soft delete: removed rows stay, hidden from every list, until undone or purged
+        let tx = db.unchecked_transaction()?;
+        tx.execute_batch("
+            ALTER TABLE resident ADD COLUMN deleted_at TEXT;
+            ALTER TABLE resident ADD COLUMN deleted_by INTEGER REFERENCES operator(id);
+            ALTER TABLE card ADD COLUMN deleted_at TEXT;
+            ALTER TABLE card ADD COLUMN deleted_by INTEGER REFERENCES operator(id);
+            ALTER TABLE package ADD COLUMN deleted_at TEXT;
+            ALTER TABLE package ADD COLUMN deleted_by INTEGER REFERENCES operator(id);
+            CREATE INDEX IF NOT EXISTS idx_resident_deleted ON resident(deleted_at) WHERE deleted_at IS NOT NULL;
+            CREATE INDEX IF NOT EXISTS idx_card_deleted ON card(deleted_at) WHERE deleted_at IS NOT NULL;
+            CREATE INDEX IF NOT EXISTS idx_package_deleted ON package(deleted_at) WHERE deleted_at IS NOT NULL;
+        ")?;
+        tx.pragma_update(None, "user_version", 12)?;
+        tx.commit()?;
+    }
+
+    if version < 13 {
+        // Erasure and log retention redact personal data in place
+        let tx = db.unchecked_transaction()?;
+        tx.execute_batch("ALTER TABLE resident ADD COLUMN erased_at TEXT;")?;
+        tx.execute_batch(audit::LOG_REDACTION)?;
+        tx.pragma_update(None, "user_version", 13)?;
+        tx.commit()?;
+    }
+
+    if version < 14 {
+        // Where a package was put, printed on its label
+        let tx = db.unchecked_transaction()?;
+        tx.execute_batch("ALTER TABLE package ADD COLUMN shelf TEXT;")?;
+        tx.pragma_update(None, "user_version", 14)?;
+        tx.commit()?;
+    }
+
+    if version < 15 {
+        // Condition records: damage flag and photos taken at intake or collection
+        let tx = db.unchecked_transaction()?;
+        tx.execute_batch("
+            ALTER TABLE package ADD COLUMN damaged INTEGER NOT NULL DEFAULT 0;
+            CREATE TABLE IF NOT EXISTS package_photo (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                package_id INTEGER NOT NULL REFERENCES package(id) ON DELETE CASCADE,
+                hash TEXT NOT NULL,
+                file TEXT NOT NULL,
+                stage TEXT NOT NULL,
+                original_name TEXT NOT NULL,
+                added_at TEXT NOT NULL,
+                added_by INTEGER REFERENCES operator(id),
+                UNIQUE (package_id, hash)
+            );
+            CREATE INDEX IF NOT EXISTS idx_package_photo_package ON package_photo(package_id);
+        ")?;
+        tx.pragma_update(None, "user_version", 15)?;
+        tx.commit()?;
+    }
+
+    if version < 16 {
+        // Signatures given at handover, one per collection covering all its packages
+        let tx = db.unchecked_transaction()?;
+        tx.execute_batch("
+            CREATE TABLE IF NOT EXISTS signature (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                strokes TEXT NOT NULL,
+                png BLOB NOT NULL,
+                signed_at TEXT NOT NULL,
+                property_id INTEGER REFERENCES property(id)
+            );
+            ALTER TABLE package ADD COLUMN signature_id INTEGER REFERENCES signature(id);
+        ")?;
+        tx.pragma_update(None, "user_version", 16)?;
+        tx.commit()?;
+    }
+
+    if version < 17 {
+        // Redaction may only blank an entry, not rewrite it
+        let tx = db.unchecked_transaction()?;
+        tx.execute_batch(audit::LOG_REDACTION_RULES)?;
+        tx.pragma_update(None, "user_version", 17)?;
+        tx.commit()?;
+    }
+
+    Ok(())
 }
 
 //Resident functions
 pub fn get_residents_data(db: &Connection) -> Result<Vec<ResidentData>, Error> {
-    let mut query = db.prepare("SELECT * FROM resident")?;
+    let mut query = db.prepare(&format!(
+        "SELECT id, apt, first_name, last_name, linked, status, moved_in, moved_out FROM resident
+         WHERE property_id = {} AND deleted_at IS NULL",
+        property::CURRENT_PROPERTY
+    ))?;
 
     let query_map = query.query_map([], |row| {
         Ok(ResidentData {
@@ -101,6 +508,9 @@ pub fn get_residents_data(db: &Connection) -> Result<Vec<ResidentData>, Error> {
             first_name: row.get(2)?,
             last_name: row.get(3)?,
             linked: row.get(4)?,
+            status: row.get(5)?,
+            moved_in: row.get(6)?,
+            moved_out: row.get(7)?,
         })
     })?;
 
@@ -119,6 +529,7 @@ pub fn convert_resident_data_vec(row_data: Vec<ResidentData>)-> (ModelRc<ModelRc
             StandardListViewItem::from(Into::<slint::SharedString>::into(resident.first_name)),
             StandardListViewItem::from(Into::<slint::SharedString>::into(resident.last_name)),
             StandardListViewItem::from(Into::<slint::SharedString>::into(resident.linked.to_string())),
+            StandardListViewItem::from(Into::<slint::SharedString>::into(resident.status)),
         ];
         let inner_model = Rc::new(VecModel::from(inner_vec));
         ModelRc::new(inner_model)
@@ -131,7 +542,7 @@ pub fn convert_resident_data_vec(row_data: Vec<ResidentData>)-> (ModelRc<ModelRc
 
 pub fn get_resident_info(db: &Connection, index: u32) -> Result<ResidentData, Error> {
     let resident = db.query_row(
-        "SELECT id, apt, first_name, last_name, linked FROM resident WHERE id = ?1",
+        "SELECT id, apt, first_name, last_name, linked, status, moved_in, moved_out FROM resident WHERE id = ?1",
         [index],
         |row| {
             Ok(ResidentData {
@@ -140,6 +551,9 @@ pub fn get_resident_info(db: &Connection, index: u32) -> Result<ResidentData, Er
                 first_name: row.get(2)?,
                 last_name: row.get(3)?,
                 linked: row.get(4)?,
+                status: row.get(5)?,
+                moved_in: row.get(6)?,
+                moved_out: row.get(7)?,
             })
         },
     )?;
@@ -148,7 +562,7 @@ pub fn get_resident_info(db: &Connection, index: u32) -> Result<ResidentData, Er
 
 pub fn get_card_info(db: &Connection, index: u32) -> Result<CardData, Error> {
     let card = db.query_row(
-        "SELECT id, resident_id, apt, added_date, hash FROM card WHERE id = ?1",
+        "SELECT id, resident_id, apt, added_date, hash, status, revoked_date FROM card WHERE id = ?1",
         [index],
         |row| {
             Ok(CardData {
@@ -157,6 +571,8 @@ pub fn get_card_info(db: &Connection, index: u32) -> Result<CardData, Error> {
                 apt: row.get(2)?,
                 added_date: row.get(3)?,
                 hash: row.get(4)?,
+                status: row.get(5)?,
+                revoked_date: row.get(6)?,
             })
         },
     )?;
@@ -165,7 +581,9 @@ pub fn get_card_info(db: &Connection, index: u32) -> Result<CardData, Error> {
 
 pub fn get_log_info(db: &Connection, index: u32) -> Result<LogData, Error> {
     let log = db.query_row(
-        "SELECT id, action_type, action, date_time FROM log WHERE id = ?1",
+        "SELECT l.id, l.action_type, l.action, l.date_time, COALESCE(o.display_name, ''), COALESCE(l.payload, '')
+         FROM log l LEFT JOIN operator o ON o.id = l.operator_id
+         WHERE l.id = ?1",
         [index],
         |row| {
             Ok(LogData {
@@ -173,33 +591,75 @@ pub fn get_log_info(db: &Connection, index: u32) -> Result<LogData, Error> {
                 action_type: row.get(1)?,
                 action: row.get(2)?,
                 date_time: row.get(3)?,
+                operator: row.get(4)?,
+                payload: row.get(5)?,
             })
         },
     )?;
     Ok(log)
 }
 
+pub fn add_resident(db: &Connection, apt: &str, first_name: &str, last_name: &str) -> Result<u32, Error> {
+    use chrono::Local;
+    require_permission(db, Permission::EditRecords)?;
+    let unit = unit::resolve_unit(db, apt)?;
+    let apt = unit.number.as_str();
+
+    let moved_in = Local::now().format("%Y-%m-%d").to_string();
+    db.execute(
+        "INSERT INTO resident (apt, first_name, last_name, linked, unit_id, property_id, moved_in)
+         VALUES (?1, ?2, ?3, 0, ?4, ?5, ?6)",
+        rusqlite::params![apt, first_name, last_name, unit.id, unit.property_id, moved_in],
+    )?;
+    let id = db.last_insert_rowid() as u32;
+
+    let log_action = format!("Resident {} {} (ID: {}, Apt: {}) was added", first_name, last_name, id, apt);
+    add_log_with_payload(db, "add", &log_action, serde_json::json!({ "resident_id": id, "apt": apt }))?;
+    Ok(id)
+}
+
+/// Removes a resident and their cards. The rows are only marked deleted, so the
+/// removal can be undone (see `trash`) until a purge deletes them for good.
 pub fn delete_resident(db: &Connection, id: u32) -> Result<(), Error> {
-    // Log the deletion
+    use chrono::Local;
+    let operator = require_permission(db, Permission::DeleteResident)?;
     let resident = get_resident_info(db, id)?;
+
+    let deleted_at = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let tx = db.unchecked_transaction()?;
+    let removed = tx.execute(
+        "UPDATE resident SET deleted_at = ?1, deleted_by = ?2 WHERE id = ?3 AND deleted_at IS NULL",
+        rusqlite::params![deleted_at, operator.id, id],
+    )?;
+    if removed == 0 {
+        return Err(app_error(format!("{} {} was already removed", resident.first_name, resident.last_name)));
+    }
+    // Cards go with the resident and come back with them on undo
+    tx.execute(
+        "UPDATE card SET deleted_at = ?1, deleted_by = ?2 WHERE resident_id = ?3 AND deleted_at IS NULL",
+        rusqlite::params![deleted_at, operator.id, id],
+    )?;
+
+    // Log the deletion
     let log_action = format!("Resident {} {} (ID: {}, Apt: {}) was removed", 
         resident.first_name, resident.last_name, id, resident.apt);
-    add_log(db, "remove", &log_action)?;
-
-    // Delete resident (cards will be deleted automatically due to CASCADE)
-    db.execute("DELETE FROM resident WHERE id = ?1", [id])?;
-    Ok(())
+    add_log_with_payload(&tx, "remove", &log_action, serde_json::json!({ "resident_id": id, "apt": resident.apt }))?;
+    tx.commit()
 }
 
+/// Residents matching every term by apartment or name, best matches first
 pub fn search_residents(db: &Connection, search_query: &str) -> Result<Vec<ResidentData>, Error> {
-
-    let query = format!("%{}%", search_query.to_lowercase());
-    let mut stmt = db.prepare(
-        "SELECT id, apt, first_name, last_name, linked FROM resident 
-         WHERE LOWER(apt) LIKE ?1 
-         OR LOWER(first_name) LIKE ?1 
-         OR LOWER(last_name) LIKE ?1"
-    )?;
+    let Some(query) = filter::fts_query(search_query) else {
+        return get_residents_data(db);
+    };
+
+    let mut stmt = db.prepare(&format!(
+        "SELECT r.id, r.apt, r.first_name, r.last_name, r.linked, r.status, r.moved_in, r.moved_out
+         FROM resident_fts f JOIN resident r ON r.id = f.rowid
+         WHERE resident_fts MATCH ?1 AND r.property_id = {} AND r.deleted_at IS NULL
+         ORDER BY f.rank",
+        property::CURRENT_PROPERTY
+    ))?;
 
     let query_map = stmt.query_map([&query], |row| {
         Ok(ResidentData {
@@ -208,6 +668,9 @@ pub fn search_residents(db: &Connection, search_query: &str) -> Result<Vec<Resid
             first_name: row.get(2)?,
             last_name: row.get(3)?,
             linked: row.get(4)?,
+            status: row.get(5)?,
+            moved_in: row.get(6)?,
+            moved_out: row.get(7)?,
         })
     })?;
 
@@ -216,7 +679,11 @@ pub fn search_residents(db: &Connection, search_query: &str) -> Result<Vec<Resid
 
 // Card functions
 pub fn get_cards_data(db: &Connection) -> Result<Vec<CardData>, Error> {
-    let mut query = db.prepare("SELECT * FROM card")?;
+    let mut query = db.prepare(&format!(
+        "SELECT id, resident_id, apt, added_date, hash, status, revoked_date FROM card
+         WHERE resident_id IN (SELECT id FROM resident WHERE property_id = {}) AND deleted_at IS NULL",
+        property::CURRENT_PROPERTY
+    ))?;
 
     let query_map = query.query_map([], |row| {
         Ok(CardData {
@@ -225,6 +692,8 @@ pub fn get_cards_data(db: &Connection) -> Result<Vec<CardData>, Error> {
             apt: row.get(2)?,
             added_date: row.get(3)?,
             hash: row.get(4)?,
+            status: row.get(5)?,
+            revoked_date: row.get(6)?,
         })
     })?;
 
@@ -249,6 +718,7 @@ pub fn convert_card_data_vec(row_data: Vec<CardData>, db: &Connection) -> (Model
             StandardListViewItem::from(Into::<slint::SharedString>::into(card.apt)),
             StandardListViewItem::from(Into::<slint::SharedString>::into(resident_name)),
             StandardListViewItem::from(Into::<slint::SharedString>::into(card.added_date)),
+            StandardListViewItem::from(Into::<slint::SharedString>::into(card.status)),
         ];
         let inner_model = Rc::new(VecModel::from(inner_vec));
         ModelRc::new(inner_model)
@@ -259,36 +729,81 @@ pub fn convert_card_data_vec(row_data: Vec<CardData>, db: &Connection) -> (Model
     (table_model, ids)
 }
 
-pub fn add_card(db: &Connection, resident_id: u32, apt: &str, hash: &str) -> Result<(), Error> {
-    use chrono::Local;
-    let added_date = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+/// Stores a card whose hash was written to the tag with UID `uid`
+pub fn add_card(db: &Connection, resident_id: u32, apt: &str, uid: &str, added_date: &str, hash: &str) -> Result<(), Error> {
+    require_permission(db, Permission::LinkCard)?;
+    let resident = get_resident_info(db, resident_id)?;
+    if resident.status != "active" {
+        return Err(app_error(format!("{} {} has moved out", resident.first_name, resident.last_name)));
+    }
+    let apt = unit::normalize_apt(apt);
+    let apt = apt.as_str();
 
     db.execute(
-        "INSERT INTO card (resident_id, apt, added_date) VALUES (?1, ?2, ?3)",
-        rusqlite::params![resident_id, apt, added_date],
+        "INSERT INTO card (resident_id, apt, added_date, hash) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![resident_id, apt, added_date, hash],
     )?;
-
-    // Log the action
-    let log_action = format!("Card {} was linked to resident ID: {} (Apt: {})", hash, resident_id, apt);
-    add_log(db, "linked", &log_action)?;
+    let card_id = db.last_insert_rowid() as u32;
 
     // Update resident linked status
     db.execute("UPDATE resident SET linked = 1 WHERE id = ?1", [resident_id])?;
 
+    // Log the action
+    let log_action = format!("Card linked: UID={}, Resident ID={}, Apt={}", uid, resident_id, apt);
+    add_log_with_payload(db, "linked", &log_action, serde_json::json!({
+        "resident_id": resident_id, "card_id": card_id, "apt": apt, "card_hash_prefix": &hash[..16.min(hash.len())],
+    }))?;
+
     Ok(())
 }
 
-pub fn search_cards(db: &Connection, search_query: &str) -> Result<Vec<CardData>, Error> {
-    let query = format!("%{}%", search_query.to_lowercase());
-    let mut stmt = db.prepare(
-        "SELECT c.id, c.resident_id, c.apt, c.added_date, c.hash 
-         FROM card c
-         JOIN resident r ON c.resident_id = r.id
-         WHERE LOWER(c.apt) LIKE ?1 
-         OR LOWER(r.first_name) LIKE ?1 
-         OR LOWER(r.last_name) LIKE ?1"
+/// Marks a card as revoked so it no longer verifies or collects packages
+pub fn revoke_card(db: &Connection, card_id: u32) -> Result<(), Error> {
+    use chrono::Local;
+    require_permission(db, Permission::RevokeCard)?;
+
+    let card = get_card_info(db, card_id)?;
+    if card.status == "revoked" {
+        return Err(app_error("Card is already revoked"));
+    }
+
+    let revoked_date = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    db.execute(
+        "UPDATE card SET status = 'revoked', revoked_date = ?1 WHERE id = ?2",
+        rusqlite::params![revoked_date, card_id],
     )?;
 
+    // The resident stays linked only while another active card exists
+    db.execute(
+        "UPDATE resident SET linked = EXISTS(SELECT 1 FROM card WHERE resident_id = ?1 AND status = 'active')
+         WHERE id = ?1",
+        [card.resident_id],
+    )?;
+
+    let log_action = format!(
+        "Card revoked: Card ID={}, Resident ID={}, Apt={}", card_id, card.resident_id, card.apt
+    );
+    add_log_with_payload(db, "revoked", &log_action, serde_json::json!({
+        "card_id": card_id, "resident_id": card.resident_id, "apt": card.apt,
+    }))
+}
+
+/// Cards whose resident matches the search, using the resident index
+pub fn search_cards(db: &Connection, search_query: &str) -> Result<Vec<CardData>, Error> {
+    let Some(query) = filter::fts_query(search_query) else {
+        return get_cards_data(db);
+    };
+
+    let mut stmt = db.prepare(&format!(
+        "SELECT c.id, c.resident_id, c.apt, c.added_date, c.hash, c.status, c.revoked_date
+         FROM resident_fts f
+         JOIN card c ON c.resident_id = f.rowid
+         JOIN resident r ON r.id = c.resident_id
+         WHERE resident_fts MATCH ?1 AND r.property_id = {} AND c.deleted_at IS NULL
+         ORDER BY f.rank, c.id",
+        property::CURRENT_PROPERTY
+    ))?;
+
     let query_map = stmt.query_map([&query], |row| {
         Ok(CardData {
             id: row.get(0)?,
@@ -296,6 +811,8 @@ pub fn search_cards(db: &Connection, search_query: &str) -> Result<Vec<CardData>
             apt: row.get(2)?,
             added_date: row.get(3)?,
             hash: row.get(4)?,  // Now this column exists!
+            status: row.get(5)?,
+            revoked_date: row.get(6)?,
         })
     })?;
 
@@ -304,7 +821,11 @@ pub fn search_cards(db: &Connection, search_query: &str) -> Result<Vec<CardData>
 
 // Log functions
 pub fn get_logs_data(db: &Connection) -> Result<Vec<LogData>, Error> {
-    let mut query = db.prepare("SELECT * FROM log ORDER BY date_time DESC")?;
+    let mut query = db.prepare(
+        "SELECT l.id, l.action_type, l.action, l.date_time, COALESCE(o.display_name, ''), COALESCE(l.payload, '')
+         FROM log l LEFT JOIN operator o ON o.id = l.operator_id
+         ORDER BY l.date_time DESC"
+    )?;
 
     let query_map = query.query_map([], |row| {
         Ok(LogData {
@@ -312,6 +833,8 @@ pub fn get_logs_data(db: &Connection) -> Result<Vec<LogData>, Error> {
             action_type: row.get(1)?,
             action: row.get(2)?,
             date_time: row.get(3)?,
+            operator: row.get(4)?,
+            payload: row.get(5)?,
         })
     })?;
 
@@ -329,6 +852,7 @@ pub fn convert_log_data_vec(row_data: Vec<LogData>) -> (ModelRc<ModelRc<Standard
             StandardListViewItem::from(Into::<slint::SharedString>::into(log.action_type)),
             StandardListViewItem::from(Into::<slint::SharedString>::into(log.action)),
             StandardListViewItem::from(Into::<slint::SharedString>::into(log.date_time)),
+            StandardListViewItem::from(Into::<slint::SharedString>::into(log.operator)),
         ];
         let inner_model = Rc::new(VecModel::from(inner_vec));
         ModelRc::new(inner_model)
@@ -339,34 +863,39 @@ pub fn convert_log_data_vec(row_data: Vec<LogData>) -> (ModelRc<ModelRc<Standard
     (table_model, ids)
 }
 
+/// Adds a log entry attributed to the operator logged in on this connection, if any
 pub fn add_log(db: &Connection, action_type: &str, action: &str) -> Result<(), Error> {
-    use chrono::Local;
-    let date_time = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-
-    db.execute(
-        "INSERT INTO log (action_type, action, date_time) VALUES (?1, ?2, ?3)",
-        rusqlite::params![action_type, action, date_time],
-    )?;
+    add_log_with_payload(db, action_type, action, serde_json::json!({}))
+}
 
-    Ok(())
+/// Adds a log entry with structured details (ids, apartment, reason...) stored as JSON.
+/// Every log write goes through here; entries are hash-chained and can't be edited afterwards.
+/// `resident_id`, `package_id` and `card_id` in the payload also fill the log columns of the same name.
+pub fn add_log_with_payload(db: &Connection, action_type: &str, action: &str, payload: serde_json::Value) -> Result<(), Error> {
+    audit::append(db, action_type, action, &payload)
 }
 
 pub fn search_logs(db: &Connection, search_query: &str) -> Result<Vec<LogData>, Error> {
-    let query = format!("%{}%", search_query.to_lowercase());
-    let mut stmt = db.prepare(
-        "SELECT id, action_type, action, date_time FROM log 
-         WHERE LOWER(action_type) LIKE ?1 
-         OR LOWER(action) LIKE ?1
-         OR LOWER(date_time) LIKE ?1
-         ORDER BY date_time DESC"
-    )?;
+    filter_logs(db, &RecordFilter { text: filter::non_empty(search_query), ..Default::default() })
+}
 
-    let query_map = stmt.query_map([&query], |row| {
+/// Log entries matching a structured filter, best text matches first, then newest first
+pub fn filter_logs(db: &Connection, filter: &RecordFilter) -> Result<Vec<LogData>, Error> {
+    filter.validate().map_err(app_error)?;
+
+    let query = filter::log_query(filter);
+    let mut stmt = db.prepare(&query.sql(
+        "l.id, l.action_type, l.action, l.date_time, COALESCE(o.display_name, ''), COALESCE(l.payload, '')"
+    ))?;
+
+    let query_map = stmt.query_map(rusqlite::params_from_iter(query.builder.params.iter()), |row| {
         Ok(LogData {
             id: row.get(0)?,
             action_type: row.get(1)?,
             action: row.get(2)?,
             date_time: row.get(3)?,
+            operator: row.get(4)?,
+            payload: row.get(5)?,
         })
     })?;
 
@@ -379,15 +908,22 @@ pub fn add_package(
     apt: &str, 
     package_number: &str,
     barcode: &str,
-    comment: Option<&str>
+    comment: Option<&str>,
+    carrier: Option<&str>,
 ) -> Result<u32, Error> {
     use chrono::Local;
+    let operator = require_permission(db, Permission::EditRecords)?;
+    let unit = unit::resolve_unit(db, apt)?;
+    let apt = unit.number.as_str();
+
     let date_time = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    // Without an explicit carrier, guess it from the tracking number
+    let carrier = carrier.filter(|c| !c.trim().is_empty()).or_else(|| carrier_from_barcode(barcode));
     
     db.execute(
-        "INSERT INTO package (apt, package_number, barcode, comment, date_time, status) 
-         VALUES (?1, ?2, ?3, ?4, ?5, 'pending')",
-        rusqlite::params![apt, package_number, barcode, comment, date_time],
+        "INSERT INTO package (apt, package_number, barcode, comment, date_time, status, carrier, received_by, unit_id, property_id) 
+         VALUES (?1, ?2, ?3, ?4, ?5, 'pending', ?6, ?7, ?8, ?9)",
+        rusqlite::params![apt, package_number, barcode, comment, date_time, carrier, operator.id, unit.id, unit.property_id],
     )?;
     
     let package_id = db.last_insert_rowid() as u32;
@@ -400,18 +936,43 @@ pub fn add_package(
         barcode,
         comment.map(|c| format!(" - {}", c)).unwrap_or_default()
     );
-    add_log(db, "package_in", &log_action)?;
+    add_log_with_payload(db, "package_in", &log_action, serde_json::json!({
+        "package_id": package_id, "apt": apt, "package_number": package_number, "barcode": barcode,
+        "carrier": carrier,
+    }))?;
     
     Ok(package_id)
 }
 
+/// Best guess at the carrier from the shape of a tracking number
+pub fn carrier_from_barcode(barcode: &str) -> Option<&'static str> {
+    let code = barcode.trim().to_uppercase();
+    let digits = code.chars().all(|c| c.is_ascii_digit());
+
+    if code.starts_with("1Z") && code.len() == 18 {
+        Some("UPS")
+    } else if code.starts_with("TBA") {
+        Some("Amazon")
+    } else if (digits && (20..=22).contains(&code.len()) && code.starts_with('9'))
+        || (code.len() == 13 && code.ends_with("US")) {
+        Some("USPS")
+    } else if digits && matches!(code.len(), 12 | 15) {
+        Some("FedEx")
+    } else if digits && code.len() == 10 {
+        Some("DHL")
+    } else {
+        None
+    }
+}
+
 pub fn get_packages_data(db: &Connection) -> Result<Vec<PackageData>, Error> {
-    let mut query = db.prepare(
-        "SELECT id, apt, package_number, barcode, comment, date_time, status 
+    let mut query = db.prepare(&format!(
+        "SELECT id, apt, package_number, barcode, comment, date_time, status, carrier
          FROM package 
-         WHERE status = 'pending'
-         ORDER BY date_time DESC"
-    )?;
+         WHERE status = 'pending' AND property_id = {} AND deleted_at IS NULL
+         ORDER BY date_time DESC",
+        property::CURRENT_PROPERTY
+    ))?;
 
     let query_map = query.query_map([], |row| {
         Ok(PackageData {
@@ -422,12 +983,24 @@ pub fn get_packages_data(db: &Connection) -> Result<Vec<PackageData>, Error> {
             comment: row.get(4)?,
             date_time: row.get(5)?,
             status: row.get(6)?,
+            carrier: row.get(7)?,
         })
     })?;
 
     query_map.collect::<Result<Vec<_>, _>>()
 }
 
+pub fn count_pending_packages(db: &Connection) -> Result<u32, Error> {
+    db.query_row(
+        &format!(
+            "SELECT COUNT(*) FROM package WHERE status = 'pending' AND property_id = {} AND deleted_at IS NULL",
+            property::CURRENT_PROPERTY
+        ),
+        [],
+        |row| row.get(0),
+    )
+}
+
 pub fn convert_package_data_vec(
     row_data: Vec<PackageData>
 ) -> (ModelRc<ModelRc<StandardListViewItem>>, Vec<u32>) {
@@ -447,6 +1020,8 @@ pub fn convert_package_data_vec(
             StandardListViewItem::from(Into::<slint::SharedString>::into(package.package_number)),
             StandardListViewItem::from(Into::<slint::SharedString>::into(package.barcode)),
             StandardListViewItem::from(Into::<slint::SharedString>::into(comment_display)),
+            StandardListViewItem::from(Into::<slint::SharedString>::into(package.carrier.unwrap_or_default())),
+            StandardListViewItem::from(Into::<slint::SharedString>::into(package.status)),
         ];
         let inner_model = Rc::new(VecModel::from(inner_vec));
         ModelRc::new(inner_model)
@@ -459,7 +1034,7 @@ pub fn convert_package_data_vec(
 
 pub fn get_package_info(db: &Connection, index: u32) -> Result<PackageData, Error> {
     let package = db.query_row(
-        "SELECT id, apt, package_number, barcode, comment, date_time, status 
+        "SELECT id, apt, package_number, barcode, comment, date_time, status, carrier
          FROM package WHERE id = ?1",
         [index],
         |row| {
@@ -471,34 +1046,56 @@ pub fn get_package_info(db: &Connection, index: u32) -> Result<PackageData, Erro
                 comment: row.get(4)?,
                 date_time: row.get(5)?,
                 status: row.get(6)?,
+                carrier: row.get(7)?,
             })
         },
     )?;
     Ok(package)
 }
 
-pub fn collect_package(db: &Connection, package_id: u32, card_hash: &str) -> Result<String, Error> {
+pub fn collect_package(db: &Connection, package_id: u32, card_hash: &str, policy: SignaturePolicy) -> Result<String, Error> {
+    collect_package_signed(db, package_id, card_hash, None, policy)
+}
+
+/// Like `collect_package`, recording the signature the resident gave at handover.
+/// Fails without one when the building's policy requires it.
+pub fn collect_package_signed(db: &Connection, package_id: u32, card_hash: &str, signature_id: Option<u32>, policy: SignaturePolicy) -> Result<String, Error> {
     use chrono::Local;
+    require_permission(db, Permission::EditRecords)?;
+    if signature_id.is_none() && policy == SignaturePolicy::Required {
+        return Err(app_error("A signature is required to collect packages in this building"));
+    }
     
     // Verify card and get resident info
     let resident = db.query_row(
-        "SELECT r.id, r.apt, r.first_name, r.last_name 
+        "SELECT r.id, r.apt, r.first_name, r.last_name, c.id, r.property_id 
          FROM card c 
          JOIN resident r ON c.resident_id = r.id
-         WHERE c.hash = ?1",
+         WHERE c.hash = ?1 AND c.status = 'active' AND c.deleted_at IS NULL",
         [card_hash],
         |row| Ok((
             row.get::<_, u32>(0)?,
             row.get::<_, String>(1)?,
             row.get::<_, String>(2)?,
             row.get::<_, String>(3)?,
+            row.get::<_, u32>(4)?,
+            row.get::<_, Option<u32>>(5)?,
         ))
     )?;
     
     // Get package info
     let package = get_package_info(db, package_id)?;
+    if package.status != "pending" {
+        return Err(app_error(format!("Package #{} was already collected", package.package_number)));
+    }
     
-    // Verify apartment matches
+    // Verify building and apartment match; apartment numbers repeat across buildings
+    let package_property: Option<u32> = db.query_row(
+        "SELECT property_id FROM package WHERE id = ?1", [package_id], |row| row.get(0)
+    )?;
+    if resident.5 != package_property {
+        return Err(app_error("Package is for another building than the card"));
+    }
     if resident.1 != package.apt {
         return Err(Error::SqliteFailure(
             rusqlite::ffi::Error::new(1),
@@ -509,8 +1106,8 @@ pub fn collect_package(db: &Connection, package_id: u32, card_hash: &str) -> Res
     // Mark as collected
     let collection_time = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
     db.execute(
-        "UPDATE package SET status = 'collected', collection_time = ?1 WHERE id = ?2",
-        rusqlite::params![collection_time, package_id],
+        "UPDATE package SET status = 'collected', collection_time = ?1, collected_by = ?2, signature_id = ?3 WHERE id = ?4",
+        rusqlite::params![collection_time, resident.0, signature_id, package_id],
     )?;
     
     // Log collection
@@ -518,26 +1115,93 @@ pub fn collect_package(db: &Connection, package_id: u32, card_hash: &str) -> Res
         "Package collected: {} {} (Apt {}) picked up package #{}", 
         resident.2, resident.3, resident.1, package.package_number
     );
-    add_log(db, "package_out", &log_action)?;
+    let mut payload = serde_json::json!({
+        "package_id": package_id, "resident_id": resident.0, "card_id": resident.4, "apt": resident.1,
+    });
+    if let Some(signature_id) = signature_id {
+        payload["signature_id"] = signature_id.into();
+    }
+    add_log_with_payload(db, "package_out", &log_action, payload)?;
     
     Ok(format!("{} {}", resident.2, resident.3))
 }
 
-pub fn search_packages(db: &Connection, search_query: &str) -> Result<Vec<PackageData>, Error> {
-    let query = format!("%{}%", search_query.to_lowercase());
-    let mut stmt = db.prepare(
-        "SELECT id, apt, package_number, barcode, comment, date_time, status 
-         FROM package 
-         WHERE status = 'pending' AND (
-            LOWER(apt) LIKE ?1 
-            OR LOWER(package_number) LIKE ?1 
-            OR LOWER(barcode) LIKE ?1
-            OR LOWER(comment) LIKE ?1
-         )
-         ORDER BY date_time DESC"
+/// Marks a package collected without a resident card. The reason is kept in the log.
+/// A signature is recorded like at a card collection, and required under the same policy.
+pub fn collect_package_override(db: &Connection, package_id: u32, reason: &str, signature_id: Option<u32>, policy: SignaturePolicy) -> Result<(), Error> {
+    use chrono::Local;
+    let operator = require_permission(db, Permission::ManualOverride)?;
+
+    let reason = reason.trim();
+    if reason.is_empty() {
+        return Err(app_error("A reason is required for a manual collection"));
+    }
+    if signature_id.is_none() && policy == SignaturePolicy::Required {
+        return Err(app_error("A signature is required to collect packages in this building"));
+    }
+
+    let package = get_package_info(db, package_id)?;
+    if package.status != "pending" {
+        return Err(app_error(format!("Package #{} was already collected", package.package_number)));
+    }
+
+    let collection_time = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    db.execute(
+        "UPDATE package SET status = 'collected', collection_time = ?1, signature_id = ?2 WHERE id = ?3",
+        rusqlite::params![collection_time, signature_id, package_id],
     )?;
 
-    let query_map = stmt.query_map([&query], |row| {
+    let log_action = format!(
+        "Manual override: package #{} (Apt {}) marked collected by {} without a card. Reason: {}",
+        package.package_number, package.apt, operator.display_name, reason
+    );
+    let mut payload = serde_json::json!({
+        "package_id": package_id, "apt": package.apt, "operator_id": operator.id, "reason": reason,
+    });
+    if let Some(signature_id) = signature_id {
+        payload["signature_id"] = signature_id.into();
+    }
+    add_log_with_payload(db, "override", &log_action, payload)
+}
+
+/// Removes a package, e.g. one scanned by mistake. Like residents it is only
+/// marked deleted until undone or purged.
+pub fn delete_package(db: &Connection, package_id: u32) -> Result<(), Error> {
+    use chrono::Local;
+    let operator = require_permission(db, Permission::DeletePackage)?;
+    let package = get_package_info(db, package_id)?;
+
+    let deleted_at = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let removed = db.execute(
+        "UPDATE package SET deleted_at = ?1, deleted_by = ?2 WHERE id = ?3 AND deleted_at IS NULL",
+        rusqlite::params![deleted_at, operator.id, package_id],
+    )?;
+    if removed == 0 {
+        return Err(app_error(format!("Package #{} was already removed", package.package_number)));
+    }
+
+    let log_action = format!("Package #{} (Apt {}) was removed", package.package_number, package.apt);
+    add_log_with_payload(db, "remove", &log_action, serde_json::json!({ "package_id": package_id, "apt": package.apt }))
+}
+
+pub fn search_packages(db: &Connection, search_query: &str) -> Result<Vec<PackageData>, Error> {
+    filter_packages(db, &RecordFilter {
+        status: Some("pending".to_string()),
+        text: filter::non_empty(search_query),
+        ..Default::default()
+    })
+}
+
+/// Packages matching a structured filter, best text matches first, then newest first
+pub fn filter_packages(db: &Connection, filter: &RecordFilter) -> Result<Vec<PackageData>, Error> {
+    filter.validate().map_err(app_error)?;
+
+    let query = filter::package_query(filter);
+    let mut stmt = db.prepare(&query.sql(
+        "p.id, p.apt, p.package_number, p.barcode, p.comment, p.date_time, p.status, p.carrier"
+    ))?;
+
+    let query_map = stmt.query_map(rusqlite::params_from_iter(query.builder.params.iter()), |row| {
         Ok(PackageData {
             id: row.get(0)?,
             apt: row.get(1)?,
@@ -546,6 +1210,7 @@ pub fn search_packages(db: &Connection, search_query: &str) -> Result<Vec<Packag
             comment: row.get(4)?,
             date_time: row.get(5)?,
             status: row.get(6)?,
+            carrier: row.get(7)?,
         })
     })?;
 
@@ -553,14 +1218,15 @@ pub fn search_packages(db: &Connection, search_query: &str) -> Result<Vec<Packag
 }
 
 pub fn get_packages_for_resident(db: &Connection, apt: &str) -> Result<Vec<PackageData>, Error> {
-    let mut query = db.prepare(
-        "SELECT id, apt, package_number, barcode, comment, date_time, status 
+    let mut query = db.prepare(&format!(
+        "SELECT id, apt, package_number, barcode, comment, date_time, status, carrier
          FROM package 
-         WHERE apt = ?1 AND status = 'pending'
-         ORDER BY date_time DESC"
-    )?;
+         WHERE apt = ?1 AND status = 'pending' AND property_id = {} AND deleted_at IS NULL
+         ORDER BY date_time DESC",
+        property::CURRENT_PROPERTY
+    ))?;
 
-    let query_map = query.query_map([apt], |row| {  // Changed from stmt to query
+    let query_map = query.query_map([unit::normalize_apt(apt)], |row| {  // Changed from stmt to query
         Ok(PackageData {
             id: row.get(0)?,
             apt: row.get(1)?,
@@ -569,8 +1235,121 @@ pub fn get_packages_for_resident(db: &Connection, apt: &str) -> Result<Vec<Packa
             comment: row.get(4)?,
             date_time: row.get(5)?,
             status: row.get(6)?,
+            carrier: row.get(7)?,
         })
     })?;
 
     query_map.collect::<Result<Vec<_>, _>>()
-}
\ No newline at end of file
+}
+
+// Resident profile
+
+/// A package on a resident's profile: pending for their apartment, or collected with their card
+pub struct PackageHistory {
+    pub id: u32,
+    pub package_number: String,
+    pub barcode: String,
+    pub carrier: Option<String>,
+    pub date_time: String,
+    pub status: String,
+    pub collection_time: Option<String>,
+    /// Signature given at collection, in `signature::encode_strokes` form
+    pub signature: Option<String>,
+}
+
+/// Everything the resident detail view shows
+pub struct ResidentProfile {
+    pub resident: ResidentData,
+    pub cards: Vec<CardData>,
+    pub packages: Vec<PackageHistory>,
+    pub events: Vec<LogData>,
+}
+
+/// Loads a resident with their cards, packages and the latest `history_limit` log entries about them
+pub fn get_resident_profile(db: &Connection, resident_id: u32, history_limit: u32) -> Result<ResidentProfile, Error> {
+    Ok(ResidentProfile {
+        resident: get_resident_info(db, resident_id)?,
+        cards: get_resident_cards(db, resident_id)?,
+        packages: get_resident_packages(db, resident_id, history_limit)?,
+        events: get_resident_events(db, resident_id, history_limit)?,
+    })
+}
+
+/// All cards ever linked to a resident, active ones first
+pub fn get_resident_cards(db: &Connection, resident_id: u32) -> Result<Vec<CardData>, Error> {
+    let mut query = db.prepare(
+        "SELECT id, resident_id, apt, added_date, hash, status, revoked_date FROM card
+         WHERE resident_id = ?1 AND deleted_at IS NULL
+         ORDER BY status = 'active' DESC, added_date DESC"
+    )?;
+
+    let query_map = query.query_map([resident_id], |row| {
+        Ok(CardData {
+            id: row.get(0)?,
+            resident_id: row.get(1)?,
+            apt: row.get(2)?,
+            added_date: row.get(3)?,
+            hash: row.get(4)?,
+            status: row.get(5)?,
+            revoked_date: row.get(6)?,
+        })
+    })?;
+
+    query_map.collect::<Result<Vec<_>, _>>()
+}
+
+/// Packages waiting at the resident's apartment, then up to `limit` packages they collected, newest first
+pub fn get_resident_packages(db: &Connection, resident_id: u32, limit: u32) -> Result<Vec<PackageHistory>, Error> {
+    let mut query = db.prepare(
+        "SELECT p.id, p.package_number, p.barcode, p.carrier, p.date_time, p.status, p.collection_time, NULL
+         FROM resident r
+         JOIN package p ON p.apt = r.apt AND p.property_id = r.property_id AND p.status = 'pending'
+             AND p.deleted_at IS NULL
+         WHERE r.id = ?1 AND r.status = 'active'
+         UNION ALL
+         SELECT * FROM (
+             SELECT p.id, p.package_number, p.barcode, p.carrier, p.date_time, p.status, p.collection_time, s.strokes
+             FROM package p LEFT JOIN signature s ON s.id = p.signature_id
+             WHERE p.collected_by = ?1 AND p.deleted_at IS NULL
+             ORDER BY p.collection_time DESC LIMIT ?2
+         )"
+    )?;
+
+    let query_map = query.query_map(rusqlite::params![resident_id, limit], |row| {
+        Ok(PackageHistory {
+            id: row.get(0)?,
+            package_number: row.get(1)?,
+            barcode: row.get(2)?,
+            carrier: row.get(3)?,
+            date_time: row.get(4)?,
+            status: row.get(5)?,
+            collection_time: row.get(6)?,
+            signature: row.get(7)?,
+        })
+    })?;
+
+    query_map.collect::<Result<Vec<_>, _>>()
+}
+
+/// Latest log entries about a resident (verifications, cards, collections), newest first
+pub fn get_resident_events(db: &Connection, resident_id: u32, limit: u32) -> Result<Vec<LogData>, Error> {
+    let mut query = db.prepare(
+        "SELECT l.id, l.action_type, l.action, l.date_time, COALESCE(o.display_name, ''), COALESCE(l.payload, '')
+         FROM log l LEFT JOIN operator o ON o.id = l.operator_id
+         WHERE l.resident_id = ?1
+         ORDER BY l.id DESC LIMIT ?2"
+    )?;
+
+    let query_map = query.query_map(rusqlite::params![resident_id, limit], |row| {
+        Ok(LogData {
+            id: row.get(0)?,
+            action_type: row.get(1)?,
+            action: row.get(2)?,
+            date_time: row.get(3)?,
+            operator: row.get(4)?,
+            payload: row.get(5)?,
+        })
+    })?;
+
+    query_map.collect::<Result<Vec<_>, _>>()
+}
@@ -1,26 +1,102 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::{error::Error, rc::Rc, cell::RefCell, sync::{Arc, Mutex}};
+use std::{error::Error, rc::Rc, cell::RefCell, sync::{Arc, Mutex}, time::{Duration, Instant}};
 use slint_rust_template::*;
+use slint_rust_template::filter::RecordFilter;
+use slint_rust_template::table_model::{LazyTableModel, SortOrder, TableKind};
 use chrono::Local;
-use slint::{VecModel, Model};
+use slint::{VecModel, Model, ModelRc, StandardListViewItem};
+use device_query::{DeviceQuery, DeviceState};
 
 mod nfc_reader;
 use nfc_reader::{NFCReader, NFCCardData};
+mod cli;
 
 slint::include_modules!();
 
 // COMPLETE FIX - Add a Mutex to control NFC reader access
 
+// Scanned but not yet saved: barcode, comment, apartment, carrier
+type ScannedPackage = (String, String, String, String);
+
+// Packages and log entries shown on a resident's profile
+const RESIDENT_HISTORY_LIMIT: u32 = 50;
+
+// Apartments offered while typing in the assign flow
+const APT_SUGGESTION_LIMIT: u32 = 6;
+
 fn main() -> Result<(), Box<dyn Error>> {
+    // Command-line mode: run the command and exit without opening the window
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(result) = cli::run(&args) {
+        return result;
+    }
+
     let ui = AppWindow::new()?;
-    let db = Arc::new(Mutex::new(slint_rust_template::connect_to_db()));
 
+    // Settings file; an invalid file falls back to defaults so the app still starts
+    let (app_config, config_error) = match config::load_config(std::path::Path::new(config::CONFIG_PATH)) {
+        Ok(app_config) => (app_config, None),
+        Err(e) => {
+            println!("⚠️  {}", e);
+            (config::Config::default(), Some(e.to_string()))
+        }
+    };
+    encryption::load_key(&app_config.database)?;
+    if encryption::is_enabled() {
+        println!("🔒 Opening encrypted database {}", app_config.database.path);
+    }
+    let db = Arc::new(Mutex::new(open_db(&app_config.database.path)?));
+
+    // Scheduled online backups with daily/weekly retention
+    if app_config.backup.enabled {
+        backup::start_backup_scheduler(std::path::PathBuf::from(&app_config.database.path), app_config.backup_settings());
+    }
+
+    // Daily package reports for every building, written at the end of the day
+    if app_config.report.enabled {
+        report::start_report_scheduler(std::path::PathBuf::from(&app_config.database.path), app_config.report_settings());
+    }
+
+    // Open the building this desk was last set to
+    if !app_config.property.current.is_empty() {
+        let db = db.lock().unwrap();
+        let switched = property::find_property(&db, &app_config.property.current)
+            .and_then(|building| property::switch_property(&db, building.id));
+        if let Err(e) = switched {
+            println!("⚠️  Could not open building '{}': {}", app_config.property.current, e);
+        }
+    }
+
+    // Anonymise log entries past the retention period
+    match privacy::apply_retention(&db.lock().unwrap(), &app_config.retention) {
+        Ok(0) => {}
+        Ok(count) => println!("🕶️  Anonymised {} log entries past the retention period", count),
+        Err(e) => println!("⚠️  Could not apply log retention: {}", e),
+    }
+
+    let app_config = Arc::new(Mutex::new(app_config));
+    if let Some(error) = config_error {
+        ui.set_info_alert(format!("Using default settings. {}", error).into());
+    }
+
+    // The app starts locked; the first run asks for an administrator account
+    ui.set_needs_setup(operator::count_operators(&db.lock().unwrap())? == 0);
+    let last_activity = Arc::new(Mutex::new(Instant::now()));
+
+    // Ids behind the resident dropdown used when linking cards
     let resident_ids: Rc<RefCell<Vec<u32>>> = Rc::new(RefCell::new(Vec::new()));
-    let card_ids: Rc<RefCell<Vec<u32>>> = Rc::new(RefCell::new(Vec::new()));
-    let log_ids: Rc<RefCell<Vec<u32>>> = Rc::new(RefCell::new(Vec::new()));
-    let package_ids: Rc<RefCell<Vec<u32>>> = Rc::new(RefCell::new(Vec::new()));
-    let unassigned_packages: Rc<RefCell<Vec<(String, String, String)>>> = Rc::new(RefCell::new(Vec::new()));
+
+    // Table models load rows a page at a time as the tables scroll
+    let resident_table = LazyTableModel::new(Arc::clone(&db), TableKind::Residents, RecordFilter::default());
+    let card_table = LazyTableModel::new(Arc::clone(&db), TableKind::Cards, RecordFilter::default());
+    let package_table = LazyTableModel::new(Arc::clone(&db), TableKind::Packages, record_filter(&ui.get_package_filter(), ""));
+    let log_table = LazyTableModel::new(Arc::clone(&db), TableKind::Logs, RecordFilter::default());
+    ui.set_residents_data(ModelRc::from(Rc::clone(&resident_table)));
+    ui.set_cards_data(ModelRc::from(Rc::clone(&card_table)));
+    ui.set_packages_data(ModelRc::from(Rc::clone(&package_table)));
+    ui.set_logs_data(ModelRc::from(Rc::clone(&log_table)));
+    let unassigned_packages: Rc<RefCell<Vec<ScannedPackage>>> = Rc::new(RefCell::new(Vec::new()));
     
     // Flag to pause automatic verification during card linking
     let verification_paused = Arc::new(Mutex::new(false));
@@ -40,8 +116,9 @@ fn main() -> Result<(), Box<dyn Error>> {
                     println!("  {}. {}", i + 1, reader_name);
                 }
                 
-                // Auto-select first reader if available
-                if let Some(first_reader) = readers.first() {
+                // Auto-select the configured reader if available
+                let chosen_reader = app_config.lock().unwrap().effective().reader.choose(&readers).cloned();
+                if let Some(first_reader) = chosen_reader.as_ref() {
                     let mut nfc = NFCReader::new().unwrap();
                     nfc.select_reader(first_reader).unwrap();
                     *nfc_reader.borrow_mut() = Some(nfc);
@@ -53,6 +130,8 @@ fn main() -> Result<(), Box<dyn Error>> {
                     let ui_weak = ui.as_weak();
                     let verification_paused_clone = Arc::clone(&verification_paused);
                     let nfc_reader_lock_clone = Arc::clone(&nfc_reader_lock);
+                    let config_clone = Arc::clone(&app_config);
+                    let last_activity_clone = Arc::clone(&last_activity);
                     
                     std::thread::spawn(move || {
                         start_automatic_verification(
@@ -60,7 +139,9 @@ fn main() -> Result<(), Box<dyn Error>> {
                             db_clone, 
                             ui_weak, 
                             verification_paused_clone,
-                            nfc_reader_lock_clone
+                            nfc_reader_lock_clone,
+                            config_clone,
+                            last_activity_clone
                         );
                     });
                     
@@ -75,33 +156,45 @@ fn main() -> Result<(), Box<dyn Error>> {
     ui.on_quick_scan_package({
         let ui_handle = ui.as_weak();
         let unassigned = Rc::clone(&unassigned_packages);
+        let repeats = RefCell::new(scanner::RepeatFilter::default());
         
-        move |barcode: slint::SharedString, comment: slint::SharedString| {
+        move |barcode: slint::SharedString, comment: slint::SharedString, carrier: slint::SharedString| {
             let ui = ui_handle.unwrap();
+
+            // The scanner listener and the focused scan field can both deliver one scan
+            if repeats.borrow_mut().is_repeat(&barcode, Instant::now()) {
+                if let Some(last) = unassigned.borrow_mut().last_mut().filter(|last| last.1.is_empty()) {
+                    last.1 = comment.to_string();
+                }
+                return;
+            }
             
             // Store temporarily with empty apartment
             unassigned.borrow_mut().push((
                 barcode.to_string(),
                 comment.to_string(),
-                String::new()  // Empty apartment initially
+                String::new(),  // Empty apartment initially
+                carrier.to_string(),
             ));
             
             // Convert to Slint model for display
             let packages: Vec<PackageData> = unassigned.borrow()
                 .iter()
                 .enumerate()
-                .map(|(idx, (bc, cmt, apt))| PackageData {
+                .map(|(idx, (bc, cmt, apt, carrier))| PackageData {
                     id: (idx + 1) as i32,
                     apt: apt.clone().into(),
                     package_number: (idx + 1).to_string().into(),
                     barcode: bc.clone().into(),
                     comment: cmt.clone().into(),
                     date_time: "".into(),
+                    carrier: carrier.clone().into(),
                 })
                 .collect();
             
             let model = Rc::new(VecModel::from(packages));
             ui.set_unassigned_packages(slint::ModelRc::from(model));
+            ui.set_scan_count(unassigned.borrow().len() as i32);
             
             println!("📦 Scanned: {} (Total: {})", barcode, unassigned.borrow().len());
         }
@@ -142,13 +235,14 @@ fn main() -> Result<(), Box<dyn Error>> {
             let packages: Vec<PackageData> = unassigned.borrow()
                 .iter()
                 .enumerate()
-                .map(|(idx, (bc, cmt, apt))| PackageData {
+                .map(|(idx, (bc, cmt, apt, carrier))| PackageData {
                     id: (idx + 1) as i32,
                     apt: apt.clone().into(),
                     package_number: (idx + 1).to_string().into(),
                     barcode: bc.clone().into(),
                     comment: cmt.clone().into(),
                     date_time: "".into(),
+                    carrier: carrier.clone().into(),
                 })
                 .collect();
             
@@ -167,6 +261,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     // Assign apartment to single package
     ui.on_assign_apartment_to_package({
         let ui_handle = ui.as_weak();
+        let db = Arc::clone(&db);
         let unassigned = Rc::clone(&unassigned_packages);
         
         move |index: i32, apt: slint::SharedString| {
@@ -185,6 +280,16 @@ fn main() -> Result<(), Box<dyn Error>> {
             
             let ui = ui_handle.unwrap();
             
+            // Only registered apartments, stored in their normalised form
+            let apt: slint::SharedString = match unit::resolve_unit(&db.lock().unwrap(), &apt) {
+                Ok(unit) => unit.number.into(),
+                Err(e) => {
+                    println!("ERROR: {}", e);
+                    ui.set_info_alert(format!("Error: {}", e).into());
+                    return;
+                }
+            };
+            
             // Get current model from UI
             let packages_model = ui.get_unassigned_packages();
             
@@ -222,6 +327,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     // Bulk assign apartment to all packages
     ui.on_bulk_assign_apartment({
         let ui_handle = ui.as_weak();
+        let db = Arc::clone(&db);
         let unassigned = Rc::clone(&unassigned_packages);
         
         move |apt: slint::SharedString| {
@@ -230,11 +336,20 @@ fn main() -> Result<(), Box<dyn Error>> {
             
             let ui = ui_handle.unwrap();
             
+            let apt = match unit::resolve_unit(&db.lock().unwrap(), &apt) {
+                Ok(unit) => unit.number,
+                Err(e) => {
+                    println!("ERROR: {}", e);
+                    ui.set_info_alert(format!("Error: {}", e).into());
+                    return;
+                }
+            };
+            
             let mut packages = unassigned.borrow_mut();
             let count = packages.len();
             
             for pkg in packages.iter_mut() {
-                pkg.2 = apt.to_string();
+                pkg.2 = apt.clone();
             }
             drop(packages);
             
@@ -242,13 +357,14 @@ fn main() -> Result<(), Box<dyn Error>> {
             let packages: Vec<PackageData> = unassigned.borrow()
                 .iter()
                 .enumerate()
-                .map(|(idx, (bc, cmt, apt))| PackageData {
+                .map(|(idx, (bc, cmt, apt, carrier))| PackageData {
                     id: (idx + 1) as i32,
                     apt: apt.clone().into(),
                     package_number: (idx + 1).to_string().into(),
                     barcode: bc.clone().into(),
                     comment: cmt.clone().into(),
                     date_time: "".into(),
+                    carrier: carrier.clone().into(),
                 })
                 .collect();
             
@@ -264,16 +380,20 @@ fn main() -> Result<(), Box<dyn Error>> {
     ui.on_save_assigned_packages({
         let ui_handle = ui.as_weak();
         let db = Arc::clone(&db);
-        let package_ids = Rc::clone(&package_ids);
+        let app_config = Arc::clone(&app_config);
+        let package_table = Rc::clone(&package_table);
         let unassigned = Rc::clone(&unassigned_packages);
         
         move || {
             let ui = ui_handle.unwrap();
             let packages_model = ui.get_unassigned_packages();
+            let shelf = ui.get_current_shelf();
             let db_guard = db.lock().unwrap();
             
             let mut saved_count = 0;
             let mut error_count = 0;
+            let mut saved_ids = Vec::new();
+            let mut shelf_error = None;
             
             for i in 0..packages_model.row_count() {
                 if let Some(pkg) = packages_model.row_data(i) {
@@ -296,10 +416,20 @@ fn main() -> Result<(), Box<dyn Error>> {
                         &(i + 1).to_string(),
                         pkg.barcode.as_str(),
                         comment,
+                        Some(pkg.carrier.as_str()),
                     ) {
-                        Ok(_) => {
+                        Ok(package_id) => {
                             saved_count += 1;
+                            saved_ids.push(package_id);
                             println!("✅ Saved: Package #{} → Apt {}", i + 1, pkg.apt);
+
+                            // The package is in; a shelf that can't be recorded is reported on its own
+                            if !shelf.trim().is_empty() {
+                                if let Err(e) = label::set_shelf(&db_guard, package_id, &shelf) {
+                                    println!("❌ Shelf not set for package #{}: {}", i + 1, e);
+                                    shelf_error = Some(e);
+                                }
+                            }
                         }
                         Err(e) => {
                             error_count += 1;
@@ -310,19 +440,38 @@ fn main() -> Result<(), Box<dyn Error>> {
             }
             
             // Refresh package list
-            let row_data = get_packages_data(&*db_guard).unwrap();
-            let (table_model, ids) = convert_package_data_vec(row_data);
-            *package_ids.borrow_mut() = ids;
-            ui.set_packages_data(table_model);
+            package_table.refresh();
             
             // Clear temporary storage
             unassigned.borrow_mut().clear();
+            ui.set_current_shelf("".into());
+            
+            // Labels for the boxes, so apartments no longer have to be written on them
+            let config = app_config.lock().unwrap().clone();
+            let printed = if config.label.auto_print && !saved_ids.is_empty() {
+                match label::print_labels(&db_guard, &saved_ids, &config.label_settings()) {
+                    Ok(target) => {
+                        println!("🏷️  {} labels sent to {}", saved_ids.len(), target);
+                        String::new()
+                    }
+                    Err(e) => {
+                        println!("❌ Labels not printed: {}", e);
+                        format!(" Labels not printed: {}", e)
+                    }
+                }
+            } else {
+                String::new()
+            };
+            let printed = match shelf_error {
+                Some(e) => format!("{} Shelf not set: {}", printed, e),
+                None => printed,
+            };
             
             // Show result
             if error_count == 0 {
-                ui.set_info_alert(format!("✅ {} packages saved successfully!", saved_count).into());
+                ui.set_info_alert(format!("✅ {} packages saved successfully!{}", saved_count, printed).into());
             } else {
-                ui.set_info_alert(format!("⚠️  {} saved, {} failed", saved_count, error_count).into());
+                ui.set_info_alert(format!("⚠️  {} saved, {} failed{}", saved_count, error_count, printed).into());
             }
             
             println!("\n📊 Final: {} saved, {} errors", saved_count, error_count);
@@ -355,7 +504,8 @@ fn main() -> Result<(), Box<dyn Error>> {
             let mut resident_strings = Vec::new();
             let mut ids = Vec::new();
             
-            for resident in &row_data {
+            // Cards can only be linked to residents still living here
+            for resident in row_data.iter().filter(|r| r.status == "active") {
                 resident_strings.push(format!("Apt {} - {} {}", 
                     resident.apt, 
                     resident.first_name, 
@@ -374,6 +524,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         let ui_handle = ui.as_weak();
         let db = Arc::clone(&db);
         let resident_ids = Rc::clone(&resident_ids);
+        let resident_table = Rc::clone(&resident_table);
         move |user_data: ResidentData|{
             let ui = ui_handle.unwrap();
             
@@ -387,31 +538,44 @@ fn main() -> Result<(), Box<dyn Error>> {
                 }
             };
             
-            match db_guard.execute("
-                INSERT INTO resident (apt, first_name, last_name, linked) VALUES (?1, ?2, ?3, ?4)
-                ", rusqlite::params![user_data.apt.to_string(), user_data.first_name.to_string(), 
-                    user_data.last_name.to_string(), user_data.linked]) {
-                Ok(_) => {
-                    let row_data = get_residents_data(&*db_guard).unwrap();
-                    let (table_model, ids) = convert_resident_data_vec(row_data);
-                    *resident_ids.borrow_mut() = ids.clone();
-                    ui.set_residents_data(table_model);
-                    ui.set_info_alert("Resident Added".into());
+            // Other tenants are typed as "First Last, First Last"
+            let others = ui.get_move_in_others();
+            let mut tenants = vec![(user_data.first_name.as_str(), user_data.last_name.as_str())];
+            tenants.extend(others.split(',').map(str::trim).filter(|name| !name.is_empty()).map(|name| {
+                name.rsplit_once(char::is_whitespace).unwrap_or((name, ""))
+            }));
+
+            match tenancy::move_in(&db_guard, user_data.apt.as_str(), &tenants, user_data.moved_in.as_str()) {
+                Ok(ids) => {
+                    resident_table.refresh();
+                    ui.set_info_alert(if ids.len() == 1 {
+                        "Resident Added".into()
+                    } else {
+                        format!("{} residents added", ids.len()).into()
+                    });
                     
                     drop(db_guard);
                     update_resident_list(&ui, &db, &resident_ids);
                 }
                 Err(e) => {
                     println!("Failed to insert resident: {}", e);
+                    ui.set_info_alert(format!("Error: {}", e).into());
                 }
             }
         }
     });
 
+    // Removals are soft; the last one can be undone from the snackbar for a short while
+    let last_removed: Rc<RefCell<Option<trash::Removed>>> = Rc::new(RefCell::new(None));
+    ui.set_undo_seconds(trash::UNDO_WINDOW_SECS as i32);
+
     ui.on_remove_resident({
         let ui_handle = ui.as_weak();
         let db = Arc::clone(&db);
         let resident_ids = Rc::clone(&resident_ids);
+        let resident_table = Rc::clone(&resident_table);
+        let card_table = Rc::clone(&card_table);
+        let last_removed = Rc::clone(&last_removed);
         move |resident_id: i32| {
             let ui = ui_handle.unwrap();
             
@@ -420,27 +584,16 @@ fn main() -> Result<(), Box<dyn Error>> {
             match delete_resident(&*db_guard, resident_id as u32) {
                 Ok(_) => {
                     // Refresh the residents data
-                    let row_data = get_residents_data(&*db_guard).unwrap();
-                    let (table_model, ids) = convert_resident_data_vec(row_data);
-                    *resident_ids.borrow_mut() = ids.clone();
-                    ui.set_residents_data(table_model);
-                    
-                    // Show success message
-                    ui.set_info_alert("Resident has been removed".into());
+                    resident_table.refresh();
+                    card_table.refresh();
                     
                     drop(db_guard);
                     
                     // Update resident list for dropdown
                     update_resident_list(&ui, &db, &resident_ids);
                     
-                    // Auto-hide alert after 10 seconds
-                    let ui_weak_clone = ui_handle.clone();
-                    std::thread::spawn(move || {
-                        std::thread::sleep(std::time::Duration::from_secs(10));
-                        if let Some(ui) = ui_weak_clone.upgrade() {
-                            ui.set_info_alert("".into());
-                        }
-                    });
+                    *last_removed.borrow_mut() = Some(trash::Removed::Resident(resident_id as u32));
+                    ui.invoke_show_undo("Resident has been removed".into());
                 }
                 Err(e) => {
                     println!("Failed to delete resident: {}", e);
@@ -450,6 +603,200 @@ fn main() -> Result<(), Box<dyn Error>> {
         } 
     });
 
+    ui.on_remove_package({
+        let ui_handle = ui.as_weak();
+        let db = Arc::clone(&db);
+        let package_table = Rc::clone(&package_table);
+        let last_removed = Rc::clone(&last_removed);
+        move |package_id: i32| {
+            let ui = ui_handle.unwrap();
+            let db = db.lock().unwrap();
+
+            match delete_package(&db, package_id as u32) {
+                Ok(()) => {
+                    package_table.refresh();
+                    if let Ok(package_count) = count_pending_packages(&db) {
+                        ui.set_package_count(package_count as i32);
+                    }
+                    *last_removed.borrow_mut() = Some(trash::Removed::Package(package_id as u32));
+                    ui.invoke_show_undo("Package has been removed".into());
+                }
+                Err(e) => {
+                    println!("Failed to delete package: {}", e);
+                    ui.set_info_alert(format!("Failed to delete package: {}", e).into());
+                }
+            }
+        }
+    });
+
+    ui.on_undo_remove({
+        let ui_handle = ui.as_weak();
+        let db = Arc::clone(&db);
+        let resident_ids = Rc::clone(&resident_ids);
+        let tables = [
+            Rc::clone(&resident_table), Rc::clone(&card_table), Rc::clone(&package_table),
+        ];
+        let last_removed = Rc::clone(&last_removed);
+        move || {
+            let ui = ui_handle.unwrap();
+            let Some(removed) = last_removed.borrow_mut().take() else { return };
+
+            let result = {
+                let db = db.lock().unwrap();
+                trash::restore(&db, removed).and_then(|()| count_pending_packages(&db))
+            };
+            match result {
+                Ok(package_count) => {
+                    println!("↩️  Restored {:?}", removed);
+                    for table in &tables {
+                        table.refresh();
+                    }
+                    ui.set_package_count(package_count as i32);
+                    update_resident_list(&ui, &db, &resident_ids);
+                }
+                Err(e) => ui.set_info_alert(format!("Error: {}", e).into()),
+            }
+        }
+    });
+
+    ui.on_purge_deleted({
+        let db = Arc::clone(&db);
+        let app_config = Arc::clone(&app_config);
+        move || -> slint::SharedString {
+            let (days, photos) = {
+                let config = app_config.lock().unwrap();
+                (config.retention.purge_deleted_after_days, config.photo_settings())
+            };
+            match trash::purge_deleted(&db.lock().unwrap(), days, &photos) {
+                Ok(report) => {
+                    println!("🗑️  {}", report.summary());
+                    report.summary().into()
+                }
+                Err(e) => format!("Error: {}", e).into(),
+            }
+        }
+    });
+
+    ui.on_erase_resident({
+        let db = Arc::clone(&db);
+        let app_config = Arc::clone(&app_config);
+        let tables = [
+            Rc::clone(&resident_table), Rc::clone(&card_table), Rc::clone(&package_table), Rc::clone(&log_table),
+        ];
+        move |resident_id: i32| -> slint::SharedString {
+            let photos = app_config.lock().unwrap().photo_settings();
+            match privacy::erase_resident(&db.lock().unwrap(), resident_id as u32, &photos) {
+                Ok(report) => {
+                    println!("🕶️  Resident {}: {}", resident_id, report.summary());
+                    for table in &tables {
+                        table.refresh();
+                    }
+                    report.summary().into()
+                }
+                Err(e) => format!("Error: {}", e).into(),
+            }
+        }
+    });
+
+    // Move-out: revoke cards, then return or reassign the packages left behind
+    let move_out_packages: Rc<VecModel<PackageData>> = Rc::new(VecModel::default());
+    ui.set_move_out_packages(ModelRc::from(Rc::clone(&move_out_packages)));
+
+    ui.on_move_out_resident({
+        let ui_handle = ui.as_weak();
+        let db = Arc::clone(&db);
+        let resident_ids = Rc::clone(&resident_ids);
+        let resident_table = Rc::clone(&resident_table);
+        let card_table = Rc::clone(&card_table);
+        let move_out_packages = Rc::clone(&move_out_packages);
+        move |resident_id: i32| -> slint::SharedString {
+            let ui = ui_handle.unwrap();
+            let result = tenancy::move_out(&db.lock().unwrap(), resident_id as u32);
+
+            match result {
+                Ok(moved) => {
+                    println!("🚚 {} {} moved out of Apt {}", moved.resident.first_name, moved.resident.last_name, moved.resident.apt);
+                    let mut summary = format!(
+                        "{} card(s) revoked. {} package(s) still pending for Apt {}.",
+                        moved.revoked_cards, moved.pending_packages.len(), moved.resident.apt
+                    );
+                    if moved.remaining_residents > 0 {
+                        summary.push_str(&format!(
+                            " {} other resident(s) still live there, so some may be theirs.", moved.remaining_residents
+                        ));
+                    }
+                    ui.set_move_out_summary(summary.into());
+                    move_out_packages.set_vec(moved.pending_packages.into_iter().map(|pkg| PackageData {
+                        id: pkg.id as i32,
+                        apt: pkg.apt.into(),
+                        package_number: pkg.package_number.into(),
+                        barcode: pkg.barcode.into(),
+                        comment: pkg.comment.unwrap_or_default().into(),
+                        date_time: pkg.date_time.into(),
+                        carrier: pkg.carrier.unwrap_or_default().into(),
+                    }).collect::<Vec<_>>());
+
+                    resident_table.refresh();
+                    card_table.refresh();
+                    update_resident_list(&ui, &db, &resident_ids);
+                    "".into()
+                }
+                Err(e) => {
+                    println!("❌ Move-out failed: {}", e);
+                    format!("Error: {}", e).into()
+                }
+            }
+        }
+    });
+
+    // Drops a handled package from the move-out list and updates the counts
+    fn package_handled(ui: &AppWindow, db: &rusqlite::Connection, packages: &VecModel<PackageData>, package_id: i32) {
+        if let Some(row) = packages.iter().position(|pkg| pkg.id == package_id) {
+            packages.remove(row);
+        }
+        if let Ok(package_count) = count_pending_packages(db) {
+            ui.set_package_count(package_count as i32);
+        }
+    }
+
+    ui.on_return_package({
+        let ui_handle = ui.as_weak();
+        let db = Arc::clone(&db);
+        let package_table = Rc::clone(&package_table);
+        let move_out_packages = Rc::clone(&move_out_packages);
+        move |package_id: i32| -> slint::SharedString {
+            let ui = ui_handle.unwrap();
+            let db = db.lock().unwrap();
+            match tenancy::return_package(&db, package_id as u32, "Addressee moved out") {
+                Ok(()) => {
+                    package_handled(&ui, &db, &move_out_packages, package_id);
+                    package_table.refresh();
+                    "".into()
+                }
+                Err(e) => format!("Error: {}", e).into(),
+            }
+        }
+    });
+
+    ui.on_reassign_package({
+        let ui_handle = ui.as_weak();
+        let db = Arc::clone(&db);
+        let package_table = Rc::clone(&package_table);
+        let move_out_packages = Rc::clone(&move_out_packages);
+        move |package_id: i32, apt: slint::SharedString| -> slint::SharedString {
+            let ui = ui_handle.unwrap();
+            let db = db.lock().unwrap();
+            match tenancy::reassign_package(&db, package_id as u32, &apt) {
+                Ok(()) => {
+                    package_handled(&ui, &db, &move_out_packages, package_id);
+                    package_table.refresh();
+                    "".into()
+                }
+                Err(e) => format!("Error: {}", e).into(),
+            }
+        }
+    });
+
     // Handle resident selection from dropdown
     ui.on_get_resident_at_index({
         let ui_handle = ui.as_weak();
@@ -470,6 +817,9 @@ fn main() -> Result<(), Box<dyn Error>> {
                         first_name: one_resident_info.first_name.clone().into(),
                         last_name: one_resident_info.last_name.clone().into(),
                         linked: one_resident_info.linked,
+                        status: one_resident_info.status.clone().into(),
+                        moved_in: one_resident_info.moved_in.clone().unwrap_or_default().into(),
+                        moved_out: one_resident_info.moved_out.clone().unwrap_or_default().into(),
                     };
                     
                     println!("✓ Resident: {} {} (ID: {}, Apt: {})", 
@@ -493,6 +843,9 @@ fn main() -> Result<(), Box<dyn Error>> {
                 first_name: "".into(),
                 last_name: "".into(),
                 linked: false,
+                status: "".into(),
+                moved_in: "".into(),
+                moved_out: "".into(),
             }
         }
     });
@@ -503,6 +856,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         let db = Arc::clone(&db);
         let verification_paused = Arc::clone(&verification_paused);
         let nfc_reader_lock = Arc::clone(&nfc_reader_lock);
+        let app_config = Arc::clone(&app_config);
         
         move |resident_id: i32, apt: slint::SharedString| -> slint::SharedString {
             println!("\n🔗 Link card called with:");
@@ -514,6 +868,11 @@ fn main() -> Result<(), Box<dyn Error>> {
                 println!("✗ {}", error);
                 return error.into();
             }
+
+            // Check before touching the card; add_card checks again when saving
+            if let Err(e) = operator::require_permission(&db.lock().unwrap(), operator::Permission::LinkCard) {
+                return format!("Error: {}", e).into();
+            }
             
             // STEP 1: Pause verification and acquire NFC lock
             println!("\n⏸️  Pausing automatic verification...");
@@ -534,15 +893,11 @@ fn main() -> Result<(), Box<dyn Error>> {
             std::thread::sleep(std::time::Duration::from_millis(500));
             
             // STEP 2: Create reader for linking
-            let reader_result = NFCReader::new().and_then(|mut r| {
+            let nfc_config = app_config.lock().unwrap().effective();
+            let reader_result = NFCReader::from_config(&nfc_config.nfc).and_then(|mut r| {
                 let readers = r.list_readers()?;
-                if let Some(contactless_reader) = readers.iter()
-                    .find(|name| name.contains("Contactless")) 
-                {
-                    r.select_reader(contactless_reader)?;
-                    Ok(r)
-                } else if let Some(first_reader) = readers.first() {
-                    r.select_reader(first_reader)?;
+                if let Some(chosen_reader) = nfc_config.reader.choose(&readers) {
+                    r.select_reader(chosen_reader)?;
                     Ok(r)
                 } else {
                     Err("No readers found".into())
@@ -564,7 +919,7 @@ fn main() -> Result<(), Box<dyn Error>> {
             println!("Waiting for card... Please tap the card on the reader.");
             
             // STEP 3: Read card UID
-            let uid = match reader.wait_for_card(15) {
+            let uid = match reader.wait_for_card(nfc_config.nfc.wait_timeout_secs) {
                 Ok(uid) => {
                     println!("✓ Card detected!");
                     println!("  UID: {}", uid);
@@ -634,25 +989,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                 }
             };
             
-            let db_result = db_guard.execute(
-                "INSERT INTO card (resident_id, apt, added_date, hash) VALUES (?1, ?2, ?3, ?4)",
-                rusqlite::params![resident_id, apt.as_str(), added_date, hash],
-            ).and_then(|_| {
-                db_guard.execute(
-                    "UPDATE resident SET linked = 1 WHERE id = ?1", 
-                    [resident_id]
-                )?;
-
-                let log_action = format!(
-                    "Card linked: UID={}, Hash={}, Resident ID={}, Apt={}", 
-                    uid, &hash[..16], resident_id, apt
-                );
-                db_guard.execute(
-                    "INSERT INTO log (action_type, action, date_time) VALUES (?1, ?2, ?3)",
-                    rusqlite::params!["linked", log_action, added_date],
-                )?;
-                Ok(())
-            });
+            let db_result = add_card(&db_guard, resident_id as u32, apt.as_str(), &uid, &added_date, &hash);
             
             drop(db_guard);
             
@@ -685,47 +1022,51 @@ fn main() -> Result<(), Box<dyn Error>> {
         let ui_handle = ui.as_weak();
         let db = Arc::clone(&db);
         let resident_ids = Rc::clone(&resident_ids);
+        let resident_table = Rc::clone(&resident_table);
         move || {
             let ui = ui_handle.unwrap();
-            let db_guard = db.lock().unwrap();
-            
-            if let Ok(row_data) = get_residents_data(&*db_guard) {
-                let (table_model, ids) = convert_resident_data_vec(row_data);
-                *resident_ids.borrow_mut() = ids;
-                ui.set_residents_data(table_model);
-            }
-            
-            drop(db_guard);
+            resident_table.set_filter(RecordFilter::default());
             update_resident_list(&ui, &db, &resident_ids);
         }
     });
 
     ui.on_show_card_data({
-        let ui_handle = ui.as_weak();
-        let db = Arc::clone(&db);
-        let card_ids = Rc::clone(&card_ids);
+        let card_table = Rc::clone(&card_table);
         move || {
-            let ui = ui_handle.unwrap();
-            let db = db.lock().unwrap();
-            if let Ok(row_data) = get_cards_data(&*db) {
-                let (table_model, ids) = convert_card_data_vec(row_data, &db);
-                *card_ids.borrow_mut() = ids;
-                ui.set_cards_data(table_model);
-            }
+            card_table.set_filter(RecordFilter::default());
         }
     });
 
     ui.on_show_log_data({
+        let log_table = Rc::clone(&log_table);
+        move || {
+            log_table.refresh();
+        }
+    });
+
+    ui.on_refresh_dashboard({
         let ui_handle = ui.as_weak();
         let db = Arc::clone(&db);
-        let log_ids = Rc::clone(&log_ids);
         move || {
             let ui = ui_handle.unwrap();
-            let db = db.lock().unwrap();
-            if let Ok(row_data) = get_logs_data(&*db) {
-                let (table_model, ids) = convert_log_data_vec(row_data);
-                *log_ids.borrow_mut() = ids;
-                ui.set_logs_data(table_model);
+            let dashboard = stats::dashboard(&db.lock().unwrap(), Local::now().naive_local());
+            match dashboard {
+                Ok(dashboard) => {
+                    ui.set_dashboard(DashboardData {
+                        pending_total: dashboard.pending_total() as i32,
+                        median_pickup: dashboard.median_pickup_hours.map(format_hours).unwrap_or_else(|| "-".to_string()).into(),
+                        unknown_card_taps: dashboard.unknown_card_taps as i32,
+                        refused_card_taps: dashboard.refused_card_taps as i32,
+                        pending_by_age: stat_bars(&dashboard.pending_by_age),
+                        intake_per_day: stat_bars(&dashboard.intake_per_day),
+                        top_apartments: stat_bars(&dashboard.top_apartments),
+                        carriers: stat_bars(&dashboard.carriers),
+                    });
+                }
+                Err(e) => {
+                    println!("❌ Could not load the dashboard: {}", e);
+                    ui.set_info_alert(format!("Error: {}", e).into());
+                }
             }
         }
     });
@@ -733,22 +1074,56 @@ fn main() -> Result<(), Box<dyn Error>> {
     ui.on_show_one_resident_info({
         let ui_handle = ui.as_weak();
         let db = Arc::clone(&db);
-        let resident_ids = Rc::clone(&resident_ids);
+        let resident_table = Rc::clone(&resident_table);
         move |index| {
             let ui = ui_handle.unwrap();
             
-            let ids = resident_ids.borrow();
-            if let Some(&db_id) = ids.get(index as usize) {
+            if let Some(db_id) = resident_table.row_id(index as usize) {
                 let db = db.lock().unwrap();
-                if let Ok(one_resident_info) = get_resident_info(&*db, db_id) {
-                    let slint_resident = ResidentData {
-                        id: one_resident_info.id as i32,
-                        apt: one_resident_info.apt.clone().into(),
-                        first_name: one_resident_info.first_name.clone().into(),
-                        last_name: one_resident_info.last_name.clone().into(),
-                        linked: one_resident_info.linked,
-                    };
-                    ui.set_resident_info(slint_resident);
+                match get_resident_profile(&db, db_id, RESIDENT_HISTORY_LIMIT) {
+                    Ok(profile) => {
+                        let resident = profile.resident;
+                        ui.set_resident_info(ResidentData {
+                            id: resident.id as i32,
+                            apt: resident.apt.into(),
+                            first_name: resident.first_name.into(),
+                            last_name: resident.last_name.into(),
+                            linked: resident.linked,
+                            status: resident.status.into(),
+                            moved_in: resident.moved_in.unwrap_or_default().into(),
+                            moved_out: resident.moved_out.unwrap_or_default().into(),
+                        });
+
+                        ui.set_resident_cards(table_rows(profile.cards.into_iter().map(|card| vec![
+                            card.id.to_string(),
+                            card.added_date,
+                            card.status,
+                            card.revoked_date.unwrap_or_default(),
+                        ])));
+                        let signatures: Vec<slint::SharedString> = profile.packages.iter()
+                            .map(|package| package.signature.as_deref()
+                                .and_then(|strokes| signature::decode_strokes(strokes).ok())
+                                .map(|strokes| signature::path_commands(&strokes))
+                                .unwrap_or_default()
+                                .into())
+                            .collect();
+                        ui.set_resident_package_signatures(ModelRc::from(Rc::new(VecModel::from(signatures))));
+                        ui.set_resident_packages(table_rows(profile.packages.into_iter().map(|package| vec![
+                            package.package_number,
+                            package.carrier.unwrap_or_default(),
+                            package.date_time,
+                            package.status,
+                            package.collection_time.unwrap_or_default(),
+                            if package.signature.is_some() { "Yes".to_string() } else { String::new() },
+                        ])));
+                        ui.set_resident_events(table_rows(profile.events.into_iter().map(|event| vec![
+                            event.date_time,
+                            event.action_type,
+                            event.action,
+                            event.operator,
+                        ])));
+                    }
+                    Err(e) => println!("⚠️  Could not load resident {}: {}", db_id, e),
                 }
             }
         }
@@ -757,12 +1132,11 @@ fn main() -> Result<(), Box<dyn Error>> {
     ui.on_show_one_card_info({
         let ui_handle = ui.as_weak();
         let db = Arc::clone(&db);
-        let card_ids = Rc::clone(&card_ids);
+        let card_table = Rc::clone(&card_table);
         move |index| {
             let ui = ui_handle.unwrap();
             
-            let ids = card_ids.borrow();
-            if let Some(&db_id) = ids.get(index as usize) {
+            if let Some(db_id) = card_table.row_id(index as usize) {
                 let db = db.lock().unwrap();
                 if let Ok(one_card_info) = get_card_info(&*db, db_id) {
                     let slint_card = CardData {
@@ -771,6 +1145,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                         apt: one_card_info.apt.clone().into(),
                         added_date: one_card_info.added_date.clone().into(),
                         hash: one_card_info.hash.clone().into(),
+                        status: one_card_info.status.clone().into(),
                     };
                     ui.set_card_info(slint_card);
                 }
@@ -781,12 +1156,11 @@ fn main() -> Result<(), Box<dyn Error>> {
     ui.on_show_one_log_info({
         let ui_handle = ui.as_weak();
         let db = Arc::clone(&db);
-        let log_ids = Rc::clone(&log_ids);
+        let log_table = Rc::clone(&log_table);
         move |index| {
             let ui = ui_handle.unwrap();
             
-            let ids = log_ids.borrow();
-            if let Some(&db_id) = ids.get(index as usize) {
+            if let Some(db_id) = log_table.row_id(index as usize) {
                 let db = db.lock().unwrap();
                 if let Ok(one_log_info) = get_log_info(&*db, db_id) {
                     let slint_log = LogData {
@@ -794,6 +1168,8 @@ fn main() -> Result<(), Box<dyn Error>> {
                         action_type: one_log_info.action_type.clone().into(),
                         action: one_log_info.action.clone().into(),
                         date_time: one_log_info.date_time.clone().into(),
+                        operator: one_log_info.operator.clone().into(),
+                        payload: one_log_info.payload.clone().into(),
                     };
                     ui.set_log_info(slint_log);
                 }
@@ -801,58 +1177,47 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
     });
 
-    ui.on_search_residents({
-        let ui_handle = ui.as_weak();
+    ui.on_verify_log_chain({
         let db = Arc::clone(&db);
-        let resident_ids = Rc::clone(&resident_ids);
+        move || {
+            let db = db.lock().unwrap();
+            match audit::verify_chain(&db) {
+                Ok(report) => {
+                    if report.is_intact() {
+                        println!("🔗 {}", report.summary());
+                    } else {
+                        println!("⚠️ {}", report.summary());
+                    }
+                    report.summary().into()
+                }
+                Err(e) => format!("Could not verify log: {}", e).into(),
+            }
+        }
+    });
+
+    ui.on_search_residents({
+        let resident_table = Rc::clone(&resident_table);
         move |query, tab_index| {
-            let ui = ui_handle.unwrap();
-            
             if tab_index != 0 {
                 return;
             }
-            
-            let db = db.lock().unwrap();
-            let row_data = if query.is_empty() {
-                get_residents_data(&*db).unwrap()
-            } else {
-                search_residents(&*db, query.as_str()).unwrap()
-            };
-            
-            let (table_model, ids) = convert_resident_data_vec(row_data);
-            *resident_ids.borrow_mut() = ids;
-            ui.set_residents_data(table_model);
+            resident_table.set_filter(RecordFilter { text: filter::non_empty(&query), ..Default::default() });
         }
     });
 
     ui.on_search_cards({
-        let ui_handle = ui.as_weak();
-        let db = Arc::clone(&db);
-        let card_ids = Rc::clone(&card_ids);
+        let card_table = Rc::clone(&card_table);
         move |query, tab_index| {
-            let ui = ui_handle.unwrap();
-            
             if tab_index != 1 {
                 return;
             }
-            
-            let db = db.lock().unwrap();
-            let row_data = if query.is_empty() {
-                get_cards_data(&*db).unwrap()
-            } else {
-                search_cards(&*db, query.as_str()).unwrap()
-            };
-            
-            let (table_model, ids) = convert_card_data_vec(row_data, &db);
-            *card_ids.borrow_mut() = ids;
-            ui.set_cards_data(table_model);
+            card_table.set_filter(RecordFilter { text: filter::non_empty(&query), ..Default::default() });
         }
     });
 
     ui.on_search_logs({
         let ui_handle = ui.as_weak();
-        let db = Arc::clone(&db);
-        let log_ids = Rc::clone(&log_ids);
+        let log_table = Rc::clone(&log_table);
         move |query, tab_index| {
             let ui = ui_handle.unwrap();
             
@@ -860,23 +1225,29 @@ fn main() -> Result<(), Box<dyn Error>> {
                 return;
             }
             
-            let db = db.lock().unwrap();
-            let row_data = if query.is_empty() {
-                get_logs_data(&*db).unwrap()
-            } else {
-                search_logs(&*db, query.as_str()).unwrap()
-            };
-            
-            let (table_model, ids) = convert_log_data_vec(row_data);
-            *log_ids.borrow_mut() = ids;
-            ui.set_logs_data(table_model);
+            let filter = record_filter(&ui.get_log_filter(), &query);
+            if let Err(e) = filter.validate() {
+                ui.set_info_alert(format!("Error: {}", e).into());
+                return;
+            }
+            log_table.set_filter(filter);
+        }
+    });
+
+    // Clicking a column header sorts that tab's table in the database
+    ui.on_sort_table({
+        let tables = [Rc::clone(&resident_table), Rc::clone(&card_table), Rc::clone(&package_table), Rc::clone(&log_table)];
+        move |tab_index, column, ascending| {
+            if let Some(table) = tables.get(tab_index as usize) {
+                table.set_sort(SortOrder { column: Some(column as usize), descending: !ascending });
+            }
         }
     });
 
     ui.on_add_package({
         let ui_handle = ui.as_weak();
         let db = Arc::clone(&db);
-        let package_ids = Rc::clone(&package_ids);
+        let package_table = Rc::clone(&package_table);
         move |package_data: PackageData| {
             let ui = ui_handle.unwrap();
             
@@ -894,12 +1265,10 @@ fn main() -> Result<(), Box<dyn Error>> {
                 package_data.package_number.as_str(),
                 package_data.barcode.as_str(),  // Add barcode here!
                 comment,
+                Some(package_data.carrier.as_str()),
             ) {
                 Ok(_) => {
-                    let row_data = get_packages_data(&*db_guard).unwrap();
-                    let (table_model, ids) = convert_package_data_vec(row_data);
-                    *package_ids.borrow_mut() = ids;
-                    ui.set_packages_data(table_model);
+                    package_table.refresh();
                     ui.set_info_alert("Package Added Successfully".into());
                     
                     println!("✅ Package added for Apt {}", package_data.apt);
@@ -915,19 +1284,15 @@ fn main() -> Result<(), Box<dyn Error>> {
     ui.on_show_packages_data({
         let ui_handle = ui.as_weak();
         let db = Arc::clone(&db);
-        let package_ids = Rc::clone(&package_ids);
+        let package_table = Rc::clone(&package_table);
         move || {
             let ui = ui_handle.unwrap();
+            package_table.refresh();
+
             let db_guard = db.lock().unwrap();
-            
-            if let Ok(row_data) = get_packages_data(&*db_guard) {
-                let package_count = row_data.len();
-                let (table_model, ids) = convert_package_data_vec(row_data);
-                *package_ids.borrow_mut() = ids;
-                ui.set_packages_data(table_model);
+            if let Ok(package_count) = count_pending_packages(&db_guard) {
                 ui.set_package_count(package_count as i32);
-                
-                println!("📦 Showing {} pending packages", package_count);
+                println!("📦 {} pending packages", package_count);
             }
         }
     });
@@ -935,12 +1300,12 @@ fn main() -> Result<(), Box<dyn Error>> {
     ui.on_show_one_package_info({
         let ui_handle = ui.as_weak();
         let db = Arc::clone(&db);
-        let package_ids = Rc::clone(&package_ids);
+        let app_config = Arc::clone(&app_config);
+        let package_table = Rc::clone(&package_table);
         move |index| {
             let ui = ui_handle.unwrap();
             
-            let ids = package_ids.borrow();
-            if let Some(&db_id) = ids.get(index as usize) {
+            if let Some(db_id) = package_table.row_id(index as usize) {
                 let db = db.lock().unwrap();
                 if let Ok(pkg_info) = get_package_info(&*db, db_id) {
                     let slint_package = PackageData {
@@ -952,8 +1317,13 @@ fn main() -> Result<(), Box<dyn Error>> {
                             .unwrap_or_else(|| "N/A".to_string())
                             .into(),
                         date_time: pkg_info.date_time.into(),
+                        carrier: pkg_info.carrier.unwrap_or_default().into(),
                     };
                     ui.set_package_info(slint_package);
+                    ui.set_photo_path("".into());
+                    show_package_condition(&ui, &db, &app_config.lock().unwrap().photo_settings(), db_id);
+                    let signature = signature::package_signature(&db, db_id).unwrap_or_default();
+                    ui.set_package_signature(signature.map(|strokes| signature::path_commands(&strokes)).unwrap_or_default().into());
                 }
             }
         }
@@ -961,8 +1331,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     
     ui.on_search_packages({
         let ui_handle = ui.as_weak();
-        let db = Arc::clone(&db);
-        let package_ids = Rc::clone(&package_ids);
+        let package_table = Rc::clone(&package_table);
         move |query, tab_index| {
             let ui = ui_handle.unwrap();
             
@@ -970,16 +1339,12 @@ fn main() -> Result<(), Box<dyn Error>> {
                 return;
             }
             
-            let db = db.lock().unwrap();
-            let row_data = if query.is_empty() {
-                get_packages_data(&*db).unwrap()
-            } else {
-                search_packages(&*db, query.as_str()).unwrap()
-            };
-            
-            let (table_model, ids) = convert_package_data_vec(row_data);
-            *package_ids.borrow_mut() = ids;
-            ui.set_packages_data(table_model);
+            let filter = record_filter(&ui.get_package_filter(), &query);
+            if let Err(e) = filter.validate() {
+                ui.set_info_alert(format!("Error: {}", e).into());
+                return;
+            }
+            package_table.set_filter(filter);
         }
     });
     
@@ -987,7 +1352,10 @@ fn main() -> Result<(), Box<dyn Error>> {
     ui.on_collect_package_with_card({
         let ui_handle = ui.as_weak();
         let db = Arc::clone(&db);
+        let verification_paused = Arc::clone(&verification_paused);
         let nfc_reader_lock = Arc::clone(&nfc_reader_lock);
+        let app_config = Arc::clone(&app_config);
+        let package_table = Rc::clone(&package_table);
         
         move |package_id: i32, _apt: slint::SharedString| -> slint::SharedString {
             println!("\n📦 Package Collection Started");
@@ -1002,10 +1370,11 @@ fn main() -> Result<(), Box<dyn Error>> {
             // Wait for card
             println!("🔍 Waiting for resident card...");
             
-            let reader_result = NFCReader::new().and_then(|mut r| {
+            let nfc_config = app_config.lock().unwrap().effective();
+            let reader_result = NFCReader::from_config(&nfc_config.nfc).and_then(|mut r| {
                 let readers = r.list_readers()?;
-                if let Some(first_reader) = readers.first() {
-                    r.select_reader(first_reader)?;
+                if let Some(chosen_reader) = nfc_config.reader.choose(&readers) {
+                    r.select_reader(chosen_reader)?;
                     Ok(r)
                 } else {
                     Err("No readers found".into())
@@ -1023,7 +1392,7 @@ fn main() -> Result<(), Box<dyn Error>> {
             };
             
             // Read card hash
-            let card_hash = match reader.wait_for_card(15) {
+            let card_hash = match reader.wait_for_card(nfc_config.nfc.wait_timeout_secs) {
                 Ok(uid) => {
                     println!("✅ Card detected: {}", uid);
                     match reader.read_hash_from_card(4) {
@@ -1051,20 +1420,17 @@ fn main() -> Result<(), Box<dyn Error>> {
             drop(_nfc_lock);
             
             // Process collection in database
+            let policy = app_config.lock().unwrap().effective().signature_policy();
             let db_guard = db.lock().unwrap();
-            match collect_package(&*db_guard, package_id as u32, &card_hash) {
+            match collect_package(&db_guard, package_id as u32, &card_hash, policy) {
                 Ok(resident_name) => {
                     println!("✅ Package collected by: {}", resident_name);
                     
                     // Refresh package list
-                    if let Ok(row_data) = get_packages_data(&*db_guard) {
-                        let (table_model, ids) = convert_package_data_vec(row_data);
-                        drop(db_guard);
-                        
-                        if let Some(ui) = ui_handle.upgrade() {
-                            ui.set_packages_data(table_model);
-                            ui.set_info_alert(format!("Package collected by {}", resident_name).into());
-                        }
+                    drop(db_guard);
+                    package_table.refresh();
+                    if let Some(ui) = ui_handle.upgrade() {
+                        ui.set_info_alert(format!("Package collected by {}", resident_name).into());
                     }
                     
                     *verification_paused.lock().unwrap() = false;
@@ -1083,7 +1449,8 @@ fn main() -> Result<(), Box<dyn Error>> {
 ui.on_collect_selected_packages({
     let ui_handle = ui.as_weak();
     let db = Arc::clone(&db);
-    let package_ids = Rc::clone(&package_ids);
+    let app_config = Arc::clone(&app_config);
+    let package_table = Rc::clone(&package_table);
     
     move |selected_ids: slint::SharedString, card_hash: slint::SharedString| {
         println!("\n📦 Collecting selected packages...");
@@ -1102,14 +1469,35 @@ ui.on_collect_selected_packages({
         
         println!("  Package IDs to collect: {:?}", ids);
         
+        let policy = app_config.lock().unwrap().effective().signature_policy();
         let db_guard = db.lock().unwrap();
         let mut collected_count = 0;
         let mut resident_name = String::new();
         let mut failed_packages = Vec::new();
+
+        // One signature covers the whole handover; it is dropped again if nothing was collected
+        let Ok(tx) = db_guard.unchecked_transaction() else { return };
+        let strokes = ui_handle.upgrade()
+            .map(|ui| signature::strokes_from_path(&ui.get_signature_path()))
+            .unwrap_or_default();
+        let signature_id = if strokes.is_empty() {
+            None
+        } else {
+            match signature::save_signature(&tx, &strokes) {
+                Ok(id) => Some(id),
+                Err(e) => {
+                    println!("  ❌ Signature not saved: {}", e);
+                    if let Some(ui) = ui_handle.upgrade() {
+                        ui.set_info_alert(format!("Error: {}", e).into());
+                    }
+                    return;
+                }
+            }
+        };
         
         // Collect each selected package
         for pkg_id in &ids {
-            match collect_package(&db_guard, *pkg_id, card_hash.as_str()) {
+            match collect_package_signed(&tx, *pkg_id, card_hash.as_str(), signature_id, policy) {
                 Ok(name) => {
                     collected_count += 1;
                     resident_name = name;
@@ -1122,17 +1510,22 @@ ui.on_collect_selected_packages({
             }
         }
         
+        if collected_count > 0 {
+            if let Err(e) = tx.commit() {
+                println!("  ❌ Collection not saved: {}", e);
+                collected_count = 0;
+            }
+        } else {
+            drop(tx);
+        }
+
         // Refresh package data
-        let row_data = get_packages_data(&db_guard).unwrap_or_default();
-        let package_count = row_data.len();
-        let (table_model, new_ids) = convert_package_data_vec(row_data);
-        *package_ids.borrow_mut() = new_ids;
-        
+        let package_count = count_pending_packages(&db_guard).unwrap_or_default();
         drop(db_guard);
+        package_table.refresh();
         
         // Update UI
         if let Some(ui) = ui_handle.upgrade() {
-            ui.set_packages_data(table_model);
             ui.set_package_count(package_count as i32);
             ui.set_show_package_selection(false);
             ui.set_verification_type(0);
@@ -1231,19 +1624,62 @@ ui.on_deselect_all_packages({
     }
 });
 
-// Helper callback: Build ID list and call collection
-ui.on_collect_selected_packages_callback({
+// Confirm by scan: tick the resident's package a scanned box belongs to
+ui.on_scan_for_collection({
     let ui_handle = ui.as_weak();
-    move || {
+    move |code: slint::SharedString| {
         if let Some(ui) = ui_handle.upgrade() {
+            let code = code.trim();
+            if code.is_empty() {
+                return;
+            }
             let packages = ui.get_resident_packages_for_collection();
             let selected = ui.get_selected_packages();
-            let card_hash = ui.get_current_card_hash();
-            
-            // Build comma-separated ID list
-            let mut ids = Vec::new();
-            for i in 0..packages.row_count() {
-                if let Some(pkg) = packages.row_data(i) {
+            let entries: Vec<(u32, slint::SharedString)> = packages.iter()
+                .map(|pkg| (pkg.id as u32, pkg.barcode))
+                .collect();
+
+            match label::match_scan(code, entries.iter().map(|(id, barcode)| (*id, barcode.as_str()))) {
+                Some(index) if selected.row_data(index) == Some(true) => {
+                    println!("📦 Package #{} was already scanned", index + 1);
+                }
+                Some(index) => {
+                    selected.set_row_data(index, true);
+                    ui.set_selection_count(selected.iter().filter(|is_selected| *is_selected).count() as i32);
+                    println!("✅ Scanned package #{} for handover", index + 1);
+                }
+                None => {
+                    println!("❌ Scanned box {} is not for Apt {}", code, ui.get_last_verified_apt());
+                    ui.set_scan_mismatch(format!(
+                        "{} is not one of the packages waiting for Apt {}. Put the box aside before collecting.",
+                        code, ui.get_last_verified_apt()
+                    ).into());
+                }
+            }
+        }
+    }
+});
+
+// Helper callback: Build ID list and call collection
+ui.on_collect_selected_packages_callback({
+    let ui_handle = ui.as_weak();
+    move || {
+        if let Some(ui) = ui_handle.upgrade() {
+            // A box that didn't match is still on the counter
+            if ui.get_confirm_by_scan() && !ui.get_scan_mismatch().is_empty() {
+                return;
+            }
+            if ui.get_signature_mode() == "required" && ui.get_signature_path().is_empty() {
+                return;
+            }
+            let packages = ui.get_resident_packages_for_collection();
+            let selected = ui.get_selected_packages();
+            let card_hash = ui.get_current_card_hash();
+            
+            // Build comma-separated ID list
+            let mut ids = Vec::new();
+            for i in 0..packages.row_count() {
+                if let Some(pkg) = packages.row_data(i) {
                     if i < selected.row_count() {
                         if let Some(is_selected) = selected.row_data(i) {
                             if is_selected {
@@ -1294,27 +1730,829 @@ ui.on_toggle_package_selection({
     }
 });
 
-    update_resident_list(&ui, &db, &resident_ids);
+    // Card revocation and manual package collection (admin only, checked in the library)
+    ui.on_revoke_card({
+        let ui_handle = ui.as_weak();
+        let db = Arc::clone(&db);
+        let card_table = Rc::clone(&card_table);
+        move |card_id: i32| -> slint::SharedString {
+            let ui = ui_handle.unwrap();
+            let db_guard = db.lock().unwrap();
+
+            match revoke_card(&db_guard, card_id as u32) {
+                Ok(()) => {
+                    println!("🚫 Card {} revoked", card_id);
+                    card_table.refresh();
+                    if let Ok(card) = get_card_info(&db_guard, card_id as u32) {
+                        let mut card_info = ui.get_card_info();
+                        card_info.status = card.status.into();
+                        ui.set_card_info(card_info);
+                    }
+                    "Card revoked".into()
+                }
+                Err(e) => {
+                    println!("❌ Failed to revoke card: {}", e);
+                    format!("Error: {}", e).into()
+                }
+            }
+        }
+    });
+
+    ui.on_override_collect_package({
+        let ui_handle = ui.as_weak();
+        let db = Arc::clone(&db);
+        let app_config = Arc::clone(&app_config);
+        let package_table = Rc::clone(&package_table);
+        move |package_id: i32, reason: slint::SharedString, signature_path: slint::SharedString| -> slint::SharedString {
+            let ui = ui_handle.unwrap();
+            let policy = app_config.lock().unwrap().effective().signature_policy();
+            let db_guard = db.lock().unwrap();
+
+            // The signature is only kept if the package is collected
+            let result = db_guard.unchecked_transaction().and_then(|tx| {
+                let strokes = signature::strokes_from_path(&signature_path);
+                let signature_id = if strokes.is_empty() { None } else { Some(signature::save_signature(&tx, &strokes)?) };
+                collect_package_override(&tx, package_id as u32, &reason, signature_id, policy)?;
+                tx.commit()
+            });
+            match result {
+                Ok(()) => {
+                    println!("⚠️  Package {} collected by manual override", package_id);
+                    if let Ok(package_count) = count_pending_packages(&db_guard) {
+                        ui.set_package_count(package_count as i32);
+                    }
+                    package_table.refresh();
+                    "Package marked as collected".into()
+                }
+                Err(e) => {
+                    println!("❌ Manual collection failed: {}", e);
+                    format!("Error: {}", e).into()
+                }
+            }
+        }
+    });
+
+    // Operator login, first-run setup and session lock
+    ui.on_login({
+        let ui_handle = ui.as_weak();
+        let db = Arc::clone(&db);
+        let last_activity = Arc::clone(&last_activity);
+        move |username: slint::SharedString, password: slint::SharedString| -> slint::SharedString {
+            let ui = ui_handle.unwrap();
+            let db = db.lock().unwrap();
+
+            let result = operator::authenticate(&db, &username, &password)
+                .and_then(|staff| operator::login(&db, &staff).map(|_| staff));
+            match result {
+                Ok(staff) => {
+                    println!("👤 {} logged in", staff.display_name);
+                    *last_activity.lock().unwrap() = Instant::now();
+                    ui.set_operator_role(staff.role.name().into());
+                    ui.set_operator_name(staff.display_name.into());
+                    ui.set_logged_in(true);
+                    ui.invoke_refresh_property();
+                    "".into()
+                }
+                Err(e) => {
+                    println!("❌ Login failed for '{}'", username);
+                    format!("{}", e).into()
+                }
+            }
+        }
+    });
+
+    // Building switcher. Switching or logging in as an operator limited to one
+    // building changes what every table shows.
+    ui.on_refresh_property({
+        let ui_handle = ui.as_weak();
+        let db = Arc::clone(&db);
+        let app_config = Arc::clone(&app_config);
+        let resident_ids = Rc::clone(&resident_ids);
+        let tables = [
+            Rc::clone(&resident_table), Rc::clone(&card_table),
+            Rc::clone(&package_table), Rc::clone(&log_table),
+        ];
+        move || {
+            let ui = ui_handle.unwrap();
+            {
+                let db_guard = db.lock().unwrap();
+                let names: Vec<slint::SharedString> = property::accessible_properties(&db_guard)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|building| building.name.into())
+                    .collect();
+                ui.set_property_names(ModelRc::from(Rc::new(VecModel::from(names))));
+                if let Ok(building) = property::current_property(&db_guard) {
+                    ui.set_current_property(building.name.into());
+                }
+                if let Ok(package_count) = count_pending_packages(&db_guard) {
+                    ui.set_package_count(package_count as i32);
+                }
+            }
+
+            apply_collection_settings(&ui, &app_config.lock().unwrap().effective().collection);
+
+            for table in &tables {
+                table.refresh();
+            }
+            update_resident_list(&ui, &db, &resident_ids);
+            if ui.get_current_tab() == 4 {
+                ui.invoke_refresh_dashboard();
+            }
+        }
+    });
+
+    ui.on_switch_property({
+        let ui_handle = ui.as_weak();
+        let db = Arc::clone(&db);
+        let app_config = Arc::clone(&app_config);
+        move |name: slint::SharedString| -> slint::SharedString {
+            let ui = ui_handle.unwrap();
+            let switched = {
+                let db = db.lock().unwrap();
+                property::find_property(&db, &name).and_then(|building| property::switch_property(&db, building.id))
+            };
+
+            match switched {
+                Ok(building) => {
+                    println!("🏢 Working in {}", building.name);
+                    app_config.lock().unwrap().property.current = building.name;
+                    ui.invoke_refresh_property();
+                    "".into()
+                }
+                Err(e) => {
+                    // Put the switcher back on the building still in use
+                    ui.invoke_refresh_property();
+                    format!("Error: {}", e).into()
+                }
+            }
+        }
+    });
+
+    ui.on_setup_first_operator({
+        let ui_handle = ui.as_weak();
+        let db = Arc::clone(&db);
+        move |username: slint::SharedString, display_name: slint::SharedString,
+              password: slint::SharedString, confirm: slint::SharedString| -> slint::SharedString {
+            let ui = ui_handle.unwrap();
+            if password != confirm {
+                return "Passwords do not match".into();
+            }
+
+            let db = db.lock().unwrap();
+            // Only allowed while no account exists, so it can't be used to add admins later
+            match operator::count_operators(&db) {
+                Ok(0) => {}
+                Ok(_) => return "An operator account already exists".into(),
+                Err(e) => return format!("{}", e).into(),
+            }
+
+            match operator::create_operator(&db, &username, &display_name, &password, operator::Role::Admin) {
+                Ok(_) => {
+                    ui.set_needs_setup(false);
+                    "".into()
+                }
+                Err(e) => format!("{}", e).into(),
+            }
+        }
+    });
+
+    ui.on_logout({
+        let ui_handle = ui.as_weak();
+        let db = Arc::clone(&db);
+        move || {
+            let ui = ui_handle.unwrap();
+            if let Err(e) = operator::logout(&db.lock().unwrap(), "manual") {
+                println!("❌ Logout failed: {}", e);
+            }
+            ui.set_operator_name("".into());
+            ui.set_operator_role("".into());
+            ui.set_logged_in(false);
+        }
+    });
+
+    ui.on_link_staff_card({
+        let db = Arc::clone(&db);
+        let verification_paused = Arc::clone(&verification_paused);
+        let nfc_reader_lock = Arc::clone(&nfc_reader_lock);
+        let app_config = Arc::clone(&app_config);
+        move || -> slint::SharedString {
+            let staff = match operator::current_operator(&db.lock().unwrap()) {
+                Ok(Some(staff)) => staff,
+                Ok(None) => return "Error: Nobody is logged in".into(),
+                Err(e) => return format!("Error: {}", e).into(),
+            };
+
+            *verification_paused.lock().unwrap() = true;
+            let nfc_lock = nfc_reader_lock.lock().unwrap();
+            std::thread::sleep(Duration::from_millis(500));
+
+            let nfc_config = app_config.lock().unwrap().effective();
+            let card_result = NFCReader::from_config(&nfc_config.nfc).and_then(|mut r| {
+                let readers = r.list_readers()?;
+                let chosen_reader = nfc_config.reader.choose(&readers).ok_or("No readers found")?;
+                r.select_reader(chosen_reader)?;
+
+                println!("Waiting for staff card for {}...", staff.display_name);
+                let uid = r.wait_for_card(nfc_config.nfc.wait_timeout_secs)?;
+                let added_date = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+                let hash = operator::staff_card_hash(&uid, staff.id, &added_date);
+                r.write_hash_to_card(&hash, 4)?;
+                Ok(hash)
+            });
+
+            drop(nfc_lock);
+            *verification_paused.lock().unwrap() = false;
+
+            let result = card_result
+                .and_then(|hash| Ok(operator::link_operator_card(&db.lock().unwrap(), staff.id, &hash)?));
+            match result {
+                Ok(()) => {
+                    println!("✓ Staff card linked to {}", staff.display_name);
+                    format!("Staff card linked to {}", staff.display_name).into()
+                }
+                Err(e) => {
+                    println!("✗ Failed to link staff card: {}", e);
+                    format!("Error: {}", e).into()
+                }
+            }
+        }
+    });
+
+    // Apartment registry: autocomplete for the assign flow and registering new apartments
+    ui.on_suggest_apartments({
+        let db = Arc::clone(&db);
+        move |typed| {
+            let suggestions = unit::suggest_units(&db.lock().unwrap(), &typed, APT_SUGGESTION_LIMIT)
+                .unwrap_or_default()
+                .into_iter()
+                .map(slint::SharedString::from)
+                .collect::<Vec<_>>();
+            ModelRc::new(VecModel::from(suggestions))
+        }
+    });
+
+    ui.on_add_apartments({
+        let db = Arc::clone(&db);
+        move |spec| -> slint::SharedString {
+            match unit::add_units_from_spec(&db.lock().unwrap(), "", &spec) {
+                Ok(added) => {
+                    println!("🏢 {} apartments registered", added);
+                    format!("{} apartments registered", added).into()
+                }
+                Err(e) => format!("Error: {}", e).into(),
+            }
+        }
+    });
+
+    // Settings screen backed by the TOML config file
+    ui.on_load_settings({
+        let ui_handle = ui.as_weak();
+        let app_config = Arc::clone(&app_config);
+        move || {
+            let ui = ui_handle.unwrap();
+            let current = app_config.lock().unwrap().clone();
+
+            ui.set_settings(SettingsData {
+                db_path: current.database.path.into(),
+                reader_name: current.reader.name.into(),
+                reader_prefer: current.reader.prefer.into(),
+                wait_timeout_secs: current.nfc.wait_timeout_secs.to_string().into(),
+                poll_interval_ms: current.nfc.poll_interval_ms.to_string().into(),
+                ntag_start_page: current.nfc.ntag_start_page.to_string().into(),
+                led_success_ms: current.nfc.led_success_ms.to_string().into(),
+                led_error_ms: current.nfc.led_error_ms.to_string().into(),
+                led_processing_ms: current.nfc.led_processing_ms.to_string().into(),
+                backup_enabled: current.backup.enabled,
+                backup_dir: current.backup.dir.into(),
+                backup_interval_hours: current.backup.interval_hours.to_string().into(),
+                backup_keep_daily: current.backup.keep_daily.to_string().into(),
+                backup_keep_weekly: current.backup.keep_weekly.to_string().into(),
+                export_dir: current.export.dir.into(),
+                auto_lock_minutes: current.security.auto_lock_minutes.to_string().into(),
+                purge_deleted_after_days: current.retention.purge_deleted_after_days.to_string().into(),
+                anonymise_log_after_months: current.retention.anonymise_log_after_months.to_string().into(),
+                report_enabled: current.report.enabled,
+                report_dir: current.report.dir.into(),
+                report_daily_at: current.report.daily_at.into(),
+                overdue_after_days: current.report.overdue_after_days.to_string().into(),
+                label_auto_print: current.label.auto_print,
+                label_format: current.label.format.into(),
+                label_barcode: current.label.barcode.into(),
+                label_output: current.label.output.into(),
+                label_notices: current.label.notices,
+                confirm_by_scan: current.collection.confirm_by_scan,
+                signature: current.collection.signature.into(),
+                scanner_mode: current.scanner.mode.into(),
+                scanner_device: current.scanner.device.into(),
+                photo_dir: current.photo.dir.into(),
+                photo_watch_dir: current.photo.watch_dir.into(),
+            });
+
+            let readers = NFCReader::new()
+                .and_then(|r| r.list_readers())
+                .unwrap_or_default();
+            ui.set_available_readers(readers.join(", ").into());
+        }
+    });
+
+    ui.on_save_settings({
+        let ui_handle = ui.as_weak();
+        let db = Arc::clone(&db);
+        let app_config = Arc::clone(&app_config);
+        move |settings: SettingsData, relink_confirmed: bool| -> slint::SharedString {
+            fn number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+                value.trim().parse().map_err(|_| format!("{} must be a whole number", name))
+            }
+
+            let parsed = (|| -> Result<config::Config, String> {
+                Ok(config::Config {
+                    database: config::DatabaseConfig {
+                        path: settings.db_path.trim().to_string(),
+                        key_file: app_config.lock().unwrap().database.key_file.clone(),
+                    },
+                    reader: config::ReaderConfig {
+                        name: settings.reader_name.trim().to_string(),
+                        prefer: settings.reader_prefer.trim().to_string(),
+                    },
+                    nfc: config::NfcConfig {
+                        wait_timeout_secs: number("Card wait", &settings.wait_timeout_secs)?,
+                        poll_interval_ms: number("Poll interval", &settings.poll_interval_ms)?,
+                        ntag_start_page: number("NTAG start page", &settings.ntag_start_page)?,
+                        led_success_ms: number("Green LED", &settings.led_success_ms)?,
+                        led_error_ms: number("Red LED", &settings.led_error_ms)?,
+                        led_processing_ms: number("Orange LED", &settings.led_processing_ms)?,
+                    },
+                    backup: config::BackupConfig {
+                        enabled: settings.backup_enabled,
+                        dir: settings.backup_dir.trim().to_string(),
+                        interval_hours: number("Backup interval", &settings.backup_interval_hours)?,
+                        keep_daily: number("Keep daily", &settings.backup_keep_daily)?,
+                        keep_weekly: number("Keep weekly", &settings.backup_keep_weekly)?,
+                    },
+                    export: config::ExportConfig { dir: settings.export_dir.trim().to_string() },
+                    security: config::SecurityConfig {
+                        auto_lock_minutes: number("Auto-lock", &settings.auto_lock_minutes)?,
+                    },
+                    property: app_config.lock().unwrap().property.clone(),
+                    report: config::ReportConfig {
+                        enabled: settings.report_enabled,
+                        dir: settings.report_dir.trim().to_string(),
+                        daily_at: settings.report_daily_at.trim().to_string(),
+                        overdue_after_days: number("Overdue after", &settings.overdue_after_days)?,
+                    },
+                    label: config::LabelConfig {
+                        auto_print: settings.label_auto_print,
+                        format: settings.label_format.trim().to_lowercase(),
+                        barcode: settings.label_barcode.trim().to_lowercase(),
+                        output: settings.label_output.trim().to_string(),
+                        notices: settings.label_notices,
+                    },
+                    collection: config::CollectionConfig {
+                        confirm_by_scan: settings.confirm_by_scan,
+                        signature: settings.signature.trim().to_lowercase(),
+                    },
+                    scanner: config::ScannerConfig {
+                        mode: settings.scanner_mode.trim().to_lowercase(),
+                        device: settings.scanner_device.trim().to_string(),
+                        ..app_config.lock().unwrap().scanner.clone()
+                    },
+                    photo: config::PhotoConfig {
+                        dir: settings.photo_dir.trim().to_string(),
+                        watch_dir: settings.photo_watch_dir.trim().to_string(),
+                    },
+                    retention: config::RetentionConfig {
+                        purge_deleted_after_days: number("Purge after", &settings.purge_deleted_after_days)?,
+                        anonymise_log_after_months: number("Anonymise log after", &settings.anonymise_log_after_months)?,
+                        anonymise_log_types: app_config.lock().unwrap().retention.anonymise_log_types.clone(),
+                    },
+                })
+            })();
+
+            let new_config = match parsed {
+                Ok(new_config) => new_config,
+                Err(e) => return format!("Error: {}", e).into(),
+            };
 
+            // Cards written at the old NTAG page stop working, so that takes a second save
+            if !relink_confirmed {
+                let current = app_config.lock().unwrap().clone();
+                match config::unreadable_cards(&db.lock().unwrap(), &current, &new_config) {
+                    Ok(0) => {}
+                    Ok(cards) => {
+                        ui_handle.unwrap().set_confirm_card_layout(true);
+                        return format!(
+                            "{} linked cards hold their hash at NTAG page {} and must be linked again after moving it to page {}. Save again to confirm.",
+                            cards, current.nfc.ntag_start_page, new_config.nfc.ntag_start_page
+                        ).into();
+                    }
+                    Err(e) => return format!("Error: {}", e).into(),
+                }
+            }
+
+            match config::update_config(&db.lock().unwrap(), std::path::Path::new(config::CONFIG_PATH), &new_config, relink_confirmed) {
+                Ok(()) => {
+                    apply_collection_settings(&ui_handle.unwrap(), &new_config.effective().collection);
+                    *app_config.lock().unwrap() = new_config;
+                    println!("⚙️  Settings saved to {}", config::CONFIG_PATH);
+                    "Settings saved".into()
+                }
+                Err(e) => format!("Error: {}", e).into(),
+            }
+        }
+    });
+
+    // Export the current tab's table to CSV/JSON
+    ui.on_export_data({
+        let db = Arc::clone(&db);
+        let app_config = Arc::clone(&app_config);
+        move |tab_index: i32, format: slint::SharedString, from_date: slint::SharedString,
+              to_date: slint::SharedString, status: slint::SharedString, apt: slint::SharedString| -> slint::SharedString {
+            let table = match tab_index {
+                0 => export::ExportTable::Residents,
+                2 => export::ExportTable::Packages,
+                3 => export::ExportTable::Logs,
+                _ => return "Error: Export is available for the People, Pack and Logs tabs".into(),
+            };
+            let format = export::ExportFormat::from_name(format.as_str()).unwrap_or(export::ExportFormat::Csv);
+            let filter = export::ExportFilter::from_strings(&from_date, &to_date, &status, &apt);
+            let export_dir = app_config.lock().unwrap().effective().export.dir;
+            let path = export::default_export_path(std::path::Path::new(&export_dir), table, format);
+
+            let db_guard = db.lock().unwrap();
+            match export::export_table(&db_guard, table, &filter, format, &path) {
+                Ok(count) => {
+                    println!("📤 Exported {} {} to {}", count, table.name(), path.display());
+                    format!("Exported {} rows to {}", count, path.display()).into()
+                }
+                Err(e) => {
+                    println!("❌ Export failed: {}", e);
+                    format!("Error: {}", e).into()
+                }
+            }
+        }
+    });
+
+    // Package report for a day or a range of days, as HTML and PDF
+    ui.on_run_report({
+        let db = Arc::clone(&db);
+        let app_config = Arc::clone(&app_config);
+        move |from: slint::SharedString, to: slint::SharedString| -> slint::SharedString {
+            let settings = app_config.lock().unwrap().report_settings();
+            let db = db.lock().unwrap();
+            let result = report::parse_range(&from, &to)
+                .and_then(|(from, to)| report::package_report(&db, from, to, settings.overdue_after_days))
+                .map_err(|e| -> Box<dyn Error> { e.into() })
+                .and_then(|report| report::write_report(&report, &settings.dir));
+            match result {
+                Ok((html_path, pdf_path)) => {
+                    println!("📊 Report written to {}", pdf_path.display());
+                    format!("Saved {} and {}", html_path.display(), pdf_path.display()).into()
+                }
+                Err(e) => {
+                    println!("❌ Report failed: {}", e);
+                    format!("Error: {}", e).into()
+                }
+            }
+        }
+    });
+
+    // Reprint the label of one package, with a notice slip if those are enabled
+    ui.on_print_package_label({
+        let db = Arc::clone(&db);
+        let app_config = Arc::clone(&app_config);
+        move |package_id: i32| -> slint::SharedString {
+            let settings = app_config.lock().unwrap().label_settings();
+            match label::print_labels(&db.lock().unwrap(), &[package_id as u32], &settings) {
+                Ok(target) => {
+                    println!("🏷️  Label for package {} sent to {}", package_id, target);
+                    format!("Label sent to {}", target).into()
+                }
+                Err(e) => {
+                    println!("❌ Label not printed: {}", e);
+                    format!("Error: {}", e).into()
+                }
+            }
+        }
+    });
+
+    ui.on_attach_package_photo({
+        let ui_handle = ui.as_weak();
+        let db = Arc::clone(&db);
+        let app_config = Arc::clone(&app_config);
+        move |package_id: i32, file: slint::SharedString| -> slint::SharedString {
+            let ui = ui_handle.unwrap();
+            let settings = app_config.lock().unwrap().photo_settings();
+            let db = db.lock().unwrap();
+            let package_id = package_id as u32;
+            let stage = photo_stage(&db, package_id);
+            match photo::attach_photo(&db, &settings, package_id, std::path::Path::new(file.trim()), stage) {
+                Ok(photo) => {
+                    println!("📷 Photo {} added to package {}", photo.hash, package_id);
+                    ui.set_photo_path("".into());
+                    show_package_condition(&ui, &db, &settings, package_id);
+                    "Photo added".into()
+                }
+                Err(e) => format!("Error: {}", e).into(),
+            }
+        }
+    });
+
+    ui.on_attach_watched_photos({
+        let ui_handle = ui.as_weak();
+        let db = Arc::clone(&db);
+        let app_config = Arc::clone(&app_config);
+        move |package_id: i32| -> slint::SharedString {
+            let ui = ui_handle.unwrap();
+            let settings = app_config.lock().unwrap().photo_settings();
+            let db = db.lock().unwrap();
+            let package_id = package_id as u32;
+            let stage = photo_stage(&db, package_id);
+            match photo::attach_watched_photos(&db, &settings, &[package_id], stage) {
+                Ok(count) => {
+                    println!("📷 {} photo(s) imported for package {}", count, package_id);
+                    show_package_condition(&ui, &db, &settings, package_id);
+                    format!("{} photo(s) added", count).into()
+                }
+                Err(e) => format!("Error: {}", e).into(),
+            }
+        }
+    });
+
+    // Photos taken at the counter, one of each package being handed over
+    ui.on_attach_watched_photos_to_selection({
+        let ui_handle = ui.as_weak();
+        let db = Arc::clone(&db);
+        let app_config = Arc::clone(&app_config);
+        move || -> slint::SharedString {
+            let ui = ui_handle.unwrap();
+            let packages = ui.get_resident_packages_for_collection();
+            let selected = ui.get_selected_packages();
+            let ids: Vec<u32> = packages.iter()
+                .zip(selected.iter())
+                .filter(|(_, is_selected)| *is_selected)
+                .map(|(package, _)| package.id as u32)
+                .collect();
+
+            let settings = app_config.lock().unwrap().photo_settings();
+            match photo::attach_watched_photos(&db.lock().unwrap(), &settings, &ids, photo::PhotoStage::Collection) {
+                Ok(count) => {
+                    println!("📷 {} photo(s) added to {} package(s) at collection", count, ids.len());
+                    format!("{} photo(s) added to {} package(s)", count, ids.len()).into()
+                }
+                Err(e) => format!("Error: {}", e).into(),
+            }
+        }
+    });
+
+    ui.on_set_package_damaged({
+        let ui_handle = ui.as_weak();
+        let db = Arc::clone(&db);
+        move |package_id: i32, damaged: bool| -> slint::SharedString {
+            match photo::set_damaged(&db.lock().unwrap(), package_id as u32, damaged, "") {
+                Ok(()) if damaged => "Package marked damaged on arrival".into(),
+                Ok(()) => "Damage flag cleared".into(),
+                Err(e) => {
+                    ui_handle.unwrap().set_package_damaged(!damaged);
+                    format!("Error: {}", e).into()
+                }
+            }
+        }
+    });
+
+    // Subject access request: everything stored about one resident, as JSON and HTML
+    ui.on_export_subject_access({
+        let db = Arc::clone(&db);
+        let app_config = Arc::clone(&app_config);
+        move |resident_id: i32| -> slint::SharedString {
+            let (export_dir, photos) = {
+                let config = app_config.lock().unwrap();
+                (config.effective().export.dir, config.photo_settings())
+            };
+            match privacy::export_subject_access(
+                &db.lock().unwrap(), resident_id as u32, std::path::Path::new(&export_dir), &photos,
+            ) {
+                Ok((json_path, html_path)) => {
+                    println!("📤 Exported data held about resident {} to {}", resident_id, html_path.display());
+                    format!("Saved {} and {}", json_path.display(), html_path.display()).into()
+                }
+                Err(e) => {
+                    println!("❌ Export failed: {}", e);
+                    format!("Error: {}", e).into()
+                }
+            }
+        }
+    });
+
+    // Lock the session after a period without mouse or keyboard input
+    let auto_lock_timer = slint::Timer::default();
+    match DeviceState::checked_new() {
+        Some(device_state) => {
+            let ui_handle = ui.as_weak();
+            let db = Arc::clone(&db);
+            let app_config = Arc::clone(&app_config);
+            let last_activity = Arc::clone(&last_activity);
+            let mut last_input = None;
+            auto_lock_timer.start(slint::TimerMode::Repeated, Duration::from_secs(1), move || {
+                let input = (device_state.get_mouse().coords, device_state.get_keys());
+                if last_input.as_ref() != Some(&input) {
+                    last_input = Some(input);
+                    *last_activity.lock().unwrap() = Instant::now();
+                    return;
+                }
+
+                let Some(ui) = ui_handle.upgrade() else { return };
+                let minutes = app_config.lock().unwrap().effective().security.auto_lock_minutes;
+                if !ui.get_logged_in() || minutes == 0 {
+                    return;
+                }
+
+                if last_activity.lock().unwrap().elapsed() >= Duration::from_secs(minutes * 60) {
+                    if let Err(e) = operator::logout(&db.lock().unwrap(), "inactivity") {
+                        println!("❌ Auto-lock logout failed: {}", e);
+                    }
+                    ui.set_operator_name("".into());
+                    ui.set_operator_role("".into());
+                    ui.set_logged_in(false);
+                    println!("🔒 Session locked after {} minutes without input", minutes);
+                }
+            });
+        }
+        None => println!("⚠️  Input monitoring unavailable - auto-lock disabled"),
+    }
+
+    // New images in the photo watch folder, offered in the package info panel and at collection
+    if let Some(watch_dir) = app_config.lock().unwrap().photo_settings().watch_dir {
+        let ui_handle = ui.as_weak();
+        photo::start_photo_watcher(watch_dir, move |images| {
+            let ui_handle = ui_handle.clone();
+            let _ = slint::invoke_from_event_loop(move || {
+                if let Some(ui) = ui_handle.upgrade() {
+                    ui.set_watched_photo_count(images.len() as i32);
+                }
+            });
+        });
+    }
+
+    // Barcode scans from a keyboard-wedge scanner, whichever tab or field has focus
+    if let Some(settings) = app_config.lock().unwrap().wedge_settings() {
+        let last_typed: Arc<Mutex<Option<Instant>>> = Arc::new(Mutex::new(None));
+        ui.global::<TextEntry>().on_typed({
+            let last_typed = Arc::clone(&last_typed);
+            move || *last_typed.lock().unwrap() = Some(Instant::now())
+        });
+
+        // A grabbed scanner device doesn't type into the window, the global listener's keyboards do
+        let types_into_window = settings.source == scanner::ScanSource::Global;
+        let ui_handle = ui.as_weak();
+        scanner::start_wedge_listener(settings, move |code| {
+            let ui_handle = ui_handle.clone();
+            let last_typed = Arc::clone(&last_typed);
+            let _ = slint::invoke_from_event_loop(move || {
+                // Typed into a text field of ours, which has the scan already
+                let typed_at = *last_typed.lock().unwrap();
+                if types_into_window && typed_at.is_some_and(|at| at.elapsed() < scanner::FIELD_ENTRY_WINDOW) {
+                    return;
+                }
+                if let Some(ui) = ui_handle.upgrade() {
+                    route_wedge_scan(&ui, &code);
+                }
+            });
+        });
+    }
+
+    ui.invoke_refresh_property();
     ui.invoke_show_residents_data();
     ui.run()?;
 
     Ok(())
 }
 
+// Rows for a small read-only StandardTableView
+fn table_rows(rows: impl Iterator<Item = Vec<String>>) -> ModelRc<ModelRc<StandardListViewItem>> {
+    let rows: Vec<ModelRc<StandardListViewItem>> = rows
+        .map(|cells| {
+            let items: Vec<StandardListViewItem> = cells.into_iter().map(|cell| StandardListViewItem::from(cell.as_str())).collect();
+            ModelRc::new(VecModel::from(items))
+        })
+        .collect();
+    ModelRc::new(VecModel::from(rows))
+}
+
+// Dashboard bars sized against the largest count in the chart
+fn stat_bars(counts: &[stats::Count]) -> ModelRc<StatBar> {
+    let max = counts.iter().map(|count| count.value).max().unwrap_or(0).max(1) as f32;
+    let bars: Vec<StatBar> = counts.iter()
+        .map(|count| StatBar {
+            label: count.label.clone().into(),
+            value: count.value as i32,
+            fraction: count.value as f32 / max,
+        })
+        .collect();
+    ModelRc::from(Rc::new(VecModel::from(bars)))
+}
+
+// e.g. "45 min", "5.5 h" or "2.3 days"
+fn format_hours(hours: f64) -> String {
+    if hours < 1.0 {
+        format!("{:.0} min", hours * 60.0)
+    } else if hours < 48.0 {
+        format!("{:.1} h", hours)
+    } else {
+        format!("{:.1} days", hours / 24.0)
+    }
+}
+
+// Pickup settings of the building in use
+fn apply_collection_settings(ui: &AppWindow, collection: &config::CollectionConfig) {
+    ui.set_confirm_by_scan(collection.confirm_by_scan);
+    ui.set_signature_mode(collection.signature.trim().into());
+}
+
+// Damage flag and photo thumbnails of the package in the info panel
+fn show_package_condition(ui: &AppWindow, db: &rusqlite::Connection, settings: &photo::PhotoSettings, package_id: u32) {
+    match photo::package_condition(db, settings, package_id) {
+        Ok(condition) => {
+            let photos: Vec<PackagePhoto> = condition.photos.iter()
+                .map(|photo| {
+                    let image = slint::Image::load_from_path(&photo.path);
+                    PackagePhoto {
+                        caption: match image {
+                            Ok(_) => format!("{} {}", photo.stage, &photo.added_at[..photo.added_at.len().min(10)]),
+                            Err(_) => "File missing".to_string(),
+                        }.into(),
+                        image: image.unwrap_or_default(),
+                    }
+                })
+                .collect();
+            ui.set_package_damaged(condition.damaged);
+            ui.set_package_photos(ModelRc::from(Rc::new(VecModel::from(photos))));
+        }
+        Err(e) => println!("❌ Could not load package condition: {}", e),
+    }
+}
+
+// Photos of a package still in the room are intake photos, later ones are taken at collection
+fn photo_stage(db: &rusqlite::Connection, package_id: u32) -> photo::PhotoStage {
+    match get_package_info(db, package_id) {
+        Ok(package) if package.status != "pending" => photo::PhotoStage::Collection,
+        _ => photo::PhotoStage::Intake,
+    }
+}
+
+// A scan from the wedge listener that didn't go into a text field: confirms a box in the
+// collection popup, otherwise joins the packages waiting to be assigned
+fn route_wedge_scan(ui: &AppWindow, code: &str) {
+    if !ui.get_logged_in() {
+        return;
+    }
+    if ui.get_show_package_selection() {
+        if ui.get_confirm_by_scan() {
+            ui.invoke_scan_for_collection(code.into());
+        }
+        return;
+    }
+
+    let carrier = ui.get_scan_carrier();
+    let carrier = if carrier == "Auto" { "".into() } else { carrier };
+    ui.invoke_quick_scan_package(code.into(), "".into(), carrier);
+    if !ui.get_show_package_form() {
+        ui.set_info_alert(format!("Scanned {} ({} waiting to be assigned)", code, ui.get_scan_count()).into());
+    }
+}
+
+// Converts the UI filter for the packages or logs tab, with the search box text
+fn record_filter(data: &FilterData, query: &str) -> filter::RecordFilter {
+    filter::RecordFilter {
+        from_date: filter::non_empty(&data.from_date),
+        to_date: filter::non_empty(&data.to_date),
+        status: filter::non_empty(&data.status),
+        apt: filter::non_empty(&data.apt),
+        carrier: filter::non_empty(&data.carrier),
+        operator: filter::non_empty(&data.operator),
+        text: filter::non_empty(query),
+    }
+}
+
 // Updated verification function that respects the NFC reader lock
 fn start_automatic_verification(
     reader_name: String, 
     db: Arc<Mutex<rusqlite::Connection>>,
     ui_weak: slint::Weak<AppWindow>,
     verification_paused: Arc<Mutex<bool>>,
-    nfc_reader_lock: Arc<Mutex<()>>
+    nfc_reader_lock: Arc<Mutex<()>>,
+    app_config: Arc<Mutex<config::Config>>,
+    last_activity: Arc<Mutex<Instant>>
 ) {
     println!("🔍 Card monitoring active - waiting for cards...\n");
     
     let mut last_uid: Option<String> = None;
     
     loop {
+        // Re-read each pass so changes from the settings screen apply immediately
+        let nfc_config = app_config.lock().unwrap().nfc.clone();
+        let poll_interval = std::time::Duration::from_millis(nfc_config.poll_interval_ms);
+
         // Check if paused
         if *verification_paused.lock().unwrap() {
             if last_uid.is_some() {
@@ -1330,13 +2568,13 @@ fn start_automatic_verification(
             Ok(lock) => lock,
             Err(_) => {
                 // NFC reader is being used by linking process
-                std::thread::sleep(std::time::Duration::from_millis(300));
+                std::thread::sleep(poll_interval);
                 continue;
             }
         };
         
         // We have the lock, create reader
-        let reader = match NFCReader::new().and_then(|mut r| {
+        let reader = match NFCReader::from_config(&nfc_config).and_then(|mut r| {
             r.select_reader(&reader_name)?;
             Ok(r)
         }) {
@@ -1362,7 +2600,7 @@ fn start_automatic_verification(
                     
                     // Acquire lock again for reading hash
                     if let Ok(_nfc_lock) = nfc_reader_lock.try_lock() {
-                        let hash_result = NFCReader::new()
+                        let hash_result = NFCReader::from_config(&nfc_config)
                             .and_then(|mut r| {
                                 r.select_reader(&reader_name)?;
                                 r.read_hash_from_card(4)
@@ -1372,25 +2610,54 @@ fn start_automatic_verification(
                         if let Ok(card_hash) = hash_result {
                             if !card_hash.is_empty() {
                                 println!("🔐 Hash from card: {}", card_hash);
+
+                                // Staff cards log the operator in instead of verifying a resident
+                                let staff_login = match db.try_lock() {
+                                    Ok(db) => operator::authenticate_card(&db, &card_hash)
+                                        .ok()
+                                        .flatten()
+                                        .filter(|staff| operator::login(&db, staff).is_ok()),
+                                    Err(_) => None,
+                                };
+                                if let Some(staff) = staff_login {
+                                    println!("👤 Staff card: {} logged in", staff.display_name);
+                                    *last_activity.lock().unwrap() = Instant::now();
+                                    let _ = ui_weak.upgrade_in_event_loop(move |ui| {
+                                        ui.set_operator_role(staff.role.name().into());
+                                        ui.set_operator_name(staff.display_name.into());
+                                        ui.set_logged_in(true);
+                                        ui.invoke_refresh_property();
+                                    });
+                                    last_uid = Some(uid);
+                                    std::thread::sleep(poll_interval);
+                                    continue;
+                                }
                                 
                                 // Database lookup with minimal lock time
                                 let verification_result = {
                                     match db.try_lock() {
                                         Ok(db) => {
                                             let result = db.query_row(
-                                                "SELECT c.resident_id, c.apt, r.first_name, r.last_name, c.hash 
+                                                "SELECT c.resident_id, c.apt, r.first_name, r.last_name, c.id, r.property_id
                                                  FROM card c 
                                                  JOIN resident r ON c.resident_id = r.id
-                                                 WHERE c.hash = ?1",
+                                                 WHERE c.hash = ?1 AND c.status = 'active' AND c.deleted_at IS NULL",
                                                 [&card_hash],
                                                 |row| Ok((
                                                     row.get::<_, u32>(0)?, 
                                                     row.get::<_, String>(1)?, 
                                                     row.get::<_, String>(2)?, 
                                                     row.get::<_, String>(3)?, 
-                                                    row.get::<_, String>(4)?
+                                                    row.get::<_, u32>(4)?,
+                                                    row.get::<_, u32>(5)?
                                                 ))
-                                            );
+                                            ).and_then(|(resident_id, apt, first_name, last_name, card_id, card_property)| {
+                                                // Cards from another building only open this reader where allowed,
+                                                // and never list packages, which belong to this building
+                                                let home = card_property == property::current_property_id(&db)?;
+                                                let accepted = home || property::accepts_cards_from(&db, card_property)?;
+                                                Ok((resident_id, apt, first_name, last_name, card_id, accepted, home))
+                                            });
                                             Some(result)
                                         }
                                         Err(_) => {
@@ -1402,14 +2669,27 @@ fn start_automatic_verification(
 
                                 if let Some(result) = verification_result {
                                     match result {
-                                        Ok((resident_id, apt, first_name, last_name, stored_hash)) if stored_hash == card_hash => {
+                                        Ok((resident_id, apt, first_name, last_name, card_id, false, _)) => {
+                                            println!("❌ Card for {} {} (Apt {}) belongs to another building", first_name, last_name, apt);
+                                            if let Some(ui) = ui_weak.upgrade() {
+                                                ui.set_verification_type(2);
+                                                ui.set_verification_status("WRONG BUILDING".into());
+                                            }
+
+                                            // Counted on the dashboard
+                                            if let Ok(db) = db.try_lock() {
+                                                let _ = add_log_with_payload(&db, "card_refused", "Card from another building refused",
+                                                    serde_json::json!({ "resident_id": resident_id, "card_id": card_id }));
+                                            }
+                                        }
+                                        Ok((resident_id, apt, first_name, last_name, card_id, _, home)) => {
                                             let success_msg = format!(
                                                 "✓ VERIFIED\n{} {}\nApartment: {}", 
                                                 first_name, last_name, apt
                                             );
 
                                             if let Some(ui) = ui_weak.upgrade() {
-                                                if ui.get_inventory() {
+                                                if home && ui.get_inventory() {
                                                     // Get packages for this resident
                                                     let db_guard = db.lock().unwrap();
                                                     let packages = get_packages_for_resident(&db_guard, &apt).unwrap_or_default();
@@ -1428,6 +2708,7 @@ fn start_automatic_verification(
                                                                 barcode: pkg.barcode.clone().into(),
                                                                 comment: pkg.comment.clone().unwrap_or_default().into(),
                                                                 date_time: pkg.date_time.clone().into(),
+                                                                carrier: pkg.carrier.clone().unwrap_or_default().into(),
                                                             }
                                                         }).collect();
                                                         
@@ -1440,6 +2721,8 @@ fn start_automatic_verification(
                                                         ui.set_resident_packages_for_collection(slint::ModelRc::from(model));
                                                         ui.set_selected_packages(slint::ModelRc::from(selection_model));
                                                         ui.set_current_card_hash(card_hash.clone().into()); // ✅ Store hash!
+                                                        ui.set_scan_mismatch("".into());
+                                                        ui.set_signature_path("".into());
                                                         ui.set_show_package_selection(true);
                                                         
                                                         println!("📦 Showing {} packages for selection", packages.len());
@@ -1457,7 +2740,7 @@ fn start_automatic_verification(
                                             }
 
                                             if let Some(ui) = ui_weak.upgrade() {
-                                                if ui.get_inventory() {
+                                                if home && ui.get_inventory() {
                                                     // Get packages for this resident
                                                     let db_guard = db.lock().unwrap();  // ✅ Use 'db', not 'db_clone'
                                                     let packages = get_packages_for_resident(&db_guard, &apt).unwrap_or_default();
@@ -1476,6 +2759,7 @@ fn start_automatic_verification(
                                                                 barcode: pkg.barcode.clone().into(),
                                                                 comment: pkg.comment.clone().unwrap_or_default().into(),
                                                                 date_time: pkg.date_time.clone().into(),
+                                                                carrier: pkg.carrier.clone().unwrap_or_default().into(),
                                                             }
                                                         }).collect();
                                                         
@@ -1488,6 +2772,8 @@ fn start_automatic_verification(
                                                         ui.set_resident_packages_for_collection(slint::ModelRc::from(model));
                                                         ui.set_selected_packages(slint::ModelRc::from(selection_model));
                                                         ui.set_current_card_hash(card_hash.clone().into());
+                                                        ui.set_scan_mismatch("".into());
+                                                        ui.set_signature_path("".into());
                                                         ui.set_show_package_selection(true);
                                                         
                                                         println!("📦 Showing {} packages for selection", packages.len());
@@ -1499,11 +2785,9 @@ fn start_automatic_verification(
                                             if let Ok(db) = db.try_lock() {
                                                 let log_action = format!("Access granted: {} {} (Apt: {})", 
                                                     first_name, last_name, apt);
-                                                let date_time = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-                                                let _ = db.execute(
-                                                    "INSERT INTO log (action_type, action, date_time) VALUES (?1, ?2, ?3)",
-                                                    rusqlite::params!["verified", log_action, date_time],
-                                                );
+                                                let _ = add_log_with_payload(&db, "verified", &log_action, serde_json::json!({
+                                                    "resident_id": resident_id, "card_id": card_id, "apt": apt,
+                                                }));
                                             }
                                         }
                                         _ => {
@@ -1512,6 +2796,13 @@ fn start_automatic_verification(
                                                 ui.set_verification_type(2);
                                                 ui.set_verification_status("UNKNOWN CARD".into());
                                             }
+
+                                            // Counted in the daily report. Only part of the UID is kept: what
+                                            // was read from an unknown card could be someone's credential.
+                                            if let Ok(db) = db.try_lock() {
+                                                let _ = add_log_with_payload(&db, "unknown_card", "Unknown card presented",
+                                                    serde_json::json!({ "reader": reader_name, "uid_prefix": &uid[..6.min(uid.len())] }));
+                                            }
                                         }
                                     }
                                 }
@@ -1538,6 +2829,6 @@ fn start_automatic_verification(
             }
         }
         
-        std::thread::sleep(std::time::Duration::from_millis(300));
+        std::thread::sleep(poll_interval);
     }
 }
\ No newline at end of file
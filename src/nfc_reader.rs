@@ -3,10 +3,15 @@ use std::thread;
 use std::time::Duration;
 use std::ffi::CString;
 use sha2::{Sha256, Digest};
+use slint_rust_template::config::NfcConfig;
 
 pub struct NFCReader {
     context: Context,
     reader_name: Option<CString>,
+    start_page: u8,
+    led_success_ms: u16,
+    led_error_ms: u16,
+    led_processing_ms: u16,
 }
 
 #[derive(Debug, Clone)]
@@ -37,10 +42,19 @@ impl NFCCardData {
 
 impl NFCReader {
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_config(&NfcConfig::default())
+    }
+
+    /// Creates a reader using the NTAG page and LED timings from the config
+    pub fn from_config(config: &NfcConfig) -> Result<Self, Box<dyn std::error::Error>> {
         let context = Context::establish(Scope::User)?;
         Ok(NFCReader {
             context,
             reader_name: None,
+            start_page: config.ntag_start_page,
+            led_success_ms: config.led_success_ms,
+            led_error_ms: config.led_error_ms,
+            led_processing_ms: config.led_processing_ms,
         })
     }
 
@@ -123,19 +137,19 @@ impl NFCReader {
     /// Convenience method: Flash green LED to indicate success
     pub fn signal_success(&self) -> Result<(), Box<dyn std::error::Error>> {
         println!("💚 Signaling success with green LED");
-        self.set_led(1, 2000) // Green for 2 seconds
+        self.set_led(1, self.led_success_ms)
     }
 
     /// Convenience method: Flash red LED to indicate error
     pub fn signal_error(&self) -> Result<(), Box<dyn std::error::Error>> {
         println!("❌ Signaling error with red LED");
-        self.set_led(2, 2000) // Red for 2 seconds
+        self.set_led(2, self.led_error_ms)
     }
 
     /// Convenience method: Flash orange LED to indicate warning/processing
     pub fn signal_processing(&self) -> Result<(), Box<dyn std::error::Error>> {
         println!("🟠 Signaling processing with orange LED");
-        self.set_led(3, 1000) // Orange for 1 second
+        self.set_led(3, self.led_processing_ms)
     }
 
     pub fn read_card_uid(&self) -> Result<String, Box<dyn std::error::Error>> {
@@ -245,7 +259,7 @@ impl NFCReader {
         println!("  🔍 Byte count: {}", hash_bytes.len());
 
         let reader_name = self.reader_name.as_ref().unwrap();
-        let start_page = 5u8;
+        let start_page = self.start_page;
         let mut byte_position = 0;
         
         // Signal processing started
@@ -383,7 +397,7 @@ impl NFCReader {
     
         let mut response_buf = [0; MAX_BUFFER_SIZE];
         let mut hash_bytes = Vec::new();
-        let start_page = 5u8;
+        let start_page = self.start_page;
         
         for page_offset in 0..16 {
             let page = start_page + page_offset;
@@ -0,0 +1,443 @@
+use rusqlite::{Connection, Error, OptionalExtension};
+use sha2::{Digest, Sha256};
+use chrono::Local;
+
+use crate::{add_log_with_payload, app_error, property};
+
+/// Minimum length accepted for new passwords
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+const HASH_SCHEME: &str = "pbkdf2_sha256";
+const HASH_ITERATIONS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Admin,
+    Concierge,
+    ReadOnly,
+}
+
+/// Actions that are checked against the logged-in operator's role
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Add residents and packages, collect packages with a resident card
+    EditRecords,
+    DeleteResident,
+    DeletePackage,
+    /// Permanently remove deleted records once they are past the retention period
+    PurgeDeleted,
+    /// Erase a former resident's personal data, including from the log
+    EraseData,
+    LinkCard,
+    RevokeCard,
+    /// Mark a package collected without a resident card
+    ManualOverride,
+    Export,
+    ChangeSettings,
+    ManageOperators,
+    /// Register apartments and take them out of use
+    ManageUnits,
+    /// Add buildings and decide which buildings' cards each one accepts
+    ManageProperties,
+    /// Move residents out, and return or reassign the packages they leave behind
+    MoveOut,
+}
+
+impl Role {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().replace('-', "_").as_str() {
+            "admin" => Some(Role::Admin),
+            "concierge" => Some(Role::Concierge),
+            "read_only" | "readonly" => Some(Role::ReadOnly),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Concierge => "concierge",
+            Role::ReadOnly => "read_only",
+        }
+    }
+
+    pub fn allows(&self, permission: Permission) -> bool {
+        match self {
+            Role::Admin => true,
+            Role::Concierge => matches!(
+                permission,
+                Permission::EditRecords | Permission::LinkCard | Permission::Export
+            ),
+            Role::ReadOnly => false,
+        }
+    }
+}
+
+impl Permission {
+    fn describe(&self) -> &'static str {
+        match self {
+            Permission::EditRecords => "add or change records",
+            Permission::DeleteResident => "remove residents",
+            Permission::DeletePackage => "remove packages",
+            Permission::PurgeDeleted => "purge deleted records",
+            Permission::EraseData => "erase personal data",
+            Permission::LinkCard => "link cards",
+            Permission::RevokeCard => "revoke cards",
+            Permission::ManualOverride => "collect packages without a card",
+            Permission::Export => "export data",
+            Permission::ChangeSettings => "change settings",
+            Permission::ManageOperators => "manage operator accounts",
+            Permission::ManageUnits => "manage apartments",
+            Permission::ManageProperties => "manage buildings",
+            Permission::MoveOut => "move residents out",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Operator {
+    pub id: u32,
+    pub username: String,
+    pub display_name: String,
+    pub has_card: bool,
+    pub active: bool,
+    pub role: Role,
+    /// Building the account is limited to; `None` works in every building
+    pub property_id: Option<u32>,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn derive_key(password: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, iterations, &mut key);
+    key
+}
+
+/// Hashes a password with a random salt.
+/// Stored as `pbkdf2_sha256$<iterations>$<salt hex>$<hash hex>`.
+pub fn hash_password(password: &str) -> Result<String, Error> {
+    let mut salt = [0u8; SALT_LEN];
+    getrandom::fill(&mut salt).map_err(|e| app_error(format!("No random source for salt: {}", e)))?;
+
+    let key = derive_key(password, &salt, HASH_ITERATIONS);
+    Ok(format!("{}${}${}${}", HASH_SCHEME, HASH_ITERATIONS, to_hex(&salt), to_hex(&key)))
+}
+
+/// Checks a password against a stored hash in constant time
+pub fn verify_password(password: &str, stored: &str) -> bool {
+    let parts: Vec<&str> = stored.split('$').collect();
+    let [scheme, iterations, salt, hash] = parts[..] else {
+        return false;
+    };
+    if scheme != HASH_SCHEME {
+        return false;
+    }
+
+    let (Ok(iterations), Some(salt), Some(hash)) = (iterations.parse::<u32>(), from_hex(salt), from_hex(hash)) else {
+        return false;
+    };
+
+    let key = derive_key(password, &salt, iterations);
+    hash.len() == key.len() && key.iter().zip(&hash).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Hash written to a staff NFC card. Prefixed so it can never match a resident card hash.
+pub fn staff_card_hash(uid: &str, operator_id: u32, added_date: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("staff:{}:{}:{}", uid, operator_id, added_date).as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+fn validate_password(password: &str) -> Result<(), Error> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(app_error(format!("Password must be at least {} characters", MIN_PASSWORD_LEN)));
+    }
+    Ok(())
+}
+
+fn row_to_operator(row: &rusqlite::Row) -> rusqlite::Result<Operator> {
+    Ok(Operator {
+        id: row.get(0)?,
+        username: row.get(1)?,
+        display_name: row.get(2)?,
+        has_card: row.get(3)?,
+        active: row.get(4)?,
+        // Unknown roles get no permissions
+        role: Role::from_name(&row.get::<_, String>(5)?).unwrap_or(Role::ReadOnly),
+        property_id: row.get(6)?,
+    })
+}
+
+const OPERATOR_COLUMNS: &str = "id, username, display_name, card_hash IS NOT NULL, active, role, property_id";
+
+// Session state lives in a TEMP table, so it belongs to this connection only
+// and is never written to the database file or its backups.
+pub(crate) fn init_session(db: &Connection) -> Result<(), Error> {
+    db.execute_batch("
+        CREATE TEMP TABLE IF NOT EXISTS session (
+            operator_id INTEGER NOT NULL
+        );
+    ")
+}
+
+pub fn count_operators(db: &Connection) -> Result<u32, Error> {
+    db.query_row("SELECT COUNT(*) FROM operator WHERE active = 1", [], |row| row.get(0))
+}
+
+pub fn get_operators(db: &Connection) -> Result<Vec<Operator>, Error> {
+    let mut stmt = db.prepare(&format!("SELECT {} FROM operator ORDER BY username", OPERATOR_COLUMNS))?;
+    let rows = stmt.query_map([], row_to_operator)?;
+    rows.collect::<Result<Vec<_>, _>>()
+}
+
+pub fn find_operator(db: &Connection, username: &str) -> Result<Operator, Error> {
+    db.query_row(
+        &format!("SELECT {} FROM operator WHERE username = ?1", OPERATOR_COLUMNS),
+        [username.trim()],
+        row_to_operator,
+    ).optional()?
+    .ok_or_else(|| app_error(format!("No operator named '{}'", username.trim())))
+}
+
+/// Creates an account. Needs `ManageOperators`, except for the first
+/// account when none are active, which must be an admin.
+pub fn create_operator(db: &Connection, username: &str, display_name: &str, password: &str, role: Role) -> Result<u32, Error> {
+    if count_operators(db)? == 0 {
+        if role != Role::Admin {
+            return Err(app_error("The first operator account must be an admin"));
+        }
+    } else {
+        require_permission(db, Permission::ManageOperators)?;
+    }
+
+    let username = username.trim();
+    let display_name = display_name.trim();
+
+    if username.is_empty() || username.contains(char::is_whitespace) {
+        return Err(app_error("Username must be a single word"));
+    }
+    validate_password(password)?;
+
+    let exists: bool = db.query_row(
+        "SELECT EXISTS(SELECT 1 FROM operator WHERE username = ?1)",
+        [username],
+        |row| row.get(0),
+    )?;
+    if exists {
+        return Err(app_error(format!("Operator '{}' already exists", username)));
+    }
+
+    let display_name = if display_name.is_empty() { username } else { display_name };
+    let created_date = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    db.execute(
+        "INSERT INTO operator (username, display_name, password_hash, created_date, role) VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![username, display_name, hash_password(password)?, created_date, role.name()],
+    )?;
+    let id = db.last_insert_rowid() as u32;
+
+    add_log_with_payload(
+        db, "operator",
+        &format!("Operator account created: {} ({}, {})", display_name, username, role.name()),
+        serde_json::json!({ "target_operator_id": id, "role": role.name() }),
+    )?;
+    Ok(id)
+}
+
+/// Operators may change their own password; other accounts need `ManageOperators`
+pub fn set_password(db: &Connection, id: u32, password: &str) -> Result<(), Error> {
+    require_self_or_permission(db, id, Permission::ManageOperators)?;
+    validate_password(password)?;
+    db.execute(
+        "UPDATE operator SET password_hash = ?1 WHERE id = ?2",
+        rusqlite::params![hash_password(password)?, id],
+    )?;
+    add_log_with_payload(
+        db, "operator", &format!("Password changed for operator ID {}", id),
+        serde_json::json!({ "target_operator_id": id }),
+    )
+}
+
+pub fn set_active(db: &Connection, id: u32, active: bool) -> Result<(), Error> {
+    require_permission(db, Permission::ManageOperators)?;
+    if !active {
+        ensure_other_admin(db, id)?;
+    }
+    db.execute("UPDATE operator SET active = ?1 WHERE id = ?2", rusqlite::params![active, id])?;
+    let state = if active { "enabled" } else { "disabled" };
+    add_log_with_payload(
+        db, "operator", &format!("Operator ID {} {}", id, state),
+        serde_json::json!({ "target_operator_id": id, "active": active }),
+    )
+}
+
+pub fn set_role(db: &Connection, id: u32, role: Role) -> Result<(), Error> {
+    require_permission(db, Permission::ManageOperators)?;
+    if role != Role::Admin {
+        ensure_other_admin(db, id)?;
+    }
+    db.execute("UPDATE operator SET role = ?1 WHERE id = ?2", rusqlite::params![role.name(), id])?;
+    add_log_with_payload(
+        db, "operator", &format!("Operator ID {} role set to {}", id, role.name()),
+        serde_json::json!({ "target_operator_id": id, "role": role.name() }),
+    )
+}
+
+// Refuses changes that would leave no active admin
+fn ensure_other_admin(db: &Connection, id: u32) -> Result<(), Error> {
+    let other_admins: u32 = db.query_row(
+        "SELECT COUNT(*) FROM operator WHERE role = 'admin' AND active = 1 AND id != ?1",
+        [id],
+        |row| row.get(0),
+    )?;
+    let is_admin: bool = db.query_row(
+        "SELECT EXISTS(SELECT 1 FROM operator WHERE id = ?1 AND role = 'admin' AND active = 1)",
+        [id],
+        |row| row.get(0),
+    )?;
+    if is_admin && other_admins == 0 {
+        return Err(app_error("At least one active admin account is required"));
+    }
+    Ok(())
+}
+
+/// Operators may link their own staff card; other accounts need `ManageOperators`
+pub fn link_operator_card(db: &Connection, id: u32, card_hash: &str) -> Result<(), Error> {
+    require_self_or_permission(db, id, Permission::ManageOperators)?;
+    db.execute(
+        "UPDATE operator SET card_hash = ?1 WHERE id = ?2",
+        rusqlite::params![card_hash, id],
+    )?;
+    add_log_with_payload(
+        db, "operator",
+        &format!("Staff card linked to operator ID {}", id),
+        serde_json::json!({ "target_operator_id": id, "card_hash_prefix": &card_hash[..16.min(card_hash.len())] }),
+    )
+}
+
+/// Checks a username and password. Disabled accounts cannot log in.
+pub fn authenticate(db: &Connection, username: &str, password: &str) -> Result<Operator, Error> {
+    let found = db.query_row(
+        &format!("SELECT {}, password_hash FROM operator WHERE username = ?1 AND active = 1", OPERATOR_COLUMNS),
+        [username.trim()],
+        |row| Ok((row_to_operator(row)?, row.get::<_, String>(7)?)),
+    ).optional()?;
+
+    match found {
+        Some((operator, stored)) if verify_password(password, &stored) => Ok(operator),
+        _ => Err(app_error("Invalid username or password")),
+    }
+}
+
+/// Finds the active operator a staff card belongs to
+pub fn authenticate_card(db: &Connection, card_hash: &str) -> Result<Option<Operator>, Error> {
+    db.query_row(
+        &format!("SELECT {} FROM operator WHERE card_hash = ?1 AND active = 1", OPERATOR_COLUMNS),
+        [card_hash],
+        row_to_operator,
+    ).optional()
+}
+
+/// Starts a session on this connection. Later log entries are attributed to `operator`.
+/// An operator limited to one building is moved into it.
+pub fn login(db: &Connection, operator: &Operator) -> Result<(), Error> {
+    db.execute("DELETE FROM temp.session", [])?;
+    db.execute("INSERT INTO temp.session (operator_id) VALUES (?1)", [operator.id])?;
+    if let Some(property_id) = operator.property_id {
+        property::set_current(db, property_id)?;
+    }
+    add_log_with_payload(
+        db, "login", &format!("Operator logged in: {} ({})", operator.display_name, operator.username),
+        serde_json::json!({ "target_operator_id": operator.id }),
+    )
+}
+
+pub fn logout(db: &Connection, reason: &str) -> Result<(), Error> {
+    if let Some(operator) = current_operator(db)? {
+        add_log_with_payload(
+            db, "logout", &format!("Operator logged out: {} ({})", operator.display_name, reason),
+            serde_json::json!({ "target_operator_id": operator.id, "reason": reason }),
+        )?;
+    }
+    db.execute("DELETE FROM temp.session", [])?;
+    Ok(())
+}
+
+/// Operator logged in on this connection. One disabled since logging in no longer counts.
+pub fn current_operator(db: &Connection) -> Result<Option<Operator>, Error> {
+    db.query_row(
+        &format!(
+            "SELECT {} FROM operator WHERE id = (SELECT operator_id FROM temp.session) AND active = 1",
+            OPERATOR_COLUMNS
+        ),
+        [],
+        row_to_operator,
+    ).optional()
+}
+
+/// Checks that the operator logged in on this connection may perform `permission`
+/// in the current building. Returns that operator so callers can attribute the action.
+pub fn require_permission(db: &Connection, permission: Permission) -> Result<Operator, Error> {
+    let Some(operator) = current_operator(db)? else {
+        return Err(app_error(format!("Permission denied: log in to {}", permission.describe())));
+    };
+    if !operator.role.allows(permission) {
+        return Err(app_error(format!(
+            "Permission denied: {} accounts cannot {}", operator.role.name(), permission.describe()
+        )));
+    }
+    if let Some(property_id) = operator.property_id {
+        if property_id != property::current_property_id(db)? {
+            return Err(app_error(format!(
+                "Permission denied: {} is not assigned to this building", operator.display_name
+            )));
+        }
+    }
+    Ok(operator)
+}
+
+fn require_self_or_permission(db: &Connection, id: u32, permission: Permission) -> Result<(), Error> {
+    match current_operator(db)? {
+        Some(operator) if operator.id == id => Ok(()),
+        _ => require_permission(db, permission).map(|_| ()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_operator_loses_permissions_on_open_sessions() {
+        let path = std::env::temp_dir().join(format!("operator-test-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let admin_db = crate::open_db(&path).unwrap();
+        create_operator(&admin_db, "admin", "Admin", "hunter2hunter2", Role::Admin).unwrap();
+        login(&admin_db, &authenticate(&admin_db, "admin", "hunter2hunter2").unwrap()).unwrap();
+        let concierge = create_operator(&admin_db, "desk", "Desk", "hunter2hunter2", Role::Concierge).unwrap();
+
+        let desk_db = crate::open_db(&path).unwrap();
+        login(&desk_db, &authenticate(&desk_db, "desk", "hunter2hunter2").unwrap()).unwrap();
+        assert!(require_permission(&desk_db, Permission::EditRecords).is_ok());
+
+        set_active(&admin_db, concierge, false).unwrap();
+        assert!(current_operator(&desk_db).unwrap().is_none());
+        assert!(require_permission(&desk_db, Permission::EditRecords).is_err());
+
+        drop((admin_db, desk_db));
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,225 @@
+use crate::export::{self, ExportData};
+
+// A4 landscape, in points
+const PAGE_WIDTH: f32 = 842.0;
+const PAGE_HEIGHT: f32 = 595.0;
+const MARGIN: f32 = 40.0;
+
+// Courier glyphs are 0.6 em wide, which is what lets tables line up
+const MONO_SIZE: f32 = 8.0;
+const MAX_COLUMN_CHARS: usize = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Style {
+    Title,
+    Heading,
+    Text,
+    Mono,
+}
+
+impl Style {
+    // Font resource names, see `PdfWriter::finish`
+    fn font(&self) -> &'static str {
+        match self {
+            Style::Title | Style::Heading => "F2",
+            Style::Text => "F1",
+            Style::Mono => "F3",
+        }
+    }
+
+    fn size(&self) -> f32 {
+        match self {
+            Style::Title => 18.0,
+            Style::Heading => 13.0,
+            Style::Text => 10.0,
+            Style::Mono => MONO_SIZE,
+        }
+    }
+}
+
+/// Writes plain text reports as PDF with the standard Helvetica and Courier
+/// fonts, so nothing has to be embedded. Text is laid out top to bottom and
+/// flows onto new pages; report pages get a page number.
+pub(crate) struct PdfWriter {
+    pages: Vec<Vec<u8>>,
+    y: f32,
+    width: f32,
+    height: f32,
+    margin: f32,
+    numbered: bool,
+}
+
+impl PdfWriter {
+    /// A4 landscape report pages
+    pub(crate) fn new() -> Self {
+        PdfWriter {
+            pages: vec![Vec::new()],
+            y: PAGE_HEIGHT - MARGIN,
+            width: PAGE_WIDTH,
+            height: PAGE_HEIGHT,
+            margin: MARGIN,
+            numbered: true,
+        }
+    }
+
+    /// Unnumbered pages of any size, e.g. labels; sizes are in points
+    pub(crate) fn with_page_size(width: f32, height: f32, margin: f32) -> Self {
+        PdfWriter { pages: vec![Vec::new()], y: height - margin, width, height, margin, numbered: false }
+    }
+
+    pub(crate) fn line(&mut self, style: Style, text: &str) {
+        let leading = style.size() * 1.35;
+        if self.y - leading < self.margin {
+            self.new_page();
+        }
+        self.y -= leading;
+        let (x, y) = (self.margin, self.y);
+        self.text_at(style, x, y, text);
+    }
+
+    pub(crate) fn gap(&mut self, points: f32) {
+        self.y -= points;
+    }
+
+    pub(crate) fn new_page(&mut self) {
+        self.pages.push(Vec::new());
+        self.y = self.height - self.margin;
+    }
+
+    /// A row of bars below the last line, e.g. a barcode. `widths` alternate
+    /// bar and space, starting with a bar, in multiples of `module` points.
+    pub(crate) fn bars(&mut self, widths: &[u8], module: f32, height: f32) {
+        if self.y - height < self.margin {
+            self.new_page();
+        }
+        self.y -= height;
+        let mut x = self.margin;
+        let page = self.pages.last_mut().expect("a writer always has a page");
+        for (i, &width) in widths.iter().enumerate() {
+            let w = f32::from(width) * module;
+            if i % 2 == 0 {
+                page.extend_from_slice(format!("{:.2} {:.2} {:.2} {:.2} re f\n", x, self.y, w, height).as_bytes());
+            }
+            x += w;
+        }
+    }
+
+    /// Rows as fixed-width text; long values are cut short
+    pub(crate) fn table(&mut self, data: &ExportData) {
+        if data.rows.is_empty() {
+            self.line(Style::Text, "None");
+            return;
+        }
+
+        let cells: Vec<Vec<String>> = data.rows.iter()
+            .map(|row| row.iter().map(|value| export::display_value(value).replace(['\n', '\r'], " ")).collect())
+            .collect();
+        let widths: Vec<usize> = data.columns.iter().enumerate()
+            .map(|(i, column)| {
+                cells.iter().map(|row| row[i].chars().count())
+                    .chain([column.chars().count()])
+                    .max()
+                    .unwrap_or(0)
+                    .min(MAX_COLUMN_CHARS)
+            })
+            .collect();
+
+        let chars_per_line = ((self.width - 2.0 * self.margin) / (MONO_SIZE * 0.6)) as usize;
+        let format_row = |values: &[String]| -> String {
+            let line: Vec<String> = values.iter().zip(&widths)
+                .map(|(value, &width)| {
+                    let cut: String = if value.chars().count() > width {
+                        value.chars().take(width.saturating_sub(1)).chain(['~']).collect()
+                    } else {
+                        value.clone()
+                    };
+                    format!("{:<width$}", cut, width = width)
+                })
+                .collect();
+            line.join("  ").chars().take(chars_per_line).collect()
+        };
+
+        let header = format_row(&data.columns);
+        self.line(Style::Mono, &header);
+        self.line(Style::Mono, &"-".repeat(header.trim_end().chars().count()));
+        for row in &cells {
+            self.line(Style::Mono, &format_row(row));
+        }
+    }
+
+    fn text_at(&mut self, style: Style, x: f32, y: f32, text: &str) {
+        let page = self.pages.last_mut().expect("a writer always has a page");
+        page.extend_from_slice(format!("BT /{} {} Tf {:.1} {:.1} Td (", style.font(), style.size(), x, y).as_bytes());
+        page.extend(pdf_string(text));
+        page.extend_from_slice(b") Tj ET\n");
+    }
+
+    /// The finished document
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        let page_count = self.pages.len();
+        if self.numbered {
+            for (i, page) in self.pages.iter_mut().enumerate() {
+                page.extend_from_slice(format!(
+                    "BT /F1 8 Tf {:.1} {:.1} Td (Page {} of {}) Tj ET\n",
+                    self.width - self.margin - 50.0, self.margin / 2.0, i + 1, page_count
+                ).as_bytes());
+            }
+        }
+
+        // Objects 1-5 are the catalog, page tree and fonts; each page adds a page and a content object
+        let mut objects: Vec<Vec<u8>> = Vec::new();
+        let kids: Vec<String> = (0..page_count).map(|i| format!("{} 0 R", 6 + 2 * i)).collect();
+        objects.push(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+        objects.push(format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), page_count).into_bytes());
+        for font in ["Helvetica", "Helvetica-Bold", "Courier"] {
+            objects.push(format!(
+                "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>", font
+            ).into_bytes());
+        }
+        for (i, content) in self.pages.iter().enumerate() {
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+                 /Resources << /Font << /F1 3 0 R /F2 4 0 R /F3 5 0 R >> >> /Contents {} 0 R >>",
+                self.width, self.height, 7 + 2 * i
+            ).into_bytes());
+            let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+            stream.extend_from_slice(content);
+            stream.extend_from_slice(b"endstream");
+            objects.push(stream);
+        }
+
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+            pdf.extend_from_slice(object);
+            pdf.extend_from_slice(b"\nendobj\n");
+        }
+
+        let xref = pdf.len();
+        pdf.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+        for offset in offsets {
+            pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        pdf.extend_from_slice(format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref
+        ).as_bytes());
+        pdf
+    }
+}
+
+// Text as a PDF string literal body. The standard fonts only cover Latin-1,
+// so anything else is replaced with '?'.
+fn pdf_string(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => bytes.extend_from_slice(&[b'\\', c as u8]),
+            c if (c as u32) < 0x20 => bytes.push(b' '),
+            c if (c as u32) < 0x100 => bytes.push(c as u32 as u8),
+            _ => bytes.push(b'?'),
+        }
+    }
+    bytes
+}
@@ -0,0 +1,410 @@
+use rusqlite::{Connection, Error};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use chrono::Local;
+
+use crate::{add_log_with_payload, app_error, get_package_info, PackageData};
+use crate::operator::{Permission, require_permission};
+use crate::property::CURRENT_PROPERTY;
+
+/// Subfolder of the watch folder that imported images are moved to
+pub const IMPORTED_DIR: &str = "imported";
+
+// Files in the watch folder changed more recently than this may still be copying in
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// When a photo was taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhotoStage {
+    Intake,
+    Collection,
+}
+
+impl PhotoStage {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "intake" => Some(PhotoStage::Intake),
+            "collection" => Some(PhotoStage::Collection),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PhotoStage::Intake => "intake",
+            PhotoStage::Collection => "collection",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PhotoSettings {
+    /// Photo store. Files are named by the SHA-256 of their contents, so an image
+    /// attached to several packages is kept once.
+    pub dir: PathBuf,
+    /// Folder new images are imported from, e.g. on a network share a camera uploads to
+    pub watch_dir: Option<PathBuf>,
+}
+
+impl Default for PhotoSettings {
+    fn default() -> Self {
+        PhotoSettings { dir: PathBuf::from("photos"), watch_dir: None }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Photo {
+    pub id: u32,
+    pub package_id: u32,
+    /// SHA-256 of the image, in hex
+    pub hash: String,
+    /// `intake` or `collection`
+    pub stage: String,
+    pub original_name: String,
+    pub added_at: String,
+    pub added_by: String,
+    pub path: PathBuf,
+}
+
+/// Damage flag and photos of one package
+#[derive(Debug, Clone)]
+pub struct PackageCondition {
+    pub damaged: bool,
+    /// Oldest first
+    pub photos: Vec<Photo>,
+}
+
+// Extension of the stored file, from the image's leading bytes
+fn image_extension(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("jpg")
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("png")
+    } else {
+        None
+    }
+}
+
+/// Whether a file is named like an image the store takes
+pub fn is_image_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| matches!(extension.to_lowercase().as_str(), "jpg" | "jpeg" | "png"))
+}
+
+/// Copies an image into the store and returns its hash and its path relative to `dir`,
+/// e.g. `3f/3fa9...c2.jpg`. An image that is already stored isn't written again.
+pub fn store_image(dir: &Path, bytes: &[u8]) -> Result<(String, String), Box<dyn std::error::Error>> {
+    let extension = image_extension(bytes).ok_or("Only JPEG and PNG images can be attached")?;
+    let hash = format!("{:x}", Sha256::digest(bytes));
+    let file = format!("{}/{}.{}", &hash[..2], hash, extension);
+
+    let path = dir.join(&file);
+    if !path.exists() {
+        fs::create_dir_all(dir.join(&hash[..2]))?;
+        // Written under another name first, so a half-written file is never taken for the image
+        let partial = path.with_extension("part");
+        fs::write(&partial, bytes)?;
+        fs::rename(&partial, &path)?;
+    }
+    Ok((hash, file))
+}
+
+// A package of the current building that hasn't been removed
+fn building_package(db: &Connection, package_id: u32) -> Result<PackageData, Error> {
+    let found: bool = db.query_row(
+        &format!(
+            "SELECT EXISTS(SELECT 1 FROM package WHERE id = ?1 AND deleted_at IS NULL AND property_id = {})",
+            CURRENT_PROPERTY
+        ),
+        [package_id],
+        |row| row.get(0),
+    )?;
+    if !found {
+        return Err(app_error(format!("No package {} in this building", package_id)));
+    }
+    get_package_info(db, package_id)
+}
+
+/// Attaches an image file to a package of the current building
+pub fn attach_photo(
+    db: &Connection,
+    settings: &PhotoSettings,
+    package_id: u32,
+    source: &Path,
+    stage: PhotoStage,
+) -> Result<Photo, Box<dyn std::error::Error>> {
+    match add_photo(db, settings, package_id, source, stage, &mut Vec::new())? {
+        Some(photo) => Ok(photo),
+        None => Err(format!(
+            "{} is already attached to package #{}",
+            source.file_name().unwrap_or_default().to_string_lossy(), get_package_info(db, package_id)?.package_number
+        ).into()),
+    }
+}
+
+// Stores and attaches an image, adding its store file to `stored`.
+// Returns `None` when the package already has the image.
+fn add_photo(
+    db: &Connection,
+    settings: &PhotoSettings,
+    package_id: u32,
+    source: &Path,
+    stage: PhotoStage,
+    stored: &mut Vec<String>,
+) -> Result<Option<Photo>, Box<dyn std::error::Error>> {
+    let operator = require_permission(db, Permission::EditRecords)?;
+    let package = building_package(db, package_id)?;
+
+    let bytes = fs::read(source).map_err(|e| format!("Could not read {}: {}", source.display(), e))?;
+    let (hash, file) = store_image(&settings.dir, &bytes)?;
+    stored.push(file.clone());
+    let original_name = source.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let added_at = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+    let inserted = db.execute(
+        "INSERT OR IGNORE INTO package_photo (package_id, hash, file, stage, original_name, added_at, added_by)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        rusqlite::params![package_id, hash, file, stage.as_str(), original_name, added_at, operator.id],
+    )?;
+    if inserted == 0 {
+        return Ok(None);
+    }
+    let id = db.last_insert_rowid() as u32;
+
+    let log_action = format!(
+        "Photo {} added to package #{} (Apt {}) at {}",
+        original_name, package.package_number, package.apt, stage.as_str()
+    );
+    add_log_with_payload(db, "package_photo", &log_action, serde_json::json!({
+        "package_id": package_id, "apt": package.apt, "hash": hash, "stage": stage.as_str(),
+    }))?;
+
+    Ok(Some(Photo {
+        id,
+        package_id,
+        hash,
+        stage: stage.as_str().to_string(),
+        original_name,
+        added_at,
+        added_by: operator.display_name,
+        path: settings.dir.join(file),
+    }))
+}
+
+/// Images in the watch folder that have finished copying, oldest first
+pub fn watched_images(watch_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let now = SystemTime::now();
+    let mut images = Vec::new();
+    for entry in fs::read_dir(watch_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() || !is_image_file(&path) {
+            continue;
+        }
+        let modified = metadata.modified()?;
+        // A file dated in the future is from a share with a clock ahead of ours, not one being written
+        if now.duration_since(modified).is_ok_and(|age| age < SETTLE_TIME) {
+            continue;
+        }
+        images.push((modified, path));
+    }
+    images.sort();
+    Ok(images.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Attaches the images waiting in the watch folder, then moves them into the folder's
+/// `imported` subfolder. With one package every image goes to it; with several there must
+/// be one image per package, matched up in order, oldest image first. Images a package
+/// already has are skipped. Returns the number of photos added.
+pub fn attach_watched_photos(
+    db: &Connection,
+    settings: &PhotoSettings,
+    package_ids: &[u32],
+    stage: PhotoStage,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let watch_dir = settings.watch_dir.as_deref().ok_or("No photo watch folder is set up")?;
+    let images = watched_images(watch_dir)?;
+    if images.is_empty() {
+        return Err("No new photos in the watch folder".into());
+    }
+    let targets = match package_ids {
+        [] => return Err("No packages selected".into()),
+        [package_id] => vec![*package_id; images.len()],
+        ids if ids.len() == images.len() => ids.to_vec(),
+        ids => return Err(format!(
+            "{} photos are waiting for {} packages; take one photo of each package", images.len(), ids.len()
+        ).into()),
+    };
+
+    let mut stored = Vec::new();
+    let mut added = 0;
+    let tx = db.unchecked_transaction()?;
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        for (image, &package_id) in images.iter().zip(&targets) {
+            if add_photo(&tx, settings, package_id, image, stage, &mut stored)?.is_some() {
+                added += 1;
+            }
+        }
+        Ok(())
+    })();
+    let result = match result {
+        Ok(()) => tx.commit().map_err(Into::into),
+        Err(e) => {
+            drop(tx);
+            Err(e)
+        }
+    };
+    if let Err(e) = result {
+        // Files written for the rolled-back photos would be left without a photo
+        let _ = delete_unreferenced(db, settings, &stored);
+        return Err(e);
+    }
+
+    for image in &images {
+        archive_watched(watch_dir, image)?;
+    }
+    Ok(added)
+}
+
+// Moves an imported image out of the way so it isn't offered again
+fn archive_watched(watch_dir: &Path, image: &Path) -> io::Result<()> {
+    let archive = watch_dir.join(IMPORTED_DIR);
+    fs::create_dir_all(&archive)?;
+    let name = image.file_name().unwrap_or_default().to_string_lossy();
+    let mut target = archive.join(name.as_ref());
+    if target.exists() {
+        target = archive.join(format!("{}-{}", Local::now().format("%Y%m%d-%H%M%S-%3f"), name));
+    }
+    fs::rename(image, target)
+}
+
+/// Starts a background thread that calls `on_change` with the images waiting
+/// in `watch_dir` whenever they change
+pub fn start_photo_watcher<F>(watch_dir: PathBuf, on_change: F) -> std::thread::JoinHandle<()>
+where
+    F: Fn(Vec<PathBuf>) + Send + 'static,
+{
+    std::thread::spawn(move || {
+        println!("📷 Watching {} for package photos", watch_dir.display());
+        let mut last: Option<Vec<PathBuf>> = None;
+        let mut failing = false;
+        loop {
+            match watched_images(&watch_dir) {
+                Ok(images) => {
+                    failing = false;
+                    if last.as_ref() != Some(&images) {
+                        on_change(images.clone());
+                        last = Some(images);
+                    }
+                }
+                Err(e) if !failing => {
+                    println!("⚠️  Cannot read photo watch folder {}: {}", watch_dir.display(), e);
+                    failing = true;
+                }
+                Err(_) => {}
+            }
+            std::thread::sleep(WATCH_INTERVAL);
+        }
+    })
+}
+
+/// Store files of the photos of the packages `package_ids` selects, a query returning
+/// package ids. Collected before the photos are removed, for `delete_unreferenced`.
+pub(crate) fn photo_files<P: rusqlite::Params>(db: &Connection, package_ids: &str, params: P) -> Result<Vec<String>, Error> {
+    db.prepare(&format!("SELECT DISTINCT file FROM package_photo WHERE package_id IN ({})", package_ids))?
+        .query_map(params, |row| row.get(0))?
+        .collect()
+}
+
+/// Deletes those of `files` from the store that no photo refers to any more, once the
+/// photos removing them has been committed. Returns how many were deleted.
+pub fn delete_unreferenced(db: &Connection, settings: &PhotoSettings, files: &[String]) -> Result<usize, Error> {
+    let mut deleted = 0;
+    for file in files {
+        let referenced: bool = db.query_row(
+            "SELECT EXISTS(SELECT 1 FROM package_photo WHERE file = ?1)", [file], |row| row.get(0)
+        )?;
+        if referenced {
+            continue;
+        }
+        let path = settings.dir.join(file);
+        match fs::remove_file(&path) {
+            Ok(()) => deleted += 1,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(app_error(format!("Could not delete photo {}: {}", path.display(), e))),
+        }
+    }
+    Ok(deleted)
+}
+
+/// A stored image as a `data:` URI, for embedding in HTML
+pub(crate) fn image_data_uri(path: &Path) -> io::Result<String> {
+    let bytes = fs::read(path)?;
+    let mime = if image_extension(&bytes) == Some("png") { "image/png" } else { "image/jpeg" };
+    Ok(crate::export::data_uri(mime, &bytes))
+}
+
+/// Damage flag and photos of a package
+pub fn package_condition(db: &Connection, settings: &PhotoSettings, package_id: u32) -> Result<PackageCondition, Error> {
+    let damaged = db.query_row("SELECT damaged FROM package WHERE id = ?1", [package_id], |row| row.get(0))?;
+
+    let mut stmt = db.prepare(
+        "SELECT p.id, p.package_id, p.hash, p.file, p.stage, p.original_name, p.added_at, COALESCE(o.display_name, '')
+         FROM package_photo p LEFT JOIN operator o ON o.id = p.added_by
+         WHERE p.package_id = ?1
+         ORDER BY p.id"
+    )?;
+    let photos = stmt.query_map([package_id], |row| {
+        Ok(Photo {
+            id: row.get(0)?,
+            package_id: row.get(1)?,
+            hash: row.get(2)?,
+            path: settings.dir.join(row.get::<_, String>(3)?),
+            stage: row.get(4)?,
+            original_name: row.get(5)?,
+            added_at: row.get(6)?,
+            added_by: row.get(7)?,
+        })
+    })?.collect::<Result<Vec<_>, _>>()?;
+
+    Ok(PackageCondition { damaged, photos })
+}
+
+/// Flags a package as damaged on arrival, or clears the flag. Both are logged.
+pub fn set_damaged(db: &Connection, package_id: u32, damaged: bool, note: &str) -> Result<(), Error> {
+    require_permission(db, Permission::EditRecords)?;
+    let package = building_package(db, package_id)?;
+
+    let changed = db.execute(
+        "UPDATE package SET damaged = ?1 WHERE id = ?2 AND damaged != ?1",
+        rusqlite::params![damaged, package_id],
+    )?;
+    if changed == 0 {
+        return Err(app_error(format!(
+            "Package #{} is {} marked damaged", package.package_number, if damaged { "already" } else { "not" }
+        )));
+    }
+
+    let note = note.trim();
+    let log_action = if damaged {
+        format!(
+            "Package #{} (Apt {}) marked damaged on arrival{}",
+            package.package_number, package.apt,
+            if note.is_empty() { String::new() } else { format!(": {}", note) }
+        )
+    } else {
+        format!("Package #{} (Apt {}) no longer marked damaged", package.package_number, package.apt)
+    };
+    add_log_with_payload(db, "damaged", &log_action, serde_json::json!({
+        "package_id": package_id, "apt": package.apt, "damaged": damaged, "note": note,
+    }))
+}
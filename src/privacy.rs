@@ -0,0 +1,393 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use chrono::{Local, Months};
+use rusqlite::{Connection, Error, OptionalExtension};
+
+use crate::{add_log_with_payload, app_error, audit, get_resident_info};
+use crate::config::RetentionConfig;
+use crate::export::{self, ExportData};
+use crate::operator::{Permission, require_permission};
+use crate::photo::{self, PhotoSettings};
+use crate::property;
+
+/// First name an erased resident is left with; the last name becomes their ID
+pub const ERASED_NAME: &str = "Erased";
+
+// Entries that record redactions are never redacted themselves
+const PROTECTED_LOG_TYPES: [&str; 2] = ["erase", "retention"];
+
+// Log entries `l` about resident ?1, their cards or the packages they collected
+const RESIDENT_LOG_ENTRIES: &str = "
+    (l.resident_id = ?1
+     OR l.card_id IN (SELECT id FROM card WHERE resident_id = ?1)
+     OR l.package_id IN (SELECT id FROM package WHERE collected_by = ?1)
+     OR EXISTS (SELECT 1 FROM json_each(l.payload, '$.resident_ids') WHERE value = CAST(?1 AS INTEGER)))";
+
+// Entries from before log payloads carried ids, which name resident ?1 only in their
+// text. Matched on the exact wording each of them was written with, using the
+// resident's name ?2 ("First Last"), apartment ?3 and building ?4.
+const LEGACY_RESIDENT_LOG_ENTRIES: &str = "
+    (l.resident_id IS NULL AND l.card_id IS NULL AND l.package_id IS NULL AND l.property_id = ?4 AND (
+        instr(l.action, 'Resident ' || ?2 || ' (ID: ' || ?1 || ',') = 1
+     OR instr(l.action, ' was linked to resident ID: ' || ?1 || ' (') > 0
+     OR instr(l.action, ', Resident ID=' || ?1 || ', ') > 0
+     OR l.action = 'Access granted: ' || ?2 || ' (Apt: ' || ?3 || ')'
+     OR instr(l.action, 'Package collected: ' || ?2 || ' (Apt ' || ?3 || ') picked up package #') = 1))";
+
+/// What `erase_resident` changed
+#[derive(Debug, Default)]
+pub struct ErasureReport {
+    pub cards: usize,
+    pub package_comments: usize,
+    pub signatures: usize,
+    pub photos: usize,
+    pub log_entries: usize,
+}
+
+impl ErasureReport {
+    pub fn summary(&self) -> String {
+        format!(
+            "Erased {} card(s), {} package comment(s), {} signature(s), {} photo(s) and {} log entries",
+            self.cards, self.package_comments, self.signatures, self.photos, self.log_entries
+        )
+    }
+}
+
+/// Erases the personal data of a resident who moved out or was removed. Their name is
+/// replaced, card hashes are destroyed, comments, signatures and photos of packages they collected
+/// are removed and log entries about them are redacted. Package rows and counts stay, and so do the
+/// resident, card and package IDs in the log, so statistics and the log chain still add up.
+pub fn erase_resident(db: &Connection, resident_id: u32, photos: &PhotoSettings) -> Result<ErasureReport, Error> {
+    require_permission(db, Permission::EraseData)?;
+
+    let resident = get_resident_info(db, resident_id)?;
+    let (resident_property, deleted, erased): (u32, bool, bool) = db.query_row(
+        "SELECT property_id, deleted_at IS NOT NULL, erased_at IS NOT NULL FROM resident WHERE id = ?1",
+        [resident_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    if erased {
+        return Err(app_error(format!("Resident {} was already erased", resident_id)));
+    }
+    if resident.status == "active" && !deleted {
+        return Err(app_error(format!(
+            "{} {} still lives here; move them out before erasing their data", resident.first_name, resident.last_name
+        )));
+    }
+    if resident_property != property::current_property_id(db)? {
+        return Err(app_error("Resident belongs to another building"));
+    }
+
+    let erased_at = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let tx = db.unchecked_transaction()?;
+    let card_ids: Vec<u32> = tx.prepare("SELECT id FROM card WHERE resident_id = ?1")?
+        .query_map([resident_id], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+
+    let log_ids: Vec<u32> = tx.prepare(&format!(
+        "SELECT l.id FROM log l
+         WHERE l.redacted_at IS NULL AND l.action_type NOT IN ('{}') AND ({} OR {})
+         ORDER BY l.id",
+        PROTECTED_LOG_TYPES.join("', '"), RESIDENT_LOG_ENTRIES, LEGACY_RESIDENT_LOG_ENTRIES
+    ))?
+        .query_map(
+            rusqlite::params![
+                resident_id, format!("{} {}", resident.first_name, resident.last_name), resident.apt, resident_property,
+            ],
+            |row| row.get(0),
+        )?
+        .collect::<Result<_, _>>()?;
+
+    let mut report = ErasureReport { cards: card_ids.len(), ..Default::default() };
+    tx.execute(
+        "UPDATE card SET hash = 'erased:' || id, status = 'revoked', revoked_date = COALESCE(revoked_date, ?1)
+         WHERE resident_id = ?2",
+        rusqlite::params![erased_at, resident_id],
+    )?;
+    report.package_comments = tx.execute(
+        "UPDATE package SET comment = NULL WHERE collected_by = ?1 AND comment IS NOT NULL AND comment != ''",
+        [resident_id],
+    )?;
+    let signature_ids: Vec<u32> = tx.prepare(
+        "SELECT DISTINCT signature_id FROM package WHERE collected_by = ?1 AND signature_id IS NOT NULL"
+    )?
+        .query_map([resident_id], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    tx.execute("UPDATE package SET signature_id = NULL WHERE collected_by = ?1", [resident_id])?;
+    for signature_id in &signature_ids {
+        report.signatures += tx.execute("DELETE FROM signature WHERE id = ?1", [signature_id])?;
+    }
+    const COLLECTED: &str = "SELECT id FROM package WHERE collected_by = ?1";
+    let photo_files = photo::photo_files(&tx, COLLECTED, [resident_id])?;
+    report.photos = tx.execute(&format!("DELETE FROM package_photo WHERE package_id IN ({})", COLLECTED), [resident_id])?;
+    tx.execute(
+        "UPDATE resident SET first_name = ?1, last_name = ?2, linked = 0, erased_at = ?3 WHERE id = ?4",
+        rusqlite::params![ERASED_NAME, format!("#{}", resident_id), erased_at, resident_id],
+    )?;
+
+    // Free text and card hashes go; the IDs that tie entries together stay
+    let keep = |key: &str| !matches!(key, "card_hash" | "card_hash_prefix" | "reason" | "name" | "note");
+    let log_action = format!("Personal data of resident {} (Apt {}) erased", resident_id, resident.apt);
+    report.log_entries = audit::redact(
+        &tx, &log_ids, &keep, "erase", &log_action,
+        serde_json::json!({ "resident_id": resident_id, "apt": resident.apt }),
+    )?;
+    if report.log_entries == 0 {
+        add_log_with_payload(&tx, "erase", &log_action, serde_json::json!({
+            "resident_id": resident_id, "apt": resident.apt,
+        }))?;
+    }
+    tx.commit()?;
+    photo::delete_unreferenced(db, photos, &photo_files)?;
+    Ok(report)
+}
+
+/// Redacts log entries of the configured types older than the retention period, in every
+/// building. They keep their building and package. Returns how many entries were redacted.
+/// Runs at startup, so it needs no login; the policy itself is set by an admin.
+pub fn apply_retention(db: &Connection, policy: &RetentionConfig) -> Result<usize, Error> {
+    if policy.anonymise_log_after_months == 0 || policy.anonymise_log_types.is_empty() {
+        return Ok(0);
+    }
+    let cutoff = Local::now()
+        .checked_sub_months(Months::new(policy.anonymise_log_after_months))
+        .ok_or_else(|| app_error("Retention period is too long"))?
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string();
+
+    let types: Vec<&str> = policy.anonymise_log_types.iter()
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty() && !PROTECTED_LOG_TYPES.contains(t))
+        .collect();
+    if types.is_empty() {
+        return Ok(0);
+    }
+    let placeholders = vec!["?"; types.len()].join(", ");
+    let mut params: Vec<&dyn rusqlite::ToSql> = vec![&cutoff];
+    params.extend(types.iter().map(|t| t as &dyn rusqlite::ToSql));
+    let log_ids: Vec<u32> = db.prepare(&format!(
+        "SELECT id FROM log WHERE redacted_at IS NULL AND date_time < ? AND action_type IN ({}) ORDER BY id",
+        placeholders
+    ))?
+        .query_map(params.as_slice(), |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    if log_ids.is_empty() {
+        return Ok(0);
+    }
+
+    let tx = db.unchecked_transaction()?;
+    let keep = |key: &str| matches!(key, "property_id" | "package_id" | "unit_id");
+    let count = audit::redact(
+        &tx, &log_ids, &keep, "retention",
+        &format!("Anonymised {} log entries written before {}", log_ids.len(), cutoff),
+        serde_json::json!({ "before": cutoff, "action_types": types }),
+    )?;
+    tx.commit()?;
+    Ok(count)
+}
+
+/// Everything stored about one resident, gathered for a subject access request.
+/// Card hashes and UIDs are left out, also from the log.
+pub struct SubjectAccess {
+    pub resident_id: u32,
+    pub name: String,
+    pub generated: String,
+    /// `resident` (one row), `cards`, `packages`, `signatures`, `photos` and `log`, in that order
+    pub sections: Vec<(&'static str, ExportData)>,
+    /// Photo store the `file` column of `photos` is relative to
+    pub photo_dir: PathBuf,
+}
+
+/// Gathers the resident row, cards, packages, signatures, photos and log entries of a resident
+/// of the current building, including one that was removed or erased
+pub fn subject_access(db: &Connection, resident_id: u32, photos: &PhotoSettings) -> Result<SubjectAccess, Error> {
+    require_permission(db, Permission::Export)?;
+
+    let resident_property: u32 = db
+        .query_row("SELECT property_id FROM resident WHERE id = ?1", [resident_id], |row| row.get(0))
+        .optional()?
+        .ok_or_else(|| app_error(format!("No resident with ID {}", resident_id)))?;
+    if resident_property != property::current_property_id(db)? {
+        return Err(app_error("Resident belongs to another building"));
+    }
+    let resident = get_resident_info(db, resident_id)?;
+    let name = format!("{} {}", resident.first_name, resident.last_name);
+    let params = [resident_id.to_string()];
+
+    let resident_row = export::query_export_data(db,
+        "SELECT r.id, r.apt, r.first_name, r.last_name, b.name AS building, r.linked, r.status,
+                r.moved_in, r.moved_out, r.deleted_at, r.erased_at
+         FROM resident r LEFT JOIN property b ON b.id = r.property_id
+         WHERE r.id = ?1",
+        &params,
+    )?;
+    let cards = export::query_export_data(db,
+        "SELECT id, apt, added_date, status, revoked_date, deleted_at FROM card WHERE resident_id = ?1 ORDER BY id",
+        &params,
+    )?;
+    let packages = export::query_export_data(db,
+        "SELECT id, apt, package_number, barcode, carrier, comment, date_time, status, collection_time, deleted_at
+         FROM package
+         WHERE collected_by = ?1 OR id IN (SELECT package_id FROM log WHERE resident_id = ?1)
+         ORDER BY date_time",
+        &params,
+    )?;
+    // The drawing as strokes and as a PNG, which the JSON carries in hex like other binary values
+    let signatures = export::query_export_data(db,
+        "SELECT s.id, s.signed_at, group_concat(p.id, ', ') AS packages, s.strokes, s.png
+         FROM signature s JOIN package p ON p.signature_id = s.id
+         WHERE p.collected_by = ?1
+         GROUP BY s.id ORDER BY s.signed_at",
+        &params,
+    )?;
+    let package_photos = export::query_export_data(db,
+        "SELECT ph.id, ph.package_id, ph.stage, ph.original_name, ph.added_at, ph.file
+         FROM package_photo ph JOIN package p ON p.id = ph.package_id
+         WHERE p.collected_by = ?1 OR p.id IN (SELECT package_id FROM log WHERE resident_id = ?1)
+         ORDER BY ph.id",
+        &params,
+    )?;
+    // Link entries spell out the card UID, and older ones the hash, before the resident.
+    // Hashes, and the prefixes kept to tell cards apart, stay out of the details.
+    // Entries are matched the same way `erase_resident` matches them.
+    let log = export::query_export_data(db, &format!(
+        "SELECT l.id, l.date_time, l.action_type,
+                CASE WHEN l.action_type = 'linked' AND instr(l.action, 'Resident ID=') > 0
+                     THEN 'Card linked: ' || substr(l.action, instr(l.action, 'Resident ID='))
+                     WHEN l.action_type = 'linked' AND instr(l.action, ' was linked to resident ID: ') > 0
+                     THEN 'Card linked to ' || substr(l.action, instr(l.action, ' was linked to resident ID: ') + 15)
+                     ELSE l.action END AS action,
+                o.display_name AS operator,
+                json_remove(l.payload, '$.card_hash', '$.card_hash_prefix') AS details, l.redacted_at
+         FROM log l LEFT JOIN operator o ON o.id = l.operator_id
+         WHERE {} OR {}
+         ORDER BY l.id",
+        RESIDENT_LOG_ENTRIES, LEGACY_RESIDENT_LOG_ENTRIES
+    ), &[resident_id.to_string(), name.clone(), resident.apt.clone(), resident_property.to_string()])?;
+
+    Ok(SubjectAccess {
+        resident_id,
+        name,
+        generated: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        sections: vec![
+            ("resident", resident_row), ("cards", cards), ("packages", packages), ("signatures", signatures),
+            ("photos", package_photos), ("log", log),
+        ],
+        photo_dir: photos.dir.clone(),
+    })
+}
+
+impl SubjectAccess {
+    /// Machine-readable bundle: the resident as an object, every other section as an array
+    pub fn to_json(&self) -> String {
+        let mut bundle = serde_json::Map::new();
+        bundle.insert("generated".to_string(), self.generated.clone().into());
+        for (section, data) in &self.sections {
+            let mut rows = export::json_rows(data);
+            let value = if *section == "resident" {
+                rows.pop().unwrap_or_default()
+            } else {
+                serde_json::Value::Array(rows)
+            };
+            bundle.insert(section.to_string(), value);
+        }
+        serde_json::to_string_pretty(&bundle).unwrap_or_else(|_| "{}".to_string())
+    }
+
+    /// Human-readable report, one table per section
+    pub fn to_html(&self) -> String {
+        let mut html = export::html_page_start(&format!("Data held about {}", self.name));
+        html.push_str(&format!("<p>Resident #{}, generated {}</p>\n", self.resident_id, self.generated));
+
+        for (section, data) in &self.sections {
+            let title = match *section {
+                "resident" => "Resident",
+                "cards" => "Cards",
+                "packages" => "Packages",
+                "signatures" => "Signatures",
+                "photos" => "Photos",
+                _ => "Log entries",
+            };
+            if *section == "resident" {
+                // A single row reads better as field/value pairs
+                html.push_str(&format!("<h2>{}</h2>\n<table>\n", title));
+                if let Some(row) = data.rows.first() {
+                    for (column, value) in data.columns.iter().zip(row) {
+                        html.push_str(&format!(
+                            "<tr><th>{}</th><td>{}</td></tr>\n",
+                            export::html_escape(column), export::html_escape(&export::display_value(value))
+                        ));
+                    }
+                }
+                html.push_str("</table>\n");
+                continue;
+            }
+            if *section == "signatures" {
+                // Shown as drawn rather than as stroke data
+                html.push_str(&format!("<h2>{} ({})</h2>\n<table>\n<tr><th>Signed</th><th>Packages</th><th>Signature</th></tr>\n", title, data.rows.len()));
+                for row in &data.rows {
+                    let png = match row.get(4) {
+                        Some(rusqlite::types::Value::Blob(png)) => export::data_uri("image/png", png),
+                        _ => String::new(),
+                    };
+                    html.push_str(&format!(
+                        "<tr><td>{}</td><td>{}</td><td><img src=\"{}\" alt=\"Signature\"></td></tr>\n",
+                        export::html_escape(&export::display_value(&row[1])),
+                        export::html_escape(&export::display_value(&row[2])),
+                        png
+                    ));
+                }
+                html.push_str("</table>\n");
+                continue;
+            }
+
+            if *section == "photos" {
+                // Embedded, so the report is complete on its own
+                html.push_str(&format!("<h2>{} ({})</h2>\n<table>\n<tr><th>Package</th><th>Taken</th><th>Added</th><th>Photo</th></tr>\n", title, data.rows.len()));
+                for row in &data.rows {
+                    let file = export::display_value(&row[5]);
+                    let image = match photo::image_data_uri(&self.photo_dir.join(&file)) {
+                        Ok(uri) => format!("<img src=\"{}\" alt=\"Photo\" style=\"max-width:480px\">", uri),
+                        Err(e) => export::html_escape(&format!("{} could not be read: {}", file, e)),
+                    };
+                    html.push_str(&format!(
+                        "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                        export::html_escape(&export::display_value(&row[1])),
+                        export::html_escape(&export::display_value(&row[2])),
+                        export::html_escape(&export::display_value(&row[4])),
+                        image
+                    ));
+                }
+                html.push_str("</table>\n");
+                continue;
+            }
+
+            html.push_str(&format!("<h2>{} ({})</h2>\n", title, data.rows.len()));
+            html.push_str(&export::html_table(data));
+        }
+
+        html.push_str("</body></html>\n");
+        html
+    }
+}
+
+/// Writes a resident's subject access bundle to `dir` as JSON and HTML,
+/// e.g. `exports/subject-access-12-20250101-173000.json`. Returns both paths.
+pub fn export_subject_access(
+    db: &Connection,
+    resident_id: u32,
+    dir: &Path,
+    photos: &PhotoSettings,
+) -> Result<(PathBuf, PathBuf), Box<dyn std::error::Error>> {
+    let access = subject_access(db, resident_id, photos)?;
+    let stamp = Local::now().format("%Y%m%d-%H%M%S");
+    let json_path = dir.join(format!("subject-access-{}-{}.json", resident_id, stamp));
+    let html_path = json_path.with_extension("html");
+
+    if !dir.as_os_str().is_empty() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&json_path, access.to_json())?;
+    fs::write(&html_path, access.to_html())?;
+    Ok((json_path, html_path))
+}
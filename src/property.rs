@@ -0,0 +1,203 @@
+use rusqlite::{Connection, Error, OptionalExtension};
+
+use crate::{add_log_with_payload, app_error};
+use crate::operator::{self, Permission, require_permission};
+
+/// The building every record belonged to before properties existed
+pub const DEFAULT_PROPERTY_ID: u32 = 1;
+
+/// SQL for the id of the building this connection is working in
+pub(crate) const CURRENT_PROPERTY: &str = "(SELECT property_id FROM temp.current_property)";
+
+/// A building managed from this installation. Units, residents, packages and
+/// log entries belong to one; operators may be limited to one.
+#[derive(Debug, Clone)]
+pub struct Property {
+    pub id: u32,
+    pub name: String,
+    pub address: String,
+    pub status: String,
+}
+
+const PROPERTY_COLUMNS: &str = "id, name, address, status";
+
+fn row_to_property(row: &rusqlite::Row) -> Result<Property, Error> {
+    Ok(Property {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        address: row.get(2)?,
+        status: row.get(3)?,
+    })
+}
+
+// Like the operator session, the selected building is per connection and never
+// stored in the file. A new connection starts in the oldest building.
+pub(crate) fn init_session(db: &Connection) -> Result<(), Error> {
+    db.execute_batch(&format!("
+        CREATE TEMP TABLE IF NOT EXISTS current_property (
+            property_id INTEGER NOT NULL
+        );
+        INSERT INTO temp.current_property (property_id)
+        SELECT COALESCE(MIN(id), {}) FROM property
+        WHERE NOT EXISTS (SELECT 1 FROM temp.current_property);
+    ", DEFAULT_PROPERTY_ID))
+}
+
+pub fn current_property_id(db: &Connection) -> Result<u32, Error> {
+    db.query_row("SELECT property_id FROM temp.current_property", [], |row| row.get(0))
+}
+
+pub fn current_property(db: &Connection) -> Result<Property, Error> {
+    db.query_row(
+        &format!("SELECT {} FROM property WHERE id = {}", PROPERTY_COLUMNS, CURRENT_PROPERTY),
+        [],
+        row_to_property,
+    )
+}
+
+pub fn get_properties(db: &Connection) -> Result<Vec<Property>, Error> {
+    let mut stmt = db.prepare(&format!("SELECT {} FROM property ORDER BY name", PROPERTY_COLUMNS))?;
+    let rows = stmt.query_map([], row_to_property)?;
+    rows.collect()
+}
+
+pub fn find_property(db: &Connection, name: &str) -> Result<Property, Error> {
+    db.query_row(
+        &format!("SELECT {} FROM property WHERE name = ?1", PROPERTY_COLUMNS),
+        [name.trim()],
+        row_to_property,
+    ).optional()?
+    .ok_or_else(|| app_error(format!("No building named '{}'", name.trim())))
+}
+
+/// Buildings the logged-in operator may switch to: all of them, unless the
+/// account is limited to one
+pub fn accessible_properties(db: &Connection) -> Result<Vec<Property>, Error> {
+    let limited_to = operator::current_operator(db)?.and_then(|staff| staff.property_id);
+    Ok(get_properties(db)?
+        .into_iter()
+        .filter(|p| p.status == "active" && limited_to.is_none_or(|id| id == p.id))
+        .collect())
+}
+
+pub fn add_property(db: &Connection, name: &str, address: &str) -> Result<u32, Error> {
+    require_permission(db, Permission::ManageProperties)?;
+
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(app_error("Building name is required"));
+    }
+    if find_property(db, name).is_ok() {
+        return Err(app_error(format!("Building '{}' already exists", name)));
+    }
+
+    db.execute(
+        "INSERT INTO property (name, address) VALUES (?1, ?2)",
+        rusqlite::params![name, address.trim()],
+    )?;
+    let id = db.last_insert_rowid() as u32;
+
+    add_log_with_payload(
+        db, "property", &format!("Building added: {}", name),
+        serde_json::json!({ "target_property_id": id, "name": name }),
+    )?;
+    Ok(id)
+}
+
+/// Makes `property_id` the building this connection works in. Operators limited
+/// to another building are refused.
+pub fn switch_property(db: &Connection, property_id: u32) -> Result<Property, Error> {
+    let property = accessible_properties(db)?
+        .into_iter()
+        .find(|p| p.id == property_id)
+        .ok_or_else(|| app_error("This account cannot work in that building"))?;
+
+    if property.id != current_property_id(db)? {
+        set_current(db, property.id)?;
+        add_log_with_payload(
+            db, "property", &format!("Switched to building {}", property.name),
+            serde_json::json!({}),
+        )?;
+    }
+    Ok(property)
+}
+
+// Used by login, which moves an operator limited to one building into it
+pub(crate) fn set_current(db: &Connection, property_id: u32) -> Result<(), Error> {
+    db.execute("UPDATE temp.current_property SET property_id = ?1", [property_id])?;
+    Ok(())
+}
+
+/// Whether a resident card issued in `card_property_id` opens this building's reader.
+/// Cards always work in their own building; elsewhere only where allowed.
+pub fn accepts_cards_from(db: &Connection, card_property_id: u32) -> Result<bool, Error> {
+    db.query_row(
+        &format!(
+            "SELECT ?1 = {current} OR EXISTS(
+                 SELECT 1 FROM property_card_access
+                 WHERE property_id = {current} AND accepts_property_id = ?1
+             )",
+            current = CURRENT_PROPERTY
+        ),
+        [card_property_id],
+        |row| row.get(0),
+    )
+}
+
+/// Lets readers in `property_id` accept (or stop accepting) cards issued in `card_property_id`
+pub fn set_card_access(db: &Connection, property_id: u32, card_property_id: u32, allowed: bool) -> Result<(), Error> {
+    require_permission(db, Permission::ManageProperties)?;
+    if property_id == card_property_id {
+        return Err(app_error("Cards are always accepted in their own building"));
+    }
+
+    if allowed {
+        db.execute(
+            "INSERT OR IGNORE INTO property_card_access (property_id, accepts_property_id) VALUES (?1, ?2)",
+            [property_id, card_property_id],
+        )?;
+    } else {
+        db.execute(
+            "DELETE FROM property_card_access WHERE property_id = ?1 AND accepts_property_id = ?2",
+            [property_id, card_property_id],
+        )?;
+    }
+
+    let verb = if allowed { "now accepts" } else { "no longer accepts" };
+    add_log_with_payload(
+        db, "property", &format!("Building {} {} cards from building {}", property_id, verb, card_property_id),
+        serde_json::json!({
+            "target_property_id": property_id, "card_property_id": card_property_id, "allowed": allowed,
+        }),
+    )
+}
+
+/// Buildings whose cards `property_id` accepts besides its own
+pub fn card_access(db: &Connection, property_id: u32) -> Result<Vec<Property>, Error> {
+    let mut stmt = db.prepare(&format!(
+        "SELECT {} FROM property WHERE id IN (
+             SELECT accepts_property_id FROM property_card_access WHERE property_id = ?1
+         ) ORDER BY name",
+        PROPERTY_COLUMNS
+    ))?;
+    let rows = stmt.query_map([property_id], row_to_property)?;
+    rows.collect()
+}
+
+/// Limits an operator to one building, or lets them work in all with `None`
+pub fn set_operator_property(db: &Connection, operator_id: u32, property_id: Option<u32>) -> Result<(), Error> {
+    require_permission(db, Permission::ManageOperators)?;
+    db.execute(
+        "UPDATE operator SET property_id = ?1 WHERE id = ?2",
+        rusqlite::params![property_id, operator_id],
+    )?;
+
+    let scope = match property_id {
+        Some(id) => format!("limited to building {}", id),
+        None => "allowed in all buildings".to_string(),
+    };
+    add_log_with_payload(
+        db, "operator", &format!("Operator ID {} {}", operator_id, scope),
+        serde_json::json!({ "target_operator_id": operator_id, "target_property_id": property_id }),
+    )
+}
@@ -0,0 +1,289 @@
+use rusqlite::{Connection, Error};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::{Local, NaiveDate, NaiveTime};
+
+use crate::app_error;
+use crate::export::{self, ExportData};
+use crate::operator::{Permission, require_permission};
+use crate::pdf::{PdfWriter, Style};
+use crate::property::{self, CURRENT_PROPERTY};
+
+#[derive(Debug, Clone)]
+pub struct ReportSettings {
+    pub dir: PathBuf,
+    /// Time of day after which the day's report is written; it is completed after midnight
+    pub daily_at: NaiveTime,
+    pub overdue_after_days: u32,
+}
+
+impl Default for ReportSettings {
+    fn default() -> Self {
+        ReportSettings {
+            dir: PathBuf::from("reports"),
+            daily_at: NaiveTime::from_hms_opt(22, 0, 0).unwrap_or_default(),
+            overdue_after_days: 7,
+        }
+    }
+}
+
+/// Intake and collection sheet for one building over a range of days.
+/// Outstanding and overdue packages are as of the end of the last day.
+pub struct PackageReport {
+    pub building: String,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub generated: String,
+    pub overdue_after_days: u32,
+    /// `received`, `collected`, `outstanding`, `overdue` and `unknown_cards`, in that order
+    pub sections: Vec<(&'static str, ExportData)>,
+}
+
+// Pending at the end of ?2: received by then and not collected or returned by then
+const OUTSTANDING: &str = "
+    p.deleted_at IS NULL AND date(p.date_time) <= ?2
+    AND (p.collection_time IS NULL OR date(p.collection_time) > ?2)";
+
+/// Parses a report range. An empty start means today and an empty end means the start day.
+pub fn parse_range(from: &str, to: &str) -> Result<(NaiveDate, NaiveDate), Error> {
+    let parse = |date: &str| NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+        .map_err(|_| app_error(format!("Invalid date '{}', expected YYYY-MM-DD", date.trim())));
+    let from = match from.trim() {
+        "" => Local::now().date_naive(),
+        date => parse(date)?,
+    };
+    let to = match to.trim() {
+        "" => from,
+        date => parse(date)?,
+    };
+    if to < from {
+        return Err(app_error("The report range ends before it starts"));
+    }
+    Ok((from, to))
+}
+
+/// Builds the report for the current building
+pub fn package_report(db: &Connection, from: NaiveDate, to: NaiveDate, overdue_after_days: u32) -> Result<PackageReport, Error> {
+    require_permission(db, Permission::Export)?;
+    build_report(db, from, to, overdue_after_days)
+}
+
+// Without a permission check, for the scheduler, which runs without a login
+fn build_report(db: &Connection, from: NaiveDate, to: NaiveDate, overdue_after_days: u32) -> Result<PackageReport, Error> {
+    let params = [from.to_string(), to.to_string(), overdue_after_days.to_string()];
+
+    let received = export::query_export_data(db, &format!(
+        "SELECT p.date_time AS received, p.apt, p.package_number, p.carrier, o.display_name AS received_by, p.status
+         FROM package p LEFT JOIN operator o ON o.id = p.received_by
+         WHERE p.property_id = {} AND p.deleted_at IS NULL AND date(p.date_time) BETWEEN ?1 AND ?2
+         ORDER BY p.date_time",
+        CURRENT_PROPERTY
+    ), &params[..2])?;
+    let collected = export::query_export_data(db, &format!(
+        "SELECT p.collection_time AS collected, p.apt, p.package_number, p.carrier,
+                CASE WHEN p.status = 'returned' THEN 'returned to sender'
+                     WHEN r.id IS NULL THEN 'manual override'
+                     ELSE r.first_name || ' ' || r.last_name END AS collected_by
+         FROM package p LEFT JOIN resident r ON r.id = p.collected_by
+         WHERE p.property_id = {} AND p.deleted_at IS NULL AND p.status IN ('collected', 'returned')
+           AND date(p.collection_time) BETWEEN ?1 AND ?2
+         ORDER BY p.collection_time",
+        CURRENT_PROPERTY
+    ), &params[..2])?;
+    let outstanding = export::query_export_data(db, &format!(
+        "SELECT p.apt, COUNT(*) AS packages, MIN(p.date_time) AS oldest
+         FROM package p
+         WHERE p.property_id = {} AND {}
+         GROUP BY p.apt ORDER BY p.apt",
+        CURRENT_PROPERTY, OUTSTANDING
+    ), &params[..2])?;
+    let overdue = export::query_export_data(db, &format!(
+        "SELECT p.date_time AS received, p.apt, p.package_number, p.carrier,
+                CAST(julianday(?2) - julianday(date(p.date_time)) AS INTEGER) AS days_waiting
+         FROM package p
+         WHERE p.property_id = {} AND {}
+           AND julianday(?2) - julianday(date(p.date_time)) > CAST(?3 AS INTEGER)
+         ORDER BY p.date_time",
+        CURRENT_PROPERTY, OUTSTANDING
+    ), &params)?;
+    let unknown_cards = export::query_export_data(db, &format!(
+        "SELECT l.date_time AS time, o.display_name AS operator_on_duty
+         FROM log l LEFT JOIN operator o ON o.id = l.operator_id
+         WHERE l.action_type = 'unknown_card' AND l.property_id = {} AND date(l.date_time) BETWEEN ?1 AND ?2
+         ORDER BY l.id",
+        CURRENT_PROPERTY
+    ), &params[..2])?;
+
+    Ok(PackageReport {
+        building: property::current_property(db)?.name,
+        from,
+        to,
+        generated: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        overdue_after_days,
+        sections: vec![
+            ("received", received),
+            ("collected", collected),
+            ("outstanding", outstanding),
+            ("overdue", overdue),
+            ("unknown_cards", unknown_cards),
+        ],
+    })
+}
+
+impl PackageReport {
+    pub fn title(&self) -> String {
+        if self.from == self.to {
+            format!("{}: packages on {}", self.building, self.from)
+        } else {
+            format!("{}: packages {} to {}", self.building, self.from, self.to)
+        }
+    }
+
+    fn section(&self, name: &str) -> &ExportData {
+        &self.sections.iter().find(|(section, _)| *section == name).expect("every report has all sections").1
+    }
+
+    /// Summary lines printed above the tables
+    pub fn summary(&self) -> Vec<String> {
+        let outstanding = self.section("outstanding");
+        let waiting: i64 = outstanding.rows.iter()
+            .filter_map(|row| match row.get(1) {
+                Some(rusqlite::types::Value::Integer(count)) => Some(*count),
+                _ => None,
+            })
+            .sum();
+        vec![
+            format!("Received: {}", self.section("received").rows.len()),
+            format!("Collected or returned: {}", self.section("collected").rows.len()),
+            format!("Outstanding: {} in {} apartment(s)", waiting, outstanding.rows.len()),
+            format!("Overdue (waiting more than {} days): {}", self.overdue_after_days, self.section("overdue").rows.len()),
+            format!("Unknown card attempts: {}", self.section("unknown_cards").rows.len()),
+        ]
+    }
+
+    fn section_title(&self, name: &str) -> String {
+        match name {
+            "received" => "Received".to_string(),
+            "collected" => "Collected".to_string(),
+            "outstanding" => format!("Outstanding by apartment at the end of {}", self.to),
+            "overdue" => format!("Overdue (waiting more than {} days)", self.overdue_after_days),
+            _ => "Unknown card attempts".to_string(),
+        }
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut html = export::html_page_start(&self.title());
+        html.push_str(&format!("<p>Generated {}</p>\n<ul>\n", self.generated));
+        for line in self.summary() {
+            html.push_str(&format!("<li>{}</li>\n", export::html_escape(&line)));
+        }
+        html.push_str("</ul>\n");
+        for (name, data) in &self.sections {
+            html.push_str(&format!("<h2>{}</h2>\n", export::html_escape(&self.section_title(name))));
+            html.push_str(&export::html_table(data));
+        }
+        html.push_str("</body></html>\n");
+        html
+    }
+
+    pub fn to_pdf(&self) -> Vec<u8> {
+        let mut pdf = PdfWriter::new();
+        pdf.line(Style::Title, &self.title());
+        pdf.line(Style::Text, &format!("Generated {}", self.generated));
+        pdf.gap(6.0);
+        for line in self.summary() {
+            pdf.line(Style::Text, &line);
+        }
+        for (name, data) in &self.sections {
+            pdf.gap(10.0);
+            pdf.line(Style::Heading, &self.section_title(name));
+            pdf.table(data);
+        }
+        pdf.finish()
+    }
+
+    // e.g. `packages-main-building-2025-01-01` or `packages-main-building-2025-01-01_2025-01-07`
+    fn file_stem(&self) -> String {
+        report_stem(&self.building, self.from, self.to)
+    }
+}
+
+fn report_stem(building: &str, from: NaiveDate, to: NaiveDate) -> String {
+    let slug: String = building.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if from == to {
+        format!("packages-{}-{}", slug, from)
+    } else {
+        format!("packages-{}-{}_{}", slug, from, to)
+    }
+}
+
+/// Writes the report to `dir` as HTML and PDF and returns both paths
+pub fn write_report(report: &PackageReport, dir: &Path) -> Result<(PathBuf, PathBuf), Box<dyn std::error::Error>> {
+    fs::create_dir_all(dir)?;
+    let html_path = dir.join(format!("{}.html", report.file_stem()));
+    let pdf_path = dir.join(format!("{}.pdf", report.file_stem()));
+    fs::write(&html_path, report.to_html())?;
+    fs::write(&pdf_path, report.to_pdf())?;
+    Ok((html_path, pdf_path))
+}
+
+// Writes the day's report for every active building, skipping buildings that
+// already have one unless `replace` is set
+fn write_daily_reports(db: &Connection, settings: &ReportSettings, day: NaiveDate, replace: bool) -> Result<(), Box<dyn std::error::Error>> {
+    for building in property::get_properties(db)? {
+        if building.status != "active"
+            || (!replace && settings.dir.join(format!("{}.pdf", report_stem(&building.name, day, day))).exists()) {
+            continue;
+        }
+        property::set_current(db, building.id)?;
+        let report = build_report(db, day, day, settings.overdue_after_days)?;
+        let (_, pdf_path) = write_report(&report, &settings.dir)?;
+        println!("📊 Daily report written to {}", pdf_path.display());
+    }
+    Ok(())
+}
+
+/// Starts a background thread that writes each building's report for the day
+/// once `settings.daily_at` has passed, and writes it again after midnight so
+/// it covers the whole day
+pub fn start_report_scheduler(db_path: PathBuf, settings: ReportSettings) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut db: Option<Connection> = None;
+        let mut day = Local::now().date_naive();
+        loop {
+            if db.is_none() {
+                match crate::open_db(&db_path).and_then(|conn| {
+                    conn.busy_timeout(Duration::from_secs(5))?;
+                    Ok(conn)
+                }) {
+                    Ok(conn) => db = Some(conn),
+                    Err(e) => println!("❌ Scheduled report failed: {}", e),
+                }
+            }
+
+            if let Some(db) = &db {
+                let now = Local::now();
+                let result = if now.date_naive() != day {
+                    // Packages kept arriving after the report was written
+                    write_daily_reports(db, &settings, day, true)
+                } else if now.time() >= settings.daily_at {
+                    write_daily_reports(db, &settings, day, false)
+                } else {
+                    Ok(())
+                };
+
+                match result {
+                    Ok(()) => day = now.date_naive(),
+                    Err(e) => println!("❌ Scheduled report failed: {}", e),
+                }
+            }
+
+            std::thread::sleep(Duration::from_secs(60));
+        }
+    })
+}
@@ -0,0 +1,400 @@
+use device_query::{DeviceQuery, DeviceState, Keycode};
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// How often the global listener samples the keyboard; scanners hold each key for a few milliseconds
+const GLOBAL_POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+// The same code again within this window is the scan field and the listener seeing one scan
+const REPEAT_WINDOW: Duration = Duration::from_millis(1500);
+
+/// A scan ending this soon after typing in a text field of the app went into that field
+pub const FIELD_ENTRY_WINDOW: Duration = Duration::from_millis(500);
+
+/// Where scanner keystrokes are read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanSource {
+    /// Every keyboard, whichever window has focus. Needs an X11 session on Linux.
+    Global,
+    /// One input device, e.g. `/dev/input/by-id/usb-...-event-kbd` (Linux only).
+    /// The device is grabbed, so its keystrokes no longer reach other windows.
+    Device(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Terminator {
+    Enter,
+    Tab,
+}
+
+impl Terminator {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "enter" => Some(Terminator::Enter),
+            "tab" => Some(Terminator::Tab),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WedgeSettings {
+    pub source: ScanSource,
+    /// Longest pause between two keystrokes of one scan. People type much slower than this.
+    pub max_gap: Duration,
+    /// Shorter bursts are ignored, so quick typing isn't taken for a scan
+    pub min_length: usize,
+    pub terminator: Terminator,
+}
+
+impl Default for WedgeSettings {
+    fn default() -> Self {
+        WedgeSettings {
+            source: ScanSource::Global,
+            max_gap: Duration::from_millis(50),
+            min_length: 4,
+            terminator: Terminator::Enter,
+        }
+    }
+}
+
+/// A key press as a wedge scanner sends it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanKey {
+    Char(char),
+    Enter,
+    Tab,
+    /// Anything else, which ends a burst without a scan
+    Other,
+}
+
+/// Turns key presses into scans: characters arriving no more than `max_gap`
+/// apart, ended by the terminator, at least `min_length` long
+pub struct BurstDecoder {
+    settings: WedgeSettings,
+    buffer: String,
+    last_key: Option<Instant>,
+}
+
+impl BurstDecoder {
+    pub fn new(settings: WedgeSettings) -> Self {
+        BurstDecoder { settings, buffer: String::new(), last_key: None }
+    }
+
+    /// Feeds one key press; returns the code when it completes a scan
+    pub fn push(&mut self, key: ScanKey, at: Instant) -> Option<String> {
+        if self.last_key.is_some_and(|last| at.saturating_duration_since(last) > self.settings.max_gap) {
+            self.buffer.clear();
+        }
+        self.last_key = Some(at);
+
+        let terminated = match (key, self.settings.terminator) {
+            (ScanKey::Char(c), _) => {
+                self.buffer.push(c);
+                return None;
+            }
+            (ScanKey::Enter, Terminator::Enter) | (ScanKey::Tab, Terminator::Tab) => true,
+            _ => false,
+        };
+        let code = std::mem::take(&mut self.buffer);
+        (terminated && code.chars().count() >= self.settings.min_length).then_some(code)
+    }
+}
+
+/// Drops a scan that repeats the previous one within a moment, which happens
+/// when the focused scan field and a scanner listener both see it
+#[derive(Default)]
+pub struct RepeatFilter {
+    last: Option<(String, Instant)>,
+}
+
+impl RepeatFilter {
+    pub fn is_repeat(&mut self, code: &str, at: Instant) -> bool {
+        let repeat = self.last.as_ref()
+            .is_some_and(|(last, when)| last == code && at.saturating_duration_since(*when) < REPEAT_WINDOW);
+        self.last = Some((code.to_string(), at));
+        repeat
+    }
+}
+
+/// Starts a background thread that reads scanner keystrokes from `settings.source`
+/// and calls `on_scan` with each complete code
+pub fn start_wedge_listener<F>(settings: WedgeSettings, on_scan: F) -> std::thread::JoinHandle<()>
+where
+    F: Fn(String) + Send + 'static,
+{
+    std::thread::spawn(move || {
+        let source = settings.source.clone();
+        let mut decoder = BurstDecoder::new(settings);
+        let result = match &source {
+            ScanSource::Global => listen_global(&mut decoder, &on_scan),
+            ScanSource::Device(path) => listen_device(path, &mut decoder, &on_scan),
+        };
+        if let Err(e) = result {
+            println!("⚠️  Barcode scanner listener stopped: {}", e);
+        }
+    })
+}
+
+// Samples the pressed keys and feeds new presses to the decoder
+fn listen_global(decoder: &mut BurstDecoder, on_scan: &dyn Fn(String)) -> io::Result<()> {
+    let device_state = DeviceState::checked_new()
+        .ok_or_else(|| io::Error::other("keyboard monitoring is unavailable"))?;
+    println!("🔫 Listening for barcode scans on all keyboards");
+
+    let mut previous: Vec<Keycode> = Vec::new();
+    loop {
+        let keys = device_state.get_keys();
+        let now = Instant::now();
+        // Shift is read from the same sample, as it may be reported after the key it modifies
+        let shift = keys.iter().any(|key| matches!(key, Keycode::LShift | Keycode::RShift));
+        for key in keys.iter().filter(|key| !previous.contains(key)) {
+            if matches!(key, Keycode::LShift | Keycode::RShift) {
+                continue;
+            }
+            if let Some(code) = decoder.push(keycode_scan_key(key, shift), now) {
+                on_scan(code);
+            }
+        }
+        previous = keys;
+        std::thread::sleep(GLOBAL_POLL_INTERVAL);
+    }
+}
+
+// Reads key events from one evdev device
+#[cfg(target_os = "linux")]
+fn listen_device(path: &std::path::Path, decoder: &mut BurstDecoder, on_scan: &dyn Fn(String)) -> io::Result<()> {
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::fd::AsRawFd;
+
+    const EV_KEY: u16 = 1;
+    const KEY_LEFTSHIFT: u16 = 42;
+    const KEY_RIGHTSHIFT: u16 = 54;
+    // _IOW('E', 0x90, int)
+    const EVIOCGRAB: libc::c_ulong = 0x4004_4590;
+
+    let mut device = File::open(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("could not open {}: {}", path.display(), e)))?;
+    // SAFETY: EVIOCGRAB takes an int by value and the descriptor is open for the whole call
+    if unsafe { libc::ioctl(device.as_raw_fd(), EVIOCGRAB, 1 as libc::c_int) } != 0 {
+        println!("⚠️  Could not grab {}; its keystrokes also reach the focused window", path.display());
+    }
+    println!("🔫 Listening for barcode scans on {}", path.display());
+
+    // struct input_event: a timeval, then type, code and value
+    let time_size = std::mem::size_of::<libc::timeval>();
+    let mut event = vec![0u8; time_size + 8];
+    let mut shift = false;
+    loop {
+        device.read_exact(&mut event)?;
+        let kind = u16::from_ne_bytes([event[time_size], event[time_size + 1]]);
+        let code = u16::from_ne_bytes([event[time_size + 2], event[time_size + 3]]);
+        let value = i32::from_ne_bytes([event[time_size + 4], event[time_size + 5], event[time_size + 6], event[time_size + 7]]);
+        if kind != EV_KEY {
+            continue;
+        }
+        if code == KEY_LEFTSHIFT || code == KEY_RIGHTSHIFT {
+            shift = value != 0;
+            continue;
+        }
+        // 1 is a press; releases (0) and auto-repeats (2) are skipped
+        if value == 1 {
+            if let Some(scan) = decoder.push(evdev_scan_key(code, shift), Instant::now()) {
+                on_scan(scan);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn listen_device(path: &std::path::Path, _decoder: &mut BurstDecoder, _on_scan: &dyn Fn(String)) -> io::Result<()> {
+    Err(io::Error::other(format!("cannot read {}: scanner devices are only supported on Linux", path.display())))
+}
+
+// The character a US keyboard types for an unshifted key
+fn us_char(c: char, shift: bool) -> char {
+    if !shift {
+        return c;
+    }
+    match c {
+        'a'..='z' => c.to_ascii_uppercase(),
+        '1' => '!', '2' => '@', '3' => '#', '4' => '$', '5' => '%',
+        '6' => '^', '7' => '&', '8' => '*', '9' => '(', '0' => ')',
+        '-' => '_', '=' => '+', '[' => '{', ']' => '}', '\\' => '|',
+        ';' => ':', '\'' => '"', '`' => '~', ',' => '<', '.' => '>', '/' => '?',
+        other => other,
+    }
+}
+
+/// Key press from the global keyboard listener
+pub fn keycode_scan_key(key: &Keycode, shift: bool) -> ScanKey {
+    use Keycode::*;
+    let c = match key {
+        Enter | NumpadEnter => return ScanKey::Enter,
+        Tab => return ScanKey::Tab,
+        Key0 | Numpad0 => '0', Key1 | Numpad1 => '1', Key2 | Numpad2 => '2', Key3 | Numpad3 => '3',
+        Key4 | Numpad4 => '4', Key5 | Numpad5 => '5', Key6 | Numpad6 => '6', Key7 | Numpad7 => '7',
+        Key8 | Numpad8 => '8', Key9 | Numpad9 => '9',
+        A => 'a', B => 'b', C => 'c', D => 'd', E => 'e', F => 'f', G => 'g', H => 'h', I => 'i',
+        J => 'j', K => 'k', L => 'l', M => 'm', N => 'n', O => 'o', P => 'p', Q => 'q', R => 'r',
+        S => 's', T => 't', U => 'u', V => 'v', W => 'w', X => 'x', Y => 'y', Z => 'z',
+        Space => ' ', Minus | NumpadSubtract => '-', Equal => '=', LeftBracket => '[', RightBracket => ']',
+        BackSlash => '\\', Semicolon => ';', Apostrophe => '\'', Grave => '`', Comma => ',',
+        Dot | NumpadDecimal => '.', Slash | NumpadDivide => '/',
+        NumpadAdd => return ScanKey::Char('+'),
+        NumpadMultiply => return ScanKey::Char('*'),
+        _ => return ScanKey::Other,
+    };
+    ScanKey::Char(us_char(c, shift))
+}
+
+/// Key press from a Linux input device, by kernel key code
+pub fn evdev_scan_key(code: u16, shift: bool) -> ScanKey {
+    const KEYS: &str = "\0\x001234567890-=\0\0qwertyuiop[]\0\0asdfghjkl;'`\0\\zxcvbnm,./";
+    let c = match code {
+        28 | 96 => return ScanKey::Enter,
+        15 => return ScanKey::Tab,
+        57 => ' ',
+        71 => '7', 72 => '8', 73 => '9', 74 => '-', 75 => '4', 76 => '5', 77 => '6',
+        78 => '+', 79 => '1', 80 => '2', 81 => '3', 82 => '0', 83 => '.', 55 => '*', 98 => '/',
+        _ => match KEYS.as_bytes().get(usize::from(code)) {
+            Some(&c) if c != 0 => c as char,
+            _ => return ScanKey::Other,
+        },
+    };
+    ScanKey::Char(us_char(c, shift))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoder(terminator: Terminator) -> BurstDecoder {
+        BurstDecoder::new(WedgeSettings { terminator, ..WedgeSettings::default() })
+    }
+
+    // Feeds the text one character per `gap`, then the end key, and returns the scan if any
+    fn type_keys(decoder: &mut BurstDecoder, start: Instant, text: &str, gap: Duration, end: ScanKey) -> Option<String> {
+        let mut at = start;
+        for c in text.chars() {
+            assert_eq!(decoder.push(ScanKey::Char(c), at), None);
+            at += gap;
+        }
+        decoder.push(end, at)
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn a_fast_burst_ended_by_the_terminator_is_a_scan() {
+        let mut decoder = decoder(Terminator::Enter);
+        let code = type_keys(&mut decoder, Instant::now(), "1Z999AA1", ms(5), ScanKey::Enter);
+        assert_eq!(code.as_deref(), Some("1Z999AA1"));
+    }
+
+    #[test]
+    fn a_gap_of_exactly_max_gap_keeps_the_burst() {
+        let mut decoder = decoder(Terminator::Enter);
+        let code = type_keys(&mut decoder, Instant::now(), "PKG-000042", ms(50), ScanKey::Enter);
+        assert_eq!(code.as_deref(), Some("PKG-000042"));
+    }
+
+    #[test]
+    fn typing_slower_than_max_gap_is_not_a_scan() {
+        let mut decoder = decoder(Terminator::Enter);
+        assert_eq!(type_keys(&mut decoder, Instant::now(), "PKG-000042", ms(51), ScanKey::Enter), None);
+    }
+
+    #[test]
+    fn a_pause_inside_a_burst_starts_a_new_one() {
+        let mut decoder = decoder(Terminator::Enter);
+        let start = Instant::now();
+        assert_eq!(decoder.push(ScanKey::Char('x'), start), None);
+        assert_eq!(decoder.push(ScanKey::Char('y'), start + ms(5)), None);
+        // Typed by hand a moment before the scanner fired
+        let code = type_keys(&mut decoder, start + ms(400), "ABCD", ms(5), ScanKey::Enter);
+        assert_eq!(code.as_deref(), Some("ABCD"));
+    }
+
+    #[test]
+    fn a_pause_before_the_terminator_drops_the_burst() {
+        let mut decoder = decoder(Terminator::Enter);
+        let start = Instant::now();
+        for (i, c) in "ABCD".chars().enumerate() {
+            decoder.push(ScanKey::Char(c), start + ms(5 * i as u64));
+        }
+        assert_eq!(decoder.push(ScanKey::Enter, start + ms(15 + 51)), None);
+    }
+
+    #[test]
+    fn bursts_shorter_than_min_length_are_ignored() {
+        let mut decoder = decoder(Terminator::Enter);
+        let start = Instant::now();
+        assert_eq!(type_keys(&mut decoder, start, "abc", ms(5), ScanKey::Enter), None);
+        // The short burst doesn't leak into the next one
+        let code = type_keys(&mut decoder, start + ms(20), "WXYZ", ms(5), ScanKey::Enter);
+        assert_eq!(code.as_deref(), Some("WXYZ"));
+    }
+
+    #[test]
+    fn only_the_configured_terminator_ends_a_scan() {
+        let start = Instant::now();
+        let mut tab = decoder(Terminator::Tab);
+        assert_eq!(type_keys(&mut tab, start, "ABCD", ms(5), ScanKey::Enter), None);
+        let code = type_keys(&mut tab, start + ms(30), "ABCD", ms(5), ScanKey::Tab);
+        assert_eq!(code.as_deref(), Some("ABCD"));
+
+        let mut enter = decoder(Terminator::Enter);
+        assert_eq!(type_keys(&mut enter, start, "ABCD", ms(5), ScanKey::Tab), None);
+    }
+
+    #[test]
+    fn other_keys_end_a_burst_without_a_scan() {
+        let mut decoder = decoder(Terminator::Enter);
+        let start = Instant::now();
+        assert_eq!(type_keys(&mut decoder, start, "ABCD", ms(5), ScanKey::Other), None);
+        assert_eq!(decoder.push(ScanKey::Enter, start + ms(25)), None);
+    }
+
+    #[test]
+    fn a_repeat_inside_the_window_is_dropped() {
+        let mut filter = RepeatFilter::default();
+        let start = Instant::now();
+        assert!(!filter.is_repeat("PKG-000042", start));
+        assert!(filter.is_repeat("PKG-000042", start + ms(200)));
+        // Each repeat restarts the window
+        assert!(filter.is_repeat("PKG-000042", start + ms(1600)));
+    }
+
+    #[test]
+    fn the_same_code_after_the_window_is_a_new_scan() {
+        let mut filter = RepeatFilter::default();
+        let start = Instant::now();
+        assert!(!filter.is_repeat("PKG-000042", start));
+        assert!(!filter.is_repeat("PKG-000042", start + REPEAT_WINDOW));
+    }
+
+    #[test]
+    fn a_different_code_is_never_a_repeat() {
+        let mut filter = RepeatFilter::default();
+        let start = Instant::now();
+        assert!(!filter.is_repeat("PKG-000042", start));
+        assert!(!filter.is_repeat("PKG-000043", start + ms(10)));
+        assert!(!filter.is_repeat("PKG-000042", start + ms(20)));
+    }
+
+    #[test]
+    fn key_codes_follow_the_us_layout() {
+        assert_eq!(evdev_scan_key(30, false), ScanKey::Char('a'));
+        assert_eq!(evdev_scan_key(30, true), ScanKey::Char('A'));
+        assert_eq!(evdev_scan_key(12, true), ScanKey::Char('_'));
+        assert_eq!(evdev_scan_key(28, false), ScanKey::Enter);
+        assert_eq!(evdev_scan_key(42, false), ScanKey::Other);
+        assert_eq!(keycode_scan_key(&Keycode::Key2, true), ScanKey::Char('@'));
+        assert_eq!(keycode_scan_key(&Keycode::NumpadEnter, false), ScanKey::Enter);
+    }
+}
@@ -0,0 +1,367 @@
+use rusqlite::{Connection, Error, OptionalExtension};
+use chrono::Local;
+
+use crate::app_error;
+use crate::operator::{Permission, require_permission};
+use crate::property;
+
+/// Drawing area of the signature pad in logical pixels; strokes are kept in these units
+pub const PAD_WIDTH: u32 = 480;
+pub const PAD_HEIGHT: u32 = 160;
+
+// Half the pen width in the PNG rendering, in pixels
+const PEN_RADIUS: i32 = 1;
+
+/// Points of one pen-down movement
+pub type Stroke = Vec<(i32, i32)>;
+
+/// Whether residents sign for their packages at pickup
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SignaturePolicy {
+    #[default]
+    Off,
+    Optional,
+    /// Packages can't be collected with a card alone
+    Required,
+}
+
+impl SignaturePolicy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "off" => Some(SignaturePolicy::Off),
+            "optional" => Some(SignaturePolicy::Optional),
+            "required" => Some(SignaturePolicy::Required),
+            _ => None,
+        }
+    }
+}
+
+/// Strokes as the pad draws them: SVG path commands with an `M x y` starting each
+/// stroke and `L x y` for every point after it. Points are clamped to the pad.
+pub fn strokes_from_path(commands: &str) -> Vec<Stroke> {
+    let mut strokes: Vec<Stroke> = Vec::new();
+    let mut tokens = commands.split(|c: char| c.is_whitespace() || c == ',').filter(|token| !token.is_empty());
+    while let Some(command) = tokens.next() {
+        let (Some(x), Some(y)) = (tokens.next(), tokens.next()) else { break };
+        let (Ok(x), Ok(y)) = (x.parse::<f32>(), y.parse::<f32>()) else { continue };
+        let point = (
+            (x.round() as i32).clamp(0, PAD_WIDTH as i32 - 1),
+            (y.round() as i32).clamp(0, PAD_HEIGHT as i32 - 1),
+        );
+        match (command, strokes.last_mut()) {
+            ("L", Some(stroke)) if stroke.last() == Some(&point) => {}
+            ("L", Some(stroke)) => stroke.push(point),
+            ("M" | "L", _) => strokes.push(vec![point]),
+            _ => {}
+        }
+    }
+    strokes
+}
+
+/// Path commands for drawing strokes, the inverse of `strokes_from_path`
+pub fn path_commands(strokes: &[Stroke]) -> String {
+    let mut commands = String::new();
+    for stroke in strokes {
+        for (i, (x, y)) in stroke.iter().enumerate() {
+            commands.push_str(&format!("{} {} {} ", if i == 0 { "M" } else { "L" }, x, y));
+        }
+    }
+    commands.trim_end().to_string()
+}
+
+/// Compact text form, e.g. `480x160|12,40 3,-1 4,0;200,52 -2,6`: the pad size, then
+/// each stroke's first point followed by the steps to the next ones
+pub fn encode_strokes(strokes: &[Stroke]) -> String {
+    let encoded: Vec<String> = strokes.iter()
+        .filter(|stroke| !stroke.is_empty())
+        .map(|stroke| {
+            let mut parts = vec![format!("{},{}", stroke[0].0, stroke[0].1)];
+            parts.extend(stroke.windows(2).map(|pair| format!("{},{}", pair[1].0 - pair[0].0, pair[1].1 - pair[0].1)));
+            parts.join(" ")
+        })
+        .collect();
+    format!("{}x{}|{}", PAD_WIDTH, PAD_HEIGHT, encoded.join(";"))
+}
+
+/// Reads strokes written by `encode_strokes`
+pub fn decode_strokes(text: &str) -> Result<Vec<Stroke>, Error> {
+    let invalid = || app_error("Invalid signature data");
+    let (_, body) = text.split_once('|').ok_or_else(invalid)?;
+    let pair = |part: &str| -> Result<(i32, i32), Error> {
+        let (x, y) = part.split_once(',').ok_or_else(invalid)?;
+        Ok((x.parse().map_err(|_| invalid())?, y.parse().map_err(|_| invalid())?))
+    };
+
+    let mut strokes = Vec::new();
+    for encoded in body.split(';').filter(|stroke| !stroke.is_empty()) {
+        let mut parts = encoded.split(' ');
+        let mut point = pair(parts.next().ok_or_else(invalid)?)?;
+        let mut stroke = vec![point];
+        for step in parts {
+            let (dx, dy) = pair(step)?;
+            point = (point.0 + dx, point.1 + dy);
+            stroke.push(point);
+        }
+        strokes.push(stroke);
+    }
+    Ok(strokes)
+}
+
+/// Black strokes on white at the pad's size, as a 1-bit greyscale PNG
+pub fn render_png(strokes: &[Stroke]) -> Vec<u8> {
+    let (width, height) = (PAD_WIDTH as i32, PAD_HEIGHT as i32);
+    let mut ink = vec![false; (width * height) as usize];
+    let mut dot = |x: i32, y: i32| {
+        for py in y - PEN_RADIUS..=y + PEN_RADIUS {
+            for px in x - PEN_RADIUS..=x + PEN_RADIUS {
+                if (0..width).contains(&px) && (0..height).contains(&py) {
+                    ink[(py * width + px) as usize] = true;
+                }
+            }
+        }
+    };
+    for stroke in strokes {
+        if let [(x, y)] = stroke.as_slice() {
+            dot(*x, *y);
+        }
+        for pair in stroke.windows(2) {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            let steps = (x1 - x0).abs().max((y1 - y0).abs()).max(1);
+            for step in 0..=steps {
+                dot(x0 + (x1 - x0) * step / steps, y0 + (y1 - y0) * step / steps);
+            }
+        }
+    }
+
+    // Each row is a filter byte then 8 pixels per byte, set bits being white
+    let row_bytes = (width as usize).div_ceil(8);
+    let mut raw = Vec::with_capacity((row_bytes + 1) * height as usize);
+    for row in ink.chunks(width as usize) {
+        raw.push(0);
+        for pixels in row.chunks(8) {
+            let mut byte = 0u8;
+            for (i, &inked) in pixels.iter().enumerate() {
+                if !inked {
+                    byte |= 0x80 >> i;
+                }
+            }
+            raw.push(byte);
+        }
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&PAD_WIDTH.to_be_bytes());
+    header.extend_from_slice(&PAD_HEIGHT.to_be_bytes());
+    header.extend_from_slice(&[1, 0, 0, 0, 0]);  // bit depth 1, greyscale, no interlacing
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png_chunk(&mut png, b"IHDR", &header);
+    png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// A zlib stream of uncompressed deflate blocks; a 1-bit signature is small enough as it is
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = data.chunks(0xFFFF).collect();
+    for (i, block) in blocks.iter().enumerate() {
+        out.push(u8::from(i + 1 == blocks.len()));
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    if blocks.is_empty() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    out.extend_from_slice(&((b << 16) | a).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Stores a signature drawn at handover and returns its id, to be recorded
+/// with the collection of each package it covers
+pub fn save_signature(db: &Connection, strokes: &[Stroke]) -> Result<u32, Error> {
+    require_permission(db, Permission::EditRecords)?;
+    if strokes.iter().map(Vec::len).sum::<usize>() < 2 {
+        return Err(app_error("The signature is empty"));
+    }
+
+    let signed_at = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    db.execute(
+        "INSERT INTO signature (strokes, png, signed_at, property_id) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![encode_strokes(strokes), render_png(strokes), signed_at, property::current_property_id(db)?],
+    )?;
+    Ok(db.last_insert_rowid() as u32)
+}
+
+/// Strokes of the signature given when a package was collected, if there was one
+pub fn package_signature(db: &Connection, package_id: u32) -> Result<Option<Vec<Stroke>>, Error> {
+    let strokes: Option<String> = db.query_row(
+        "SELECT s.strokes FROM package p JOIN signature s ON s.id = p.signature_id WHERE p.id = ?1",
+        [package_id],
+        |row| row.get(0),
+    ).optional()?;
+    strokes.map(|strokes| decode_strokes(&strokes)).transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Data of the PNG's chunks by kind, checking each chunk's CRC
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let body = &rest[4..8 + len];
+            assert_eq!(u32::from_be_bytes(rest[8 + len..12 + len].try_into().unwrap()), crc32(body));
+            chunks.push((body[..4].try_into().unwrap(), body[4..].to_vec()));
+            rest = &rest[12 + len..];
+        }
+        chunks
+    }
+
+    // Contents of a zlib stream made of stored blocks
+    fn unstore(zlib: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut rest = &zlib[2..];
+        loop {
+            let last = rest[0] == 1;
+            let len = u16::from_le_bytes([rest[1], rest[2]]) as usize;
+            assert_eq!(u16::from_le_bytes([rest[3], rest[4]]), !(len as u16));
+            data.extend_from_slice(&rest[5..5 + len]);
+            rest = &rest[5 + len..];
+            if last {
+                break;
+            }
+        }
+        assert_eq!(rest.len(), 4);
+        data
+    }
+
+    fn inked(raw: &[u8], x: usize, y: usize) -> bool {
+        let row_bytes = (PAD_WIDTH as usize).div_ceil(8);
+        raw[y * (row_bytes + 1) + 1 + x / 8] & (0x80 >> (x % 8)) == 0
+    }
+
+    #[test]
+    fn pad_paths_become_strokes() {
+        let strokes = strokes_from_path("M 10 20 L 11.4 20.6 L 15,25 M 100 50 L 101 51");
+        assert_eq!(strokes, vec![vec![(10, 20), (11, 21), (15, 25)], vec![(100, 50), (101, 51)]]);
+        assert!(strokes_from_path("").is_empty());
+    }
+
+    #[test]
+    fn pad_points_are_clamped_and_repeats_dropped() {
+        let strokes = strokes_from_path("M -5 -5 L 2000 900 L 2001 901 L 479.2 159.4");
+        assert_eq!(strokes, vec![vec![(0, 0), (479, 159)]]);
+    }
+
+    #[test]
+    fn a_line_without_a_move_starts_a_stroke_and_bad_points_are_skipped() {
+        let strokes = strokes_from_path("L 5 5 L x 6 L 7 7 Z 1 1 L 8");
+        assert_eq!(strokes, vec![vec![(5, 5), (7, 7)]]);
+    }
+
+    #[test]
+    fn path_commands_are_the_inverse_of_strokes_from_path() {
+        let strokes = vec![vec![(10, 20), (11, 21), (15, 25)], vec![(100, 50)]];
+        let commands = path_commands(&strokes);
+        assert_eq!(commands, "M 10 20 L 11 21 L 15 25 M 100 50");
+        assert_eq!(strokes_from_path(&commands), strokes);
+    }
+
+    #[test]
+    fn strokes_survive_encoding() {
+        let strokes = vec![vec![(12, 40), (15, 39), (19, 39)], vec![(200, 52), (198, 58)], vec![(3, 3)]];
+        let encoded = encode_strokes(&strokes);
+        assert_eq!(encoded, "480x160|12,40 3,-1 4,0;200,52 -2,6;3,3");
+        assert_eq!(decode_strokes(&encoded).unwrap(), strokes);
+        assert_eq!(decode_strokes(&encode_strokes(&[])).unwrap(), Vec::<Stroke>::new());
+    }
+
+    #[test]
+    fn invalid_stroke_data_is_an_error() {
+        for text in ["", "12,40 3,-1", "480x160|12", "480x160|12,40 3", "480x160|12,a", "480x160|12,40  3,1"] {
+            assert!(decode_strokes(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn crc32_matches_the_standard_check_values() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    }
+
+    #[test]
+    fn zlib_stream_of_nothing_is_one_empty_final_block() {
+        assert_eq!(zlib_stored(&[]), [0x78, 0x01, 1, 0, 0, 0xFF, 0xFF, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn zlib_stream_splits_long_data_into_stored_blocks() {
+        let data: Vec<u8> = (0..70_000u32).map(|i| i as u8).collect();
+        let zlib = zlib_stored(&data);
+        assert_eq!(&zlib[2..5], [0, 0xFF, 0xFF]);
+        assert_eq!(unstore(&zlib), data);
+
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in &data {
+            a = (a + u32::from(byte)) % 65521;
+            b = (b + a) % 65521;
+        }
+        assert_eq!(zlib[zlib.len() - 4..], ((b << 16) | a).to_be_bytes());
+    }
+
+    #[test]
+    fn rendered_png_has_the_pad_size_and_the_ink() {
+        let png = render_png(&[vec![(10, 10)], vec![(100, 20), (110, 20)]]);
+        let chunks = chunks(&png);
+        let kinds: Vec<&[u8; 4]> = chunks.iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 1, 224, 0, 0, 0, 160, 1, 0, 0, 0, 0]);
+
+        let raw = unstore(&chunks[1].1);
+        assert_eq!(raw.len(), (60 + 1) * 160);
+        assert!(inked(&raw, 10, 10) && inked(&raw, 11, 11) && !inked(&raw, 12, 12));
+        assert!((100..=110).all(|x| inked(&raw, x, 20)));
+        assert!(!inked(&raw, 105, 22) && !inked(&raw, 0, 0));
+    }
+
+    #[test]
+    fn ink_at_the_pad_edge_stays_inside_the_image() {
+        let png = render_png(&[vec![(0, 0)], vec![(479, 159)]]);
+        let raw = unstore(&chunks(&png)[1].1);
+        assert!(inked(&raw, 0, 0) && inked(&raw, 479, 159));
+    }
+}
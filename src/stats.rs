@@ -0,0 +1,274 @@
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use rusqlite::{Connection, Error};
+
+use crate::property::CURRENT_PROPERTY;
+
+/// Days of history behind the intake chart and the other windowed figures
+pub const DASHBOARD_DAYS: u32 = 30;
+
+/// Number of apartments listed by volume
+pub const TOP_APARTMENTS: u32 = 5;
+
+// Upper bound in days of each pending-age bucket; the last one is open-ended
+const AGE_BUCKETS: [(&str, Option<f64>); 4] = [
+    ("Under 1 day", Some(1.0)),
+    ("1-3 days", Some(3.0)),
+    ("3-7 days", Some(7.0)),
+    ("Over 7 days", None),
+];
+
+/// A labelled count, one bar on the dashboard
+#[derive(Debug, Clone, PartialEq)]
+pub struct Count {
+    pub label: String,
+    pub value: u32,
+}
+
+/// Figures for the current building's dashboard tab
+#[derive(Debug, Clone)]
+pub struct Dashboard {
+    /// Pending packages by how long they have waited, youngest bucket first; every bucket is present
+    pub pending_by_age: Vec<Count>,
+    /// Packages received per day, oldest day first, `DASHBOARD_DAYS` entries labelled `YYYY-MM-DD`
+    pub intake_per_day: Vec<Count>,
+    /// Median hours from intake to collection over the window, if anything was collected
+    pub median_pickup_hours: Option<f64>,
+    pub top_apartments: Vec<Count>,
+    /// Packages received over the window by carrier, largest first
+    pub carriers: Vec<Count>,
+    pub unknown_card_taps: u32,
+    /// Taps by cards from another building that this reader doesn't accept
+    pub refused_card_taps: u32,
+}
+
+impl Dashboard {
+    pub fn pending_total(&self) -> u32 {
+        self.pending_by_age.iter().map(|bucket| bucket.value).sum()
+    }
+}
+
+/// All dashboard figures as of `now`, over the last `DASHBOARD_DAYS` days including today
+pub fn dashboard(db: &Connection, now: NaiveDateTime) -> Result<Dashboard, Error> {
+    let today = now.date();
+    let since = window_start(today, DASHBOARD_DAYS);
+    Ok(Dashboard {
+        pending_by_age: pending_by_age(db, now)?,
+        intake_per_day: intake_per_day(db, today, DASHBOARD_DAYS)?,
+        median_pickup_hours: median_pickup_hours(db, since)?,
+        top_apartments: top_apartments(db, since, TOP_APARTMENTS)?,
+        carriers: carrier_breakdown(db, since)?,
+        unknown_card_taps: log_count(db, "unknown_card", since)?,
+        refused_card_taps: log_count(db, "card_refused", since)?,
+    })
+}
+
+// First day of a window of `days` days ending today
+fn window_start(today: NaiveDate, days: u32) -> NaiveDate {
+    today - Duration::days(i64::from(days.max(1)) - 1)
+}
+
+/// Pending packages in the current building by age at `now`
+pub fn pending_by_age(db: &Connection, now: NaiveDateTime) -> Result<Vec<Count>, Error> {
+    let mut stmt = db.prepare(&format!(
+        "SELECT julianday(?1) - julianday(date_time) FROM package
+         WHERE status = 'pending' AND deleted_at IS NULL AND property_id = {}",
+        CURRENT_PROPERTY
+    ))?;
+    let ages = stmt.query_map([now.format("%Y-%m-%d %H:%M:%S").to_string()], |row| row.get::<_, Option<f64>>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut buckets: Vec<Count> = AGE_BUCKETS.iter()
+        .map(|(label, _)| Count { label: label.to_string(), value: 0 })
+        .collect();
+    for age in ages {
+        let age = age.unwrap_or(0.0);
+        let index = AGE_BUCKETS.iter()
+            .position(|(_, limit)| limit.is_none_or(|limit| age < limit))
+            .unwrap_or(AGE_BUCKETS.len() - 1);
+        buckets[index].value += 1;
+    }
+    Ok(buckets)
+}
+
+/// Packages received in the current building on each of the `days` days up to `today`,
+/// including days with none
+pub fn intake_per_day(db: &Connection, today: NaiveDate, days: u32) -> Result<Vec<Count>, Error> {
+    let since = window_start(today, days);
+    let mut stmt = db.prepare(&format!(
+        "SELECT date(date_time) AS day, COUNT(*) FROM package
+         WHERE deleted_at IS NULL AND property_id = {} AND date(date_time) BETWEEN ?1 AND ?2
+         GROUP BY day",
+        CURRENT_PROPERTY
+    ))?;
+    let counts = stmt.query_map([since.to_string(), today.to_string()], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?))
+    })?.collect::<Result<std::collections::HashMap<_, _>, _>>()?;
+
+    Ok(since.iter_days()
+        .take_while(|day| *day <= today)
+        .map(|day| {
+            let label = day.to_string();
+            let value = counts.get(&label).copied().unwrap_or(0);
+            Count { label, value }
+        })
+        .collect())
+}
+
+/// Median hours between intake and collection for packages collected since `since`
+pub fn median_pickup_hours(db: &Connection, since: NaiveDate) -> Result<Option<f64>, Error> {
+    let mut stmt = db.prepare(&format!(
+        "SELECT (julianday(collection_time) - julianday(date_time)) * 24 AS hours FROM package
+         WHERE status = 'collected' AND deleted_at IS NULL AND property_id = {}
+           AND date(collection_time) >= ?1 AND hours IS NOT NULL
+         ORDER BY hours",
+        CURRENT_PROPERTY
+    ))?;
+    let hours = stmt.query_map([since.to_string()], |row| row.get::<_, f64>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let middle = hours.len() / 2;
+    Ok(match hours.len() {
+        0 => None,
+        len if len % 2 == 1 => Some(hours[middle]),
+        _ => Some((hours[middle - 1] + hours[middle]) / 2.0),
+    })
+}
+
+/// Apartments with the most packages received since `since`, busiest first
+pub fn top_apartments(db: &Connection, since: NaiveDate, limit: u32) -> Result<Vec<Count>, Error> {
+    grouped_counts(db, &format!(
+        "SELECT apt, COUNT(*) AS packages FROM package
+         WHERE deleted_at IS NULL AND property_id = {} AND date(date_time) >= ?1
+         GROUP BY apt ORDER BY packages DESC, apt LIMIT {}",
+        CURRENT_PROPERTY, limit
+    ), since)
+}
+
+/// Packages received since `since` by carrier; packages without one count as "Unknown"
+pub fn carrier_breakdown(db: &Connection, since: NaiveDate) -> Result<Vec<Count>, Error> {
+    grouped_counts(db, &format!(
+        "SELECT COALESCE(NULLIF(TRIM(carrier), ''), 'Unknown') AS name, COUNT(*) AS packages FROM package
+         WHERE deleted_at IS NULL AND property_id = {} AND date(date_time) >= ?1
+         GROUP BY name ORDER BY packages DESC, name",
+        CURRENT_PROPERTY
+    ), since)
+}
+
+/// Log entries of one type in the current building since `since`
+pub fn log_count(db: &Connection, action_type: &str, since: NaiveDate) -> Result<u32, Error> {
+    db.query_row(
+        &format!(
+            "SELECT COUNT(*) FROM log WHERE action_type = ?1 AND property_id = {} AND date(date_time) >= ?2",
+            CURRENT_PROPERTY
+        ),
+        [action_type, &since.to_string()],
+        |row| row.get(0),
+    )
+}
+
+// Label and count pairs from a query taking the window start as ?1
+fn grouped_counts(db: &Connection, sql: &str, since: NaiveDate) -> Result<Vec<Count>, Error> {
+    let mut stmt = db.prepare(sql)?;
+    let counts = stmt.query_map([since.to_string()], |row| {
+        Ok(Count { label: row.get(0)?, value: row.get(1)? })
+    })?.collect::<Result<Vec<_>, _>>()?;
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::property;
+
+    fn test_db() -> Connection {
+        crate::open_db(":memory:").unwrap()
+    }
+
+    fn at(date_time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(date_time, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn day(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    fn add_package(db: &Connection, received: &str, status: &str, collected: Option<&str>) {
+        db.execute(
+            "INSERT INTO package (apt, package_number, barcode, date_time, status, collection_time, property_id)
+             VALUES ('101', '1', '', ?1, ?2, ?3, ?4)",
+            rusqlite::params![received, status, collected, property::current_property_id(db).unwrap()],
+        ).unwrap();
+    }
+
+    fn values(counts: &[Count]) -> Vec<u32> {
+        counts.iter().map(|count| count.value).collect()
+    }
+
+    #[test]
+    fn pending_age_at_a_bucket_limit_goes_to_the_older_bucket() {
+        let db = test_db();
+        add_package(&db, "2025-03-10 11:59:59", "pending", None);
+        add_package(&db, "2025-03-09 12:00:00", "pending", None);
+        add_package(&db, "2025-03-07 12:00:00", "pending", None);
+        add_package(&db, "2025-03-03 12:00:00", "pending", None);
+        add_package(&db, "2025-03-03 12:00:01", "pending", None);
+        add_package(&db, "2025-01-01 12:00:00", "collected", Some("2025-01-02 12:00:00"));
+
+        let buckets = pending_by_age(&db, at("2025-03-10 12:00:00")).unwrap();
+        assert_eq!(buckets.len(), AGE_BUCKETS.len());
+        assert_eq!(values(&buckets), vec![1, 1, 2, 1]);
+    }
+
+    #[test]
+    fn pending_buckets_are_listed_when_nothing_is_waiting() {
+        let db = test_db();
+        let buckets = pending_by_age(&db, at("2025-03-10 12:00:00")).unwrap();
+        assert_eq!(values(&buckets), vec![0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn median_pickup_of_an_odd_count_is_the_middle_value() {
+        let db = test_db();
+        add_package(&db, "2025-03-01 08:00:00", "collected", Some("2025-03-01 09:00:00"));
+        add_package(&db, "2025-03-01 08:00:00", "collected", Some("2025-03-01 18:00:00"));
+        add_package(&db, "2025-03-01 08:00:00", "collected", Some("2025-03-01 11:00:00"));
+
+        let median = median_pickup_hours(&db, day("2025-03-01")).unwrap().unwrap();
+        assert!((median - 3.0).abs() < 1e-6, "{}", median);
+    }
+
+    #[test]
+    fn median_pickup_of_an_even_count_averages_the_middle_values() {
+        let db = test_db();
+        add_package(&db, "2025-03-01 08:00:00", "collected", Some("2025-03-01 09:00:00"));
+        add_package(&db, "2025-03-01 08:00:00", "collected", Some("2025-03-01 10:00:00"));
+        add_package(&db, "2025-03-01 08:00:00", "collected", Some("2025-03-01 14:00:00"));
+        add_package(&db, "2025-03-01 08:00:00", "collected", Some("2025-03-02 08:00:00"));
+
+        let median = median_pickup_hours(&db, day("2025-03-01")).unwrap().unwrap();
+        assert!((median - 4.0).abs() < 1e-6, "{}", median);
+    }
+
+    #[test]
+    fn median_pickup_is_none_without_collections_in_the_window() {
+        let db = test_db();
+        add_package(&db, "2025-03-01 08:00:00", "pending", None);
+        add_package(&db, "2025-02-01 08:00:00", "collected", Some("2025-02-01 09:00:00"));
+
+        assert_eq!(median_pickup_hours(&db, day("2025-03-01")).unwrap(), None);
+    }
+
+    #[test]
+    fn intake_lists_every_day_of_the_window() {
+        let db = test_db();
+        add_package(&db, "2025-03-05 23:00:00", "pending", None);
+        add_package(&db, "2025-03-06 09:00:00", "pending", None);
+        add_package(&db, "2025-03-08 09:00:00", "pending", None);
+        add_package(&db, "2025-03-08 17:00:00", "collected", Some("2025-03-08 18:00:00"));
+
+        let intake = intake_per_day(&db, day("2025-03-08"), 3).unwrap();
+        let labels: Vec<&str> = intake.iter().map(|count| count.label.as_str()).collect();
+        assert_eq!(labels, vec!["2025-03-06", "2025-03-07", "2025-03-08"]);
+        assert_eq!(values(&intake), vec![1, 0, 2]);
+    }
+}
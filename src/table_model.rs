@@ -0,0 +1,287 @@
+use rusqlite::{Connection, Error, types::Value};
+use slint::{Model, ModelNotify, ModelRc, ModelTracker, SharedString, StandardListViewItem, VecModel};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use crate::filter::{self, FilterQuery, RecordFilter};
+
+/// Rows fetched per query
+pub const PAGE_SIZE: usize = 100;
+
+/// Pages kept in memory; the ones furthest from the latest request are dropped first
+const MAX_CACHED_PAGES: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TableKind {
+    Residents,
+    Cards,
+    Packages,
+    Logs,
+}
+
+impl TableKind {
+    // Displayed columns, in the order of the table headers in the UI
+    fn columns(&self) -> &'static [&'static str] {
+        match self {
+            TableKind::Residents => &[
+                "r.id", "r.apt", "r.first_name", "r.last_name",
+                "CASE WHEN r.linked THEN 'true' ELSE 'false' END", "r.status",
+            ],
+            TableKind::Cards => &[
+                "c.id", "c.apt", "r.first_name || ' ' || r.last_name", "c.added_date", "c.status",
+            ],
+            TableKind::Packages => &[
+                "p.id", "p.apt", "p.package_number", "p.barcode", "p.comment", "p.carrier", "p.status",
+            ],
+            TableKind::Logs => &[
+                "l.id", "l.action_type", "l.action", "l.date_time", "o.display_name",
+            ],
+        }
+    }
+
+    // What clicking a column header sorts by, where it differs from the displayed value
+    fn sort_key(&self, column: usize) -> Option<&'static str> {
+        match (self, column) {
+            (TableKind::Residents, 4) => Some("r.linked"),
+            (TableKind::Cards, 2) => Some("r.last_name || ' ' || r.first_name"),
+            _ => self.columns().get(column).copied(),
+        }
+    }
+
+    fn query(&self, filter: &RecordFilter) -> FilterQuery {
+        match self {
+            TableKind::Residents => filter::resident_query(filter),
+            TableKind::Cards => filter::card_query(filter),
+            TableKind::Packages => filter::package_query(filter),
+            TableKind::Logs => filter::log_query(filter),
+        }
+    }
+}
+
+/// Column chosen by clicking a table header. `None` keeps the query's own order
+/// (newest first, or best match when searching).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SortOrder {
+    pub column: Option<usize>,
+    pub descending: bool,
+}
+
+struct Row {
+    id: u32,
+    cells: Vec<SharedString>,
+}
+
+// Sort key and id of a row; the next page starts strictly after it
+type PageKey = (Value, u32);
+
+/// A `slint::Model` for the `StandardTableView` that loads rows a page at a time,
+/// only when the view asks for them.
+///
+/// Pages are fetched with keyset pagination: a page starts right after the
+/// sort key of the last row of the page before it, so scrolling never asks the
+/// database to skip rows. Jumping far ahead seeks from the nearest known
+/// page start and skips only the rows in between.
+pub struct LazyTableModel {
+    db: Arc<Mutex<Connection>>,
+    kind: TableKind,
+    filter: RefCell<RecordFilter>,
+    sort: Cell<SortOrder>,
+    count: Cell<Option<usize>>,
+    pages: RefCell<HashMap<usize, Rc<Vec<Row>>>>,
+    // Page index -> key of the last row on the page before it
+    page_starts: RefCell<BTreeMap<usize, PageKey>>,
+    notify: ModelNotify,
+}
+
+impl LazyTableModel {
+    pub fn new(db: Arc<Mutex<Connection>>, kind: TableKind, filter: RecordFilter) -> Rc<Self> {
+        Rc::new(LazyTableModel {
+            db,
+            kind,
+            filter: RefCell::new(filter),
+            sort: Cell::new(SortOrder::default()),
+            count: Cell::new(None),
+            pages: RefCell::new(HashMap::new()),
+            page_starts: RefCell::new(BTreeMap::new()),
+            notify: ModelNotify::default(),
+        })
+    }
+
+    /// Drops cached rows so the view reloads them, e.g. after a change to the table.
+    /// Nothing is queried until the view asks for rows, so this can be called
+    /// while the database lock is held.
+    pub fn refresh(&self) {
+        self.count.set(None);
+        self.pages.borrow_mut().clear();
+        self.page_starts.borrow_mut().clear();
+        self.notify.reset();
+    }
+
+    pub fn filter(&self) -> RecordFilter {
+        self.filter.borrow().clone()
+    }
+
+    pub fn set_filter(&self, filter: RecordFilter) {
+        *self.filter.borrow_mut() = filter;
+        self.refresh();
+    }
+
+    pub fn set_sort(&self, sort: SortOrder) {
+        self.sort.set(sort);
+        self.refresh();
+    }
+
+    /// Database id of the row at `row`, for opening its details
+    pub fn row_id(&self, row: usize) -> Option<u32> {
+        let page = self.page(row / PAGE_SIZE)?;
+        page.get(row % PAGE_SIZE).map(|r| r.id)
+    }
+
+    /// Number of rows matching the current filter
+    pub fn total(&self) -> usize {
+        if let Some(count) = self.count.get() {
+            return count;
+        }
+
+        let query = self.kind.query(&self.filter.borrow());
+        let sql = format!("SELECT COUNT(*) FROM {}{}", query.from, query.builder.sql());
+        let db = self.db.lock().unwrap();
+        let count = db
+            .query_row(&sql, rusqlite::params_from_iter(query.builder.params.iter()), |row| row.get::<_, i64>(0))
+            .unwrap_or_else(|e| {
+                println!("⚠️  Could not count {:?} rows: {}", self.kind, e);
+                0
+            }) as usize;
+
+        self.count.set(Some(count));
+        count
+    }
+
+    fn page(&self, index: usize) -> Option<Rc<Vec<Row>>> {
+        if let Some(page) = self.pages.borrow().get(&index) {
+            return Some(Rc::clone(page));
+        }
+
+        let rows = match self.fetch_page(index) {
+            Ok(rows) => Rc::new(rows),
+            Err(e) => {
+                println!("⚠️  Could not load {:?} page {}: {}", self.kind, index, e);
+                return None;
+            }
+        };
+
+        let mut pages = self.pages.borrow_mut();
+        if pages.len() >= MAX_CACHED_PAGES {
+            if let Some(&furthest) = pages.keys().max_by_key(|&&p| p.abs_diff(index)) {
+                pages.remove(&furthest);
+            }
+        }
+        pages.insert(index, Rc::clone(&rows));
+        Some(rows)
+    }
+
+    fn fetch_page(&self, index: usize) -> Result<Vec<Row>, Error> {
+        let mut query = self.kind.query(&self.filter.borrow());
+        let sort = self.sort.get();
+        let (key, descending) = match sort.column.and_then(|c| self.kind.sort_key(c)) {
+            Some(key) => (format!("COALESCE({}, '')", key), sort.descending),
+            None => (query.default_key.clone(), query.default_descending),
+        };
+
+        // Start from the closest page whose first row is known
+        let start = self.page_starts.borrow().range(..=index).next_back().map(|(&i, k)| (i, k.clone()));
+        let (skip_pages, after) = match start {
+            Some((start_index, key)) => (index - start_index, Some(key)),
+            None => (index, None),
+        };
+
+        let mut params: Vec<Value> = query.builder.params.drain(..).map(Value::Text).collect();
+        let mut where_sql = query.builder.sql();
+        if let Some((after_key, after_id)) = after {
+            let comparison = if descending { "<" } else { ">" };
+            let clause = format!("({}, {}) {} (?{}, ?{})", key, query.id, comparison, params.len() + 1, params.len() + 2);
+            where_sql = if where_sql.is_empty() {
+                format!(" WHERE {}", clause)
+            } else {
+                format!("{} AND {}", where_sql, clause)
+            };
+            params.push(after_key);
+            params.push(Value::Integer(after_id as i64));
+        }
+
+        let direction = if descending { "DESC" } else { "ASC" };
+        let sql = format!(
+            "SELECT {id}, {key}, {columns} FROM {from}{where_sql}
+             ORDER BY {key} {dir}, {id} {dir} LIMIT {limit} OFFSET {offset}",
+            id = query.id,
+            key = key,
+            columns = self.kind.columns().join(", "),
+            from = query.from,
+            where_sql = where_sql,
+            dir = direction,
+            limit = PAGE_SIZE,
+            offset = skip_pages * PAGE_SIZE,
+        );
+
+        let db = self.db.lock().unwrap();
+        let mut stmt = db.prepare(&sql)?;
+        let column_count = self.kind.columns().len();
+        let mut last_key = None;
+        let rows = stmt.query_map(rusqlite::params_from_iter(params.iter()), |row| {
+            let id: u32 = row.get(0)?;
+            let sort_value: Value = row.get(1)?;
+            let cells = (0..column_count)
+                .map(|i| row.get::<_, Value>(i + 2).map(|v| display_value(&v)))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok((id, sort_value, cells))
+        })?;
+
+        let mut page = Vec::with_capacity(PAGE_SIZE);
+        for row in rows {
+            let (id, sort_value, cells) = row?;
+            last_key = Some((sort_value, id));
+            page.push(Row { id, cells });
+        }
+
+        if page.len() == PAGE_SIZE {
+            if let Some(key) = last_key {
+                self.page_starts.borrow_mut().insert(index + 1, key);
+            }
+        }
+        Ok(page)
+    }
+}
+
+fn display_value(value: &Value) -> SharedString {
+    match value {
+        Value::Null => SharedString::new(),
+        Value::Integer(i) => i.to_string().into(),
+        Value::Real(f) => f.to_string().into(),
+        Value::Text(s) => s.as_str().into(),
+        Value::Blob(_) => "<binary>".into(),
+    }
+}
+
+impl Model for LazyTableModel {
+    type Data = ModelRc<StandardListViewItem>;
+
+    fn row_count(&self) -> usize {
+        self.total()
+    }
+
+    fn row_data(&self, row: usize) -> Option<Self::Data> {
+        let page = self.page(row / PAGE_SIZE)?;
+        let row = page.get(row % PAGE_SIZE)?;
+
+        let items: Vec<StandardListViewItem> = row.cells.iter()
+            .map(|cell| StandardListViewItem::from(cell.clone()))
+            .collect();
+        Some(ModelRc::new(VecModel::from(items)))
+    }
+
+    fn model_tracker(&self) -> &dyn ModelTracker {
+        &self.notify
+    }
+}
@@ -0,0 +1,180 @@
+use chrono::{Local, NaiveDate};
+use rusqlite::{Connection, Error};
+
+use crate::{
+    add_log_with_payload, add_resident, app_error, get_package_info, get_packages_for_resident,
+    get_resident_info, revoke_card, PackageData, ResidentData,
+};
+use crate::operator::{Permission, require_permission};
+use crate::property;
+use crate::unit;
+
+/// What is left to sort out after a resident moved out
+pub struct MoveOut {
+    pub resident: ResidentData,
+    pub revoked_cards: usize,
+    /// Packages still waiting for the apartment, to be returned or reassigned
+    pub pending_packages: Vec<PackageData>,
+    /// Residents still living in the apartment, who may own those packages
+    pub remaining_residents: u32,
+}
+
+/// Marks a resident as moved out and revokes their cards. The resident row,
+/// their collections and log entries stay for the history.
+pub fn move_out(db: &Connection, resident_id: u32) -> Result<MoveOut, Error> {
+    require_permission(db, Permission::MoveOut)?;
+
+    let resident = get_resident_info(db, resident_id)?;
+    if resident.status != "active" {
+        return Err(app_error(format!("{} {} has already moved out", resident.first_name, resident.last_name)));
+    }
+    let resident_property: u32 = db.query_row(
+        "SELECT property_id FROM resident WHERE id = ?1", [resident_id], |row| row.get(0)
+    )?;
+    if resident_property != property::current_property_id(db)? {
+        return Err(app_error("Resident belongs to another building"));
+    }
+
+    let tx = db.unchecked_transaction()?;
+    let card_ids: Vec<u32> = tx.prepare("SELECT id FROM card WHERE resident_id = ?1 AND status = 'active'")?
+        .query_map([resident_id], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    for &card_id in &card_ids {
+        revoke_card(&tx, card_id)?;
+    }
+
+    let moved_out = Local::now().format("%Y-%m-%d").to_string();
+    tx.execute(
+        "UPDATE resident SET status = 'moved_out', moved_out = ?1, linked = 0 WHERE id = ?2",
+        rusqlite::params![moved_out, resident_id],
+    )?;
+
+    let remaining_residents: u32 = tx.query_row(
+        "SELECT COUNT(*) FROM resident
+         WHERE apt = ?1 AND property_id = ?2 AND status = 'active' AND deleted_at IS NULL",
+        rusqlite::params![resident.apt, resident_property],
+        |row| row.get(0),
+    )?;
+    let pending_packages = get_packages_for_resident(&tx, &resident.apt)?;
+
+    let log_action = format!(
+        "Resident {} {} (ID: {}, Apt: {}) moved out, {} card(s) revoked, {} package(s) pending",
+        resident.first_name, resident.last_name, resident_id, resident.apt, card_ids.len(), pending_packages.len()
+    );
+    add_log_with_payload(&tx, "move_out", &log_action, serde_json::json!({
+        "resident_id": resident_id, "apt": resident.apt, "revoked_card_ids": card_ids,
+        "pending_package_ids": pending_packages.iter().map(|p| p.id).collect::<Vec<_>>(),
+    }))?;
+    tx.commit()?;
+
+    Ok(MoveOut {
+        resident: get_resident_info(db, resident_id)?,
+        revoked_cards: card_ids.len(),
+        pending_packages,
+        remaining_residents,
+    })
+}
+
+/// Registers the tenants moving into an apartment, as (first name, last name).
+/// `moved_in` is a `YYYY-MM-DD` date; empty means today.
+pub fn move_in(db: &Connection, apt: &str, tenants: &[(&str, &str)], moved_in: &str) -> Result<Vec<u32>, Error> {
+    require_permission(db, Permission::EditRecords)?;
+
+    let tenants: Vec<(&str, &str)> = tenants.iter()
+        .map(|(first, last)| (first.trim(), last.trim()))
+        .filter(|(first, last)| !first.is_empty() || !last.is_empty())
+        .collect();
+    if tenants.is_empty() {
+        return Err(app_error("At least one tenant is required"));
+    }
+    if let Some((first, last)) = tenants.iter().find(|(first, last)| first.is_empty() || last.is_empty()) {
+        return Err(app_error(format!("'{}' needs a first and a last name", format!("{} {}", first, last).trim())));
+    }
+    let moved_in = match moved_in.trim() {
+        "" => Local::now().format("%Y-%m-%d").to_string(),
+        date => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|_| app_error(format!("Invalid move-in date '{}', expected YYYY-MM-DD", date)))?
+            .to_string(),
+    };
+    let unit = unit::resolve_unit(db, apt)?;
+
+    let tx = db.unchecked_transaction()?;
+    let mut ids = Vec::new();
+    for (first_name, last_name) in &tenants {
+        let id = add_resident(&tx, &unit.number, first_name, last_name)?;
+        tx.execute("UPDATE resident SET moved_in = ?1 WHERE id = ?2", rusqlite::params![moved_in, id])?;
+        ids.push(id);
+    }
+
+    let names: Vec<String> = tenants.iter().map(|(first, last)| format!("{} {}", first, last)).collect();
+    add_log_with_payload(
+        &tx, "move_in", &format!("Moved into Apt {} on {}: {}", unit.number, moved_in, names.join(", ")),
+        serde_json::json!({ "apt": unit.number, "resident_ids": ids, "moved_in": moved_in }),
+    )?;
+    tx.commit()?;
+    Ok(ids)
+}
+
+// Pending packages of the current building only; anything else can't be handed back here
+fn pending_package(db: &Connection, package_id: u32) -> Result<PackageData, Error> {
+    let package = get_package_info(db, package_id)?;
+    if package.status != "pending" {
+        return Err(app_error(format!("Package #{} is no longer pending", package.package_number)));
+    }
+    let (package_property, deleted): (u32, bool) = db.query_row(
+        "SELECT property_id, deleted_at IS NOT NULL FROM package WHERE id = ?1",
+        [package_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    if deleted {
+        return Err(app_error(format!("Package #{} was removed", package.package_number)));
+    }
+    if package_property != property::current_property_id(db)? {
+        return Err(app_error("Package belongs to another building"));
+    }
+    Ok(package)
+}
+
+/// Sends a pending package back to the carrier, e.g. after the addressee moved out
+pub fn return_package(db: &Connection, package_id: u32, reason: &str) -> Result<(), Error> {
+    require_permission(db, Permission::MoveOut)?;
+    let package = pending_package(db, package_id)?;
+
+    let returned = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    db.execute(
+        "UPDATE package SET status = 'returned', collection_time = ?1 WHERE id = ?2",
+        rusqlite::params![returned, package_id],
+    )?;
+
+    let reason = reason.trim();
+    let log_action = format!(
+        "Package #{} (Apt {}) returned to sender{}",
+        package.package_number, package.apt,
+        if reason.is_empty() { String::new() } else { format!(". Reason: {}", reason) }
+    );
+    add_log_with_payload(db, "returned", &log_action, serde_json::json!({
+        "package_id": package_id, "apt": package.apt, "reason": reason,
+    }))
+}
+
+/// Moves a pending package to another apartment of the current building
+pub fn reassign_package(db: &Connection, package_id: u32, apt: &str) -> Result<(), Error> {
+    require_permission(db, Permission::MoveOut)?;
+    let package = pending_package(db, package_id)?;
+    let unit = unit::resolve_unit(db, apt)?;
+    if unit.number == package.apt {
+        return Err(app_error(format!("Package #{} is already for Apt {}", package.package_number, unit.number)));
+    }
+
+    db.execute(
+        "UPDATE package SET apt = ?1, unit_id = ?2 WHERE id = ?3",
+        rusqlite::params![unit.number, unit.id, package_id],
+    )?;
+
+    let log_action = format!(
+        "Package #{} reassigned from Apt {} to Apt {}", package.package_number, package.apt, unit.number
+    );
+    add_log_with_payload(db, "reassigned", &log_action, serde_json::json!({
+        "package_id": package_id, "apt": unit.number, "from_apt": package.apt,
+    }))
+}
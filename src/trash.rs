@@ -0,0 +1,125 @@
+use chrono::{Duration, Local};
+use rusqlite::{Connection, Error, OptionalExtension};
+
+use crate::{add_log_with_payload, app_error};
+use crate::operator::{Permission, require_permission};
+use crate::photo::{self, PhotoSettings};
+
+/// How long after a removal the Undo action still restores it
+pub const UNDO_WINDOW_SECS: i64 = 30;
+
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// A record removed with `delete_resident` or `delete_package`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Removed {
+    Resident(u32),
+    Package(u32),
+}
+
+/// Rows deleted for good by `purge_deleted`
+#[derive(Debug, Default)]
+pub struct PurgeReport {
+    pub residents: usize,
+    pub cards: usize,
+    pub packages: usize,
+    pub photos: usize,
+}
+
+impl PurgeReport {
+    pub fn summary(&self) -> String {
+        format!(
+            "Purged {} resident(s), {} card(s), {} package(s) and {} photo(s)",
+            self.residents, self.cards, self.packages, self.photos
+        )
+    }
+}
+
+fn timestamp_before(duration: Duration) -> String {
+    (Local::now() - duration).format(TIMESTAMP_FORMAT).to_string()
+}
+
+// When `table` row `id` was removed, if it is
+fn deleted_at(db: &Connection, table: &str, id: u32) -> Result<Option<String>, Error> {
+    db.query_row(&format!("SELECT deleted_at FROM {} WHERE id = ?1", table), [id], |row| row.get(0))
+        .optional()?
+        .ok_or_else(|| app_error(format!("No {} with ID {}", table, id)))
+}
+
+/// Undoes a removal made within the last `UNDO_WINDOW_SECS` seconds.
+/// A resident comes back with the cards that were removed with them.
+pub fn restore(db: &Connection, removed: Removed) -> Result<(), Error> {
+    let (table, id, permission) = match removed {
+        Removed::Resident(id) => ("resident", id, Permission::DeleteResident),
+        Removed::Package(id) => ("package", id, Permission::DeletePackage),
+    };
+    require_permission(db, permission)?;
+
+    let Some(deleted_at) = deleted_at(db, table, id)? else {
+        return Err(app_error(format!("The {} was not removed", table)));
+    };
+    if deleted_at < timestamp_before(Duration::seconds(UNDO_WINDOW_SECS)) {
+        return Err(app_error(format!(
+            "Too late to undo: the {} was removed more than {} seconds ago", table, UNDO_WINDOW_SECS
+        )));
+    }
+
+    let tx = db.unchecked_transaction()?;
+    tx.execute(
+        &format!("UPDATE {} SET deleted_at = NULL, deleted_by = NULL WHERE id = ?1", table),
+        [id],
+    )?;
+    let payload = match removed {
+        Removed::Resident(id) => {
+            tx.execute(
+                "UPDATE card SET deleted_at = NULL, deleted_by = NULL WHERE resident_id = ?1 AND deleted_at = ?2",
+                rusqlite::params![id, deleted_at],
+            )?;
+            serde_json::json!({ "resident_id": id })
+        }
+        Removed::Package(id) => serde_json::json!({ "package_id": id }),
+    };
+
+    add_log_with_payload(&tx, "restore", &format!("Removed {} (ID: {}) was restored", table, id), payload)?;
+    tx.commit()
+}
+
+/// Permanently deletes residents, cards and packages removed more than `older_than_days`
+/// days ago, in every building, along with the packages' photos. Their log entries stay.
+pub fn purge_deleted(db: &Connection, older_than_days: u32, photos: &PhotoSettings) -> Result<PurgeReport, Error> {
+    require_permission(db, Permission::PurgeDeleted)?;
+    let cutoff = timestamp_before(Duration::days(older_than_days as i64));
+
+    const PURGED_PACKAGES: &str = "SELECT id FROM package WHERE deleted_at < ?1";
+    let tx = db.unchecked_transaction()?;
+    let photo_files = photo::photo_files(&tx, PURGED_PACKAGES, [&cutoff])?;
+    let mut report = PurgeReport {
+        photos: tx.execute(&format!("DELETE FROM package_photo WHERE package_id IN ({})", PURGED_PACKAGES), [&cutoff])?,
+        ..Default::default()
+    };
+    report.packages = tx.execute("DELETE FROM package WHERE deleted_at < ?1", [&cutoff])?;
+
+    // Collected packages keep their row but lose the link to a purged resident
+    tx.execute(
+        "UPDATE package SET collected_by = NULL
+         WHERE collected_by IN (SELECT id FROM resident WHERE deleted_at < ?1)",
+        [&cutoff],
+    )?;
+    report.cards = tx.execute(
+        "DELETE FROM card
+         WHERE deleted_at < ?1 OR resident_id IN (SELECT id FROM resident WHERE deleted_at < ?1)",
+        [&cutoff],
+    )?;
+    report.residents = tx.execute("DELETE FROM resident WHERE deleted_at < ?1", [&cutoff])?;
+
+    add_log_with_payload(
+        &tx, "purge", &format!("{} removed before {}", report.summary(), cutoff),
+        serde_json::json!({
+            "before": cutoff, "residents": report.residents, "cards": report.cards, "packages": report.packages,
+            "photos": report.photos,
+        }),
+    )?;
+    tx.commit()?;
+    photo::delete_unreferenced(db, photos, &photo_files)?;
+    Ok(report)
+}
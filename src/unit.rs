@@ -0,0 +1,221 @@
+use rusqlite::{Connection, Error, OptionalExtension};
+
+use crate::{add_log_with_payload, app_error};
+use crate::operator::{Permission, require_permission};
+use crate::property::CURRENT_PROPERTY;
+
+/// Most apartments a single range may register, to catch typos like `101-10100`
+const MAX_RANGE: u32 = 1000;
+
+/// An apartment in the registry. `number` is the normalised apartment used
+/// everywhere else (`resident.apt`, `package.apt`, ...) and is unique within
+/// its property. `building` is an optional wing or entrance label.
+#[derive(Debug, Clone)]
+pub struct Unit {
+    pub id: u32,
+    pub property_id: u32,
+    pub building: String,
+    pub number: String,
+    pub floor: Option<i32>,
+    pub status: String,
+}
+
+impl Unit {
+    pub fn is_active(&self) -> bool {
+        self.status == "active"
+    }
+}
+
+/// Canonical form of a typed apartment: no spaces, upper case, no `#` prefix
+/// and no leading zeros, so "0101", "101 " and "#101" are all "101".
+pub fn normalize_apt(apt: &str) -> String {
+    let apt: String = apt.trim().trim_start_matches('#').chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_uppercase)
+        .collect();
+
+    let digits = apt.chars().take_while(char::is_ascii_digit).count();
+    let zeros = apt.chars().take_while(|&c| c == '0').count().min(digits.saturating_sub(1));
+    apt[zeros..].to_string()
+}
+
+/// Floor implied by a numeric apartment like 1204 (floor 12), if any
+pub fn floor_from_number(number: &str) -> Option<i32> {
+    if number.len() >= 3 && number.chars().all(|c| c.is_ascii_digit()) {
+        number[..number.len() - 2].parse().ok()
+    } else {
+        None
+    }
+}
+
+const UNIT_COLUMNS: &str = "id, property_id, building, number, floor, status";
+
+fn row_to_unit(row: &rusqlite::Row) -> Result<Unit, Error> {
+    Ok(Unit {
+        id: row.get(0)?,
+        property_id: row.get(1)?,
+        building: row.get(2)?,
+        number: row.get(3)?,
+        floor: row.get(4)?,
+        status: row.get(5)?,
+    })
+}
+
+/// Apartments of the current building
+pub fn get_units(db: &Connection) -> Result<Vec<Unit>, Error> {
+    let mut stmt = db.prepare(&format!(
+        "SELECT {} FROM unit WHERE property_id = {} ORDER BY building, floor, LENGTH(number), number",
+        UNIT_COLUMNS, CURRENT_PROPERTY
+    ))?;
+    let units = stmt.query_map([], row_to_unit)?;
+    units.collect()
+}
+
+pub fn find_unit(db: &Connection, apt: &str) -> Result<Option<Unit>, Error> {
+    db.query_row(
+        &format!("SELECT {} FROM unit WHERE property_id = {} AND number = ?1", UNIT_COLUMNS, CURRENT_PROPERTY),
+        [normalize_apt(apt)],
+        row_to_unit,
+    ).optional()
+}
+
+/// Looks up the active unit for a typed apartment in the current building.
+/// Residents and packages can only be assigned to apartments that pass this check.
+pub fn resolve_unit(db: &Connection, apt: &str) -> Result<Unit, Error> {
+    let number = normalize_apt(apt);
+    if number.is_empty() {
+        return Err(app_error("Apartment is required"));
+    }
+
+    match find_unit(db, &number)? {
+        Some(unit) if unit.is_active() => Ok(unit),
+        Some(_) => Err(app_error(format!("Apartment {} is inactive", number))),
+        None => Err(app_error(format!("Apartment {} is not registered", number))),
+    }
+}
+
+/// Active apartments starting with what was typed so far, for autocomplete
+pub fn suggest_units(db: &Connection, prefix: &str, limit: u32) -> Result<Vec<String>, Error> {
+    let prefix = normalize_apt(prefix);
+    if prefix.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut stmt = db.prepare(&format!(
+        "SELECT number FROM unit
+         WHERE property_id = {} AND status = 'active' AND number LIKE ?1 || '%' ESCAPE '\\'
+         ORDER BY LENGTH(number), number LIMIT ?2",
+        CURRENT_PROPERTY
+    ))?;
+    let escaped = prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    let numbers = stmt.query_map(rusqlite::params![escaped, limit], |row| row.get(0))?;
+    numbers.collect()
+}
+
+/// Registers an apartment in the current building. The floor is taken from the number when not given.
+pub fn add_unit(db: &Connection, building: &str, number: &str, floor: Option<i32>) -> Result<u32, Error> {
+    require_permission(db, Permission::ManageUnits)?;
+
+    let number = normalize_apt(number);
+    if number.is_empty() {
+        return Err(app_error("Apartment number is required"));
+    }
+    if find_unit(db, &number)?.is_some() {
+        return Err(app_error(format!("Apartment {} is already registered", number)));
+    }
+
+    let floor = floor.or_else(|| floor_from_number(&number));
+    db.execute(
+        &format!("INSERT INTO unit (property_id, building, number, floor) VALUES ({}, ?1, ?2, ?3)", CURRENT_PROPERTY),
+        rusqlite::params![building.trim(), number, floor],
+    )?;
+    let id = db.last_insert_rowid() as u32;
+
+    add_log_with_payload(
+        db, "unit", &format!("Apartment {} registered", number),
+        serde_json::json!({ "unit_id": id, "apt": number, "building": building.trim(), "floor": floor }),
+    )?;
+    Ok(id)
+}
+
+/// Registers a comma-separated list of apartments and numeric ranges,
+/// e.g. `101-110, 201, 3A`. Already registered ones are skipped.
+/// Returns how many were added.
+pub fn add_units_from_spec(db: &Connection, building: &str, spec: &str) -> Result<usize, Error> {
+    require_permission(db, Permission::ManageUnits)?;
+
+    let mut numbers = Vec::new();
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let (Ok(from), Ok(to)) = (from.trim().parse::<u32>(), to.trim().parse::<u32>()) else {
+                    return Err(app_error(format!("Invalid range '{}', expected e.g. 101-120", part)));
+                };
+                if from > to || to - from >= MAX_RANGE {
+                    return Err(app_error(format!("Invalid range '{}'", part)));
+                }
+                numbers.extend((from..=to).map(|n| n.to_string()));
+            }
+            None => numbers.push(part.to_string()),
+        }
+    }
+    if numbers.is_empty() {
+        return Err(app_error("No apartments given"));
+    }
+
+    let tx = db.unchecked_transaction()?;
+    let mut added = 0;
+    for number in numbers {
+        if find_unit(&tx, &number)?.is_none() {
+            add_unit(&tx, building, &number, None)?;
+            added += 1;
+        }
+    }
+    tx.commit()?;
+    Ok(added)
+}
+
+/// Inactive apartments keep their history but accept no new residents or packages
+pub fn set_unit_active(db: &Connection, apt: &str, active: bool) -> Result<(), Error> {
+    require_permission(db, Permission::ManageUnits)?;
+
+    let unit = find_unit(db, apt)?
+        .ok_or_else(|| app_error(format!("Apartment {} is not registered", normalize_apt(apt))))?;
+    let status = if active { "active" } else { "inactive" };
+    db.execute("UPDATE unit SET status = ?1 WHERE id = ?2", rusqlite::params![status, unit.id])?;
+
+    add_log_with_payload(
+        db, "unit", &format!("Apartment {} set {}", unit.number, status),
+        serde_json::json!({ "unit_id": unit.id, "apt": unit.number, "active": active }),
+    )
+}
+
+/// Normalises the free-text apartments stored before the registry existed,
+/// registers each distinct one as a unit and links residents and packages to it.
+/// Part of the schema v9 migration.
+pub(crate) fn register_existing_apartments(db: &Connection) -> Result<(), Error> {
+    let apartments: Vec<String> = db.prepare(
+        "SELECT apt FROM resident UNION SELECT apt FROM card UNION SELECT apt FROM package"
+    )?.query_map([], |row| row.get(0))?.collect::<Result<_, _>>()?;
+
+    for apt in apartments {
+        let number = normalize_apt(&apt);
+        if number.is_empty() {
+            continue;
+        }
+        if number != apt {
+            for table in ["resident", "card", "package"] {
+                db.execute(&format!("UPDATE {} SET apt = ?1 WHERE apt = ?2", table), [&number, &apt])?;
+            }
+        }
+        db.execute(
+            "INSERT OR IGNORE INTO unit (number, floor) VALUES (?1, ?2)",
+            rusqlite::params![number, floor_from_number(&number)],
+        )?;
+    }
+
+    db.execute_batch("
+        UPDATE resident SET unit_id = (SELECT id FROM unit WHERE unit.number = resident.apt);
+        UPDATE package SET unit_id = (SELECT id FROM unit WHERE unit.number = package.apt);
+    ")
+}